@@ -0,0 +1,564 @@
+//! Aggregator mode: proxies the tools, resources and prompts of downstream MCP servers.
+//!
+//! A downstream server named `db` contributes its tools and prompts as
+//! `db__<name>` and its resources as `db+<uri>`. Tool calls reach it only after
+//! this server's own token permissions, confirmations and logging.
+//!
+//! Each server's tool list is cached until it sends `tools/list_changed`,
+//! which is passed on to this server's clients.
+
+use anyhow::{anyhow, bail, Result};
+use rmcp::{
+    handler::client::ClientHandler,
+    model::*,
+    service::{NotificationContext, RunningService, ServiceError},
+    transport::{
+        streamable_http_client::StreamableHttpClientTransportConfig, StreamableHttpClientTransport,
+        TokioChildProcess,
+    },
+    ErrorData as McpError, RoleClient, ServiceExt,
+};
+use std::collections::HashSet;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{ChildStderr, Command};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::DownstreamConfig;
+use crate::tool::{self, ToolContext, ToolFuture};
+
+/// Joins a downstream server's name and one of its tool or prompt names.
+pub const SEPARATOR: &str = "__";
+
+struct Downstream {
+    name: String,
+    client: RunningService<RoleClient, Listener>,
+}
+
+/// A downstream server's tools as last listed, until it says they changed.
+#[derive(Default)]
+struct ToolCache {
+    tools: Mutex<Option<Vec<Tool>>>,
+    /// Bumped on every change, so a list fetched meanwhile isn't cached.
+    generation: AtomicU64,
+}
+
+/// The client side of a downstream connection, listening for tool list changes.
+struct Listener {
+    info: ClientInfo,
+    server: String,
+    cache: Arc<ToolCache>,
+    tools_changed: UnboundedSender<()>,
+}
+
+impl ClientHandler for Listener {
+    fn get_info(&self) -> ClientInfo {
+        self.info.clone()
+    }
+
+    async fn on_tool_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        tracing::debug!(server = %self.server, "Downstream tool list changed");
+        self.cache.generation.fetch_add(1, Ordering::SeqCst);
+        *self.cache.tools.lock().unwrap() = None;
+        let _ = self.tools_changed.send(());
+    }
+}
+
+impl Downstream {
+    /// The server's tools, from the cache when it is current.
+    async fn tools(&self) -> Result<Vec<Tool>, ServiceError> {
+        let cache = &self.client.service().cache;
+        if let Some(tools) = cache.tools.lock().unwrap().clone() {
+            return Ok(tools);
+        }
+        let generation = cache.generation.load(Ordering::SeqCst);
+        let tools = self.client.list_all_tools().await?;
+        let mut cached = cache.tools.lock().unwrap();
+        if cache.generation.load(Ordering::SeqCst) == generation {
+            *cached = Some(tools.clone());
+        }
+        Ok(tools)
+    }
+
+    fn supports(&self, capability: impl Fn(&ServerCapabilities) -> bool) -> bool {
+        self.client
+            .peer_info()
+            .is_some_and(|info| capability(&info.capabilities))
+    }
+
+    fn error(&self, e: ServiceError) -> McpError {
+        match e {
+            ServiceError::McpError(e) => e,
+            other => McpError {
+                code: ErrorCode::INTERNAL_ERROR,
+                message: format!("Downstream server '{}' failed: {}", self.name, other).into(),
+                data: None,
+            },
+        }
+    }
+}
+
+/// A downstream tool under its namespaced name.
+struct DownstreamTool {
+    server: Arc<Downstream>,
+    /// The name the downstream server knows it by.
+    name: String,
+    definition: Tool,
+}
+
+impl tool::Tool for DownstreamTool {
+    fn name(&self) -> &str {
+        &self.definition.name
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.definition.title.as_deref()
+    }
+
+    fn description(&self) -> &str {
+        self.definition.description.as_deref().unwrap_or_default()
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        self.definition.input_schema.clone()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        self.definition.output_schema.clone()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        self.definition.annotations.clone().unwrap_or_default()
+    }
+
+    fn execute(&self, arguments: JsonObject, _context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let request = CallToolRequestParam {
+                name: self.name.clone().into(),
+                arguments: Some(arguments),
+            };
+            self.server.client.call_tool(request).await.map_err(|e| self.server.error(e))
+        })
+    }
+
+    fn definition(&self) -> Tool {
+        self.definition.clone()
+    }
+}
+
+pub struct Aggregator {
+    servers: Vec<Arc<Downstream>>,
+}
+
+impl Aggregator {
+    /// Connects to every configured server. A server that can't be reached is
+    /// logged and left out rather than keeping this one from starting.
+    /// `tools_changed` is sent to whenever a server's tool list changes.
+    pub async fn connect(configs: &[DownstreamConfig], tools_changed: UnboundedSender<()>) -> Result<Self> {
+        validate(configs)?;
+
+        let mut servers = Vec::new();
+        for config in configs {
+            match connect(config, tools_changed.clone()).await {
+                Ok(client) => {
+                    tracing::info!(server = %config.name, "Connected to downstream MCP server");
+                    servers.push(Arc::new(Downstream {
+                        name: config.name.clone(),
+                        client,
+                    }));
+                }
+                Err(e) => {
+                    tracing::error!(server = %config.name, error = %e, "Failed to connect to downstream MCP server")
+                }
+            }
+        }
+        Ok(Self { servers })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    fn route<'a>(&self, name: &'a str) -> Option<(&Arc<Downstream>, &'a str)> {
+        let (prefix, rest) = name.split_once(SEPARATOR)?;
+        self.servers.iter().find(|s| s.name == prefix).map(|s| (s, rest))
+    }
+
+    fn route_uri<'a>(&self, uri: &'a str) -> Option<(&Arc<Downstream>, &'a str)> {
+        let (prefix, rest) = uri.split_once('+')?;
+        self.servers.iter().find(|s| s.name == prefix).map(|s| (s, rest))
+    }
+
+    pub async fn list_tools(&self) -> Vec<Tool> {
+        let mut tools = Vec::new();
+        for server in self.servers.iter().filter(|s| s.supports(|c| c.tools.is_some())) {
+            match server.tools().await {
+                Ok(list) => tools.extend(list.into_iter().map(|mut tool| {
+                    tool.name = format!("{}{}{}", server.name, SEPARATOR, tool.name).into();
+                    tool
+                })),
+                Err(e) => tracing::warn!(server = %server.name, error = %e, "Failed to list downstream tools"),
+            }
+        }
+        tools
+    }
+
+    /// A downstream tool, looked up by its namespaced name.
+    pub async fn tool(&self, name: &str) -> Option<Arc<dyn tool::Tool>> {
+        let (server, inner) = self.route(name)?;
+        let tools = server.tools().await.ok()?;
+        let mut definition = tools.into_iter().find(|tool| tool.name == inner)?;
+        definition.name = name.to_string().into();
+        Some(Arc::new(DownstreamTool {
+            server: server.clone(),
+            name: inner.to_string(),
+            definition,
+        }))
+    }
+
+    pub async fn list_resources(&self) -> Vec<Resource> {
+        let mut resources = Vec::new();
+        for server in self.servers.iter().filter(|s| s.supports(|c| c.resources.is_some())) {
+            match server.client.list_all_resources().await {
+                Ok(list) => resources.extend(list.into_iter().map(|mut resource| {
+                    resource.raw.uri = format!("{}+{}", server.name, resource.raw.uri);
+                    resource
+                })),
+                Err(e) => tracing::warn!(server = %server.name, error = %e, "Failed to list downstream resources"),
+            }
+        }
+        resources
+    }
+
+    pub async fn list_resource_templates(&self) -> Vec<ResourceTemplate> {
+        let mut templates = Vec::new();
+        for server in self.servers.iter().filter(|s| s.supports(|c| c.resources.is_some())) {
+            match server.client.list_all_resource_templates().await {
+                Ok(list) => templates.extend(list.into_iter().map(|mut template| {
+                    template.raw.uri_template = format!("{}+{}", server.name, template.raw.uri_template);
+                    template
+                })),
+                Err(e) => {
+                    tracing::warn!(server = %server.name, error = %e, "Failed to list downstream resource templates")
+                }
+            }
+        }
+        templates
+    }
+
+    pub async fn read_resource(&self, uri: &str) -> Result<ReadResourceResult, McpError> {
+        let (server, inner) = self
+            .route_uri(uri)
+            .ok_or_else(|| McpError::resource_not_found(format!("Unknown resource '{}'", uri), None))?;
+        let mut result = server
+            .client
+            .read_resource(ReadResourceRequestParam { uri: inner.to_string() })
+            .await
+            .map_err(|e| server.error(e))?;
+        for contents in &mut result.contents {
+            let (ResourceContents::TextResourceContents { uri, .. }
+            | ResourceContents::BlobResourceContents { uri, .. }) = contents;
+            *uri = format!("{}+{}", server.name, uri);
+        }
+        Ok(result)
+    }
+
+    pub async fn list_prompts(&self) -> Vec<Prompt> {
+        let mut prompts = Vec::new();
+        for server in self.servers.iter().filter(|s| s.supports(|c| c.prompts.is_some())) {
+            match server.client.list_all_prompts().await {
+                Ok(list) => prompts.extend(list.into_iter().map(|mut prompt| {
+                    prompt.name = format!("{}{}{}", server.name, SEPARATOR, prompt.name);
+                    prompt
+                })),
+                Err(e) => tracing::warn!(server = %server.name, error = %e, "Failed to list downstream prompts"),
+            }
+        }
+        prompts
+    }
+
+    pub async fn get_prompt(&self, mut request: GetPromptRequestParam) -> Result<GetPromptResult, McpError> {
+        let (server, name) = self
+            .route(&request.name)
+            .map(|(server, name)| (server, name.to_string()))
+            .ok_or_else(|| McpError::invalid_params(format!("Unknown prompt '{}'", request.name), None))?;
+        request.name = name;
+        server.client.get_prompt(request).await.map_err(|e| server.error(e))
+    }
+
+    /// The prefixed prompt name or resource URI `reference` names, when a
+    /// downstream server completes it.
+    pub fn completion_target(&self, reference: &Reference) -> Option<String> {
+        match reference {
+            Reference::Prompt(prompt) => self.route(&prompt.name).map(|_| prompt.name.clone()),
+            Reference::Resource(resource) => self.route_uri(&resource.uri).map(|_| resource.uri.clone()),
+        }
+    }
+
+    /// Forwards completion for a downstream prompt or resource template, if `reference` names one.
+    pub async fn complete(&self, request: &CompleteRequestParam) -> Option<Result<CompleteResult, McpError>> {
+        let (server, reference) = match &request.r#ref {
+            Reference::Prompt(prompt) => {
+                let (server, name) = self.route(&prompt.name)?;
+                (server, Reference::for_prompt(name))
+            }
+            Reference::Resource(resource) => {
+                let (server, uri) = self.route_uri(&resource.uri)?;
+                (server, Reference::for_resource(uri))
+            }
+        };
+        let request = CompleteRequestParam {
+            r#ref: reference,
+            ..request.clone()
+        };
+        Some(server.client.complete(request).await.map_err(|e| server.error(e)))
+    }
+}
+
+/// Checks server names are valid and unique and each has exactly one of `command` or `url`.
+pub fn validate(configs: &[DownstreamConfig]) -> Result<()> {
+    let mut names = HashSet::new();
+    for config in configs {
+        let valid = config.name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && config.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            bail!(
+                "Downstream server name '{}' must start with a letter and contain only letters, digits and '-'",
+                config.name
+            );
+        }
+        if !names.insert(&config.name) {
+            bail!("Duplicate downstream server name '{}'", config.name);
+        }
+        if config.command.is_some() == config.url.is_some() {
+            bail!("Downstream server '{}' needs exactly one of `command` or `url`", config.name);
+        }
+    }
+    Ok(())
+}
+
+async fn connect(
+    config: &DownstreamConfig,
+    tools_changed: UnboundedSender<()>,
+) -> Result<RunningService<RoleClient, Listener>> {
+    let info = listener(&config.name, tools_changed);
+    let client = match (&config.command, &config.url) {
+        (Some(command), _) => {
+            let mut cmd = Command::new(command);
+            cmd.args(&config.args).envs(&config.env);
+            // Captured so a chatty server can't write over a client sharing our stderr.
+            let (transport, stderr) = TokioChildProcess::builder(cmd)
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| anyhow!("Failed to spawn '{}': {}", command, e))?;
+            if let Some(stderr) = stderr {
+                forward_stderr(config.name.clone(), stderr);
+            }
+            info.serve(transport).await
+        }
+        (None, Some(url)) => {
+            let mut transport = StreamableHttpClientTransportConfig::with_uri(url.as_str());
+            if let Some(token) = &config.token {
+                transport = transport.auth_header(token.clone());
+            }
+            info.serve(StreamableHttpClientTransport::from_config(transport)).await
+        }
+        (None, None) => bail!("Downstream server '{}' has no `command` or `url`", config.name),
+    };
+    client.map_err(|e| anyhow!("Failed to initialize '{}': {}", config.name, e))
+}
+
+fn listener(server: &str, tools_changed: UnboundedSender<()>) -> Listener {
+    Listener {
+        info: ClientInfo {
+            client_info: Implementation {
+                name: env!("CARGO_PKG_NAME").into(),
+                version: env!("CARGO_PKG_VERSION").into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        server: server.to_string(),
+        cache: Arc::default(),
+        tools_changed,
+    }
+}
+
+fn forward_stderr(server: String, stderr: ChildStderr) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            tracing::debug!(%server, %line, "Downstream server stderr");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::handler::server::ServerHandler;
+    use rmcp::service::RequestContext;
+    use rmcp::RoleServer;
+    use serde_json::Value;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    use crate::workspace::Workspace;
+
+    fn downstream(name: &str, command: Option<&str>, url: Option<&str>) -> DownstreamConfig {
+        DownstreamConfig {
+            name: name.to_string(),
+            command: command.map(str::to_string),
+            args: Vec::new(),
+            env: Default::default(),
+            url: url.map(str::to_string),
+            token: None,
+        }
+    }
+
+    /// A downstream server with one resource and the tools in `tools`,
+    /// counting how often they are listed.
+    #[derive(Clone, Default)]
+    struct Fake {
+        tools: Arc<Mutex<Vec<String>>>,
+        lists: Arc<AtomicUsize>,
+    }
+
+    impl ServerHandler for Fake {
+        fn get_info(&self) -> ServerInfo {
+            ServerInfo {
+                capabilities: ServerCapabilities::builder().enable_tools().enable_resources().build(),
+                ..Default::default()
+            }
+        }
+
+        async fn list_tools(
+            &self,
+            _request: Option<PaginatedRequestParam>,
+            _context: RequestContext<RoleServer>,
+        ) -> Result<ListToolsResult, McpError> {
+            self.lists.fetch_add(1, Ordering::SeqCst);
+            let tools = self.tools.lock().unwrap().iter().map(|name| Tool::new(name.clone(), "", JsonObject::new())).collect();
+            Ok(ListToolsResult::with_all_items(tools))
+        }
+
+        async fn call_tool(
+            &self,
+            request: CallToolRequestParam,
+            _context: RequestContext<RoleServer>,
+        ) -> Result<CallToolResult, McpError> {
+            let arguments = Value::Object(request.arguments.unwrap_or_default());
+            Ok(CallToolResult::success(vec![Content::text(format!("{} {}", request.name, arguments))]))
+        }
+
+        async fn list_resources(
+            &self,
+            _request: Option<PaginatedRequestParam>,
+            _context: RequestContext<RoleServer>,
+        ) -> Result<ListResourcesResult, McpError> {
+            Ok(ListResourcesResult::with_all_items(vec![RawResource::new("file:///notes", "notes").no_annotation()]))
+        }
+
+        async fn read_resource(
+            &self,
+            request: ReadResourceRequestParam,
+            _context: RequestContext<RoleServer>,
+        ) -> Result<ReadResourceResult, McpError> {
+            Ok(ReadResourceResult {
+                contents: vec![ResourceContents::text("hello", request.uri)],
+            })
+        }
+    }
+
+    /// An aggregator with `fake` connected as `db` over an in-process stream.
+    async fn aggregate(fake: Fake) -> (Aggregator, RunningService<RoleServer, Fake>, mpsc::UnboundedReceiver<()>) {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let server = tokio::spawn(fake.serve(server_io));
+        let (tools_changed, changes) = mpsc::unbounded_channel();
+        let client = listener("db", tools_changed).serve(client_io).await.unwrap();
+        let server = server.await.unwrap().unwrap();
+        let aggregator = Aggregator {
+            servers: vec![Arc::new(Downstream {
+                name: "db".to_string(),
+                client,
+            })],
+        };
+        (aggregator, server, changes)
+    }
+
+    fn text(result: &CallToolResult) -> String {
+        result.content[0].as_text().unwrap().text.clone()
+    }
+
+    #[test]
+    fn validate_checks_names_and_transports() {
+        assert!(validate(&[downstream("db", Some("db-server"), None), downstream("web-2", None, Some("http://x"))]).is_ok());
+        for configs in [
+            vec![downstream("2db", Some("x"), None)],
+            vec![downstream("my_db", Some("x"), None)],
+            vec![downstream("db", Some("x"), None), downstream("db", None, Some("http://x"))],
+            vec![downstream("db", None, None)],
+            vec![downstream("db", Some("x"), Some("http://x"))],
+        ] {
+            assert!(validate(&configs).is_err(), "{:?}", configs);
+        }
+    }
+
+    #[tokio::test]
+    async fn names_and_uris_are_routed_by_prefix() {
+        let (aggregator, _server, _changes) = aggregate(Fake::default()).await;
+        assert_eq!(aggregator.route("db__query").map(|(s, name)| (s.name.as_str(), name)), Some(("db", "query")));
+        assert_eq!(aggregator.route("db__a__b").map(|(_, name)| name), Some("a__b"));
+        assert!(aggregator.route("web__query").is_none());
+        assert!(aggregator.route("db_query").is_none());
+        assert_eq!(aggregator.route_uri("db+file:///notes").map(|(_, uri)| uri), Some("file:///notes"));
+        assert!(aggregator.route_uri("db__file:///notes").is_none());
+        assert_eq!(
+            aggregator.completion_target(&Reference::for_prompt("db__greet")),
+            Some("db__greet".to_string())
+        );
+        assert_eq!(aggregator.completion_target(&Reference::for_resource("web+file:///x")), None);
+    }
+
+    #[tokio::test]
+    async fn downstream_tools_and_resources_are_namespaced_and_cached() {
+        let fake = Fake::default();
+        fake.tools.lock().unwrap().push("query".to_string());
+        let (aggregator, server, mut changes) = aggregate(fake.clone()).await;
+
+        let names = |tools: Vec<Tool>| tools.into_iter().map(|tool| tool.name.to_string()).collect::<Vec<_>>();
+        assert_eq!(names(aggregator.list_tools().await), ["db__query"]);
+        assert_eq!(names(aggregator.list_tools().await), ["db__query"]);
+        assert_eq!(fake.lists.load(Ordering::SeqCst), 1);
+
+        let tool = aggregator.tool("db__query").await.unwrap();
+        assert_eq!(tool.name(), "db__query");
+        let dir = tempfile::tempdir().unwrap();
+        let context = ToolContext::new(Arc::new(Workspace::new(dir.path().to_path_buf())));
+        let arguments = serde_json::json!({"sql": "SELECT 1"}).as_object().unwrap().clone();
+        let result = tool.execute(arguments, context).await.unwrap();
+        assert_eq!(text(&result), r#"query {"sql":"SELECT 1"}"#);
+        assert!(aggregator.tool("db__missing").await.is_none());
+
+        let resources = aggregator.list_resources().await;
+        assert_eq!(resources[0].raw.uri, "db+file:///notes");
+        let read = aggregator.read_resource("db+file:///notes").await.unwrap();
+        let ResourceContents::TextResourceContents { uri, text, .. } = &read.contents[0] else {
+            panic!("expected text contents");
+        };
+        assert_eq!((uri.as_str(), text.as_str()), ("db+file:///notes", "hello"));
+        assert!(aggregator.read_resource("web+file:///notes").await.is_err());
+
+        // A change notification drops the cached list and is passed on.
+        fake.tools.lock().unwrap().push("explain".to_string());
+        server.peer().notify_tool_list_changed().await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), changes.recv()).await.unwrap().unwrap();
+        assert_eq!(names(aggregator.list_tools().await), ["db__query", "db__explain"]);
+        assert_eq!(fake.lists.load(Ordering::SeqCst), 2);
+        assert!(aggregator.tool("db__explain").await.is_some());
+        assert_eq!(fake.lists.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,110 @@
+//! Audit trail of tool calls, resource reads and prompt requests: who called
+//! what, how it ended and what was redacted.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::AuditConfig;
+use crate::redact::Findings;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Ok,
+    /// The tool ran and reported an error.
+    Error,
+    /// Policy stopped the call before it ran.
+    Denied,
+}
+
+/// What an audited request asked for.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Tool,
+    Resource,
+    Prompt,
+    Completion,
+}
+
+impl Kind {
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Tool => "Tool call",
+            Kind::Resource => "Resource read",
+            Kind::Prompt => "Prompt request",
+            Kind::Completion => "Completion request",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditEvent<'a> {
+    pub session: &'a str,
+    /// The token's name for authenticated HTTP callers.
+    pub identity: Option<&'a str>,
+    pub kind: Kind,
+    /// The tool or prompt name, or the resource URI.
+    pub tool: &'a str,
+    pub outcome: Outcome,
+    pub elapsed_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<&'a str>,
+    #[serde(skip_serializing_if = "Findings::is_empty")]
+    pub redactions: &'a Findings,
+}
+
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+}
+
+impl AuditLog {
+    pub fn new(config: &AuditConfig) -> Result<Self> {
+        let file = match &config.file {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| anyhow!("Failed to open audit log '{}': {}", path.display(), e))?,
+            )),
+            None => None,
+        };
+        Ok(Self { file })
+    }
+
+    /// Logs the event and, when an audit file is configured, appends it as a JSON line.
+    pub fn record(&self, event: &AuditEvent) {
+        let tool = event.tool;
+        let elapsed_ms = event.elapsed_ms;
+        let what = event.kind.label();
+        match event.outcome {
+            Outcome::Ok => tracing::info!(%tool, elapsed_ms, "{} succeeded", what),
+            Outcome::Error => tracing::warn!(%tool, elapsed_ms, error = event.error, "{} failed", what),
+            Outcome::Denied => tracing::warn!(%tool, identity = event.identity, reason = event.error, "{} denied", what),
+        }
+        if !event.redactions.is_empty() {
+            tracing::warn!(%tool, redactions = ?event.redactions, "Redacted secrets from the output");
+        }
+
+        let Some(file) = &self.file else {
+            return;
+        };
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        let mut line = match serde_json::to_value(event) {
+            Ok(serde_json::Value::Object(mut map)) => {
+                map.insert("timestamp_ms".into(), timestamp.into());
+                serde_json::Value::Object(map).to_string()
+            }
+            _ => return,
+        };
+        line.push('\n');
+        if let Err(e) = file.lock().unwrap().write_all(line.as_bytes()) {
+            tracing::error!(error = %e, "Failed to write audit log");
+        }
+    }
+}
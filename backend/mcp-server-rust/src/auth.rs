@@ -0,0 +1,252 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rmcp::model::Extensions;
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use crate::config::AuthConfig;
+use crate::metrics::metrics;
+
+/// The caller behind an authenticated HTTP request.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub name: String,
+    tools: Option<HashSet<String>>,
+}
+
+impl Identity {
+    pub fn can_use(&self, tool: &str) -> bool {
+        self.tools.as_ref().is_none_or(|tools| tools.contains(tool))
+    }
+
+    /// Looks up the identity attached to the HTTP request that carried an MCP message.
+    /// Messages received over stdio have none.
+    pub fn from_extensions(extensions: &Extensions) -> Option<&Identity> {
+        extensions
+            .get::<http::request::Parts>()?
+            .extensions
+            .get::<Identity>()
+    }
+}
+
+/// Token bucket refilled continuously at `per_minute / 60` requests per second.
+pub(crate) struct RateLimiter {
+    capacity: f64,
+    available: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(per_minute: u32) -> Self {
+        Self {
+            capacity: per_minute as f64,
+            available: per_minute as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    pub(crate) fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.updated = now;
+        if self.available >= 1.0 {
+            self.available -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+struct TokenEntry {
+    secret: String,
+    identity: Identity,
+    limit: Option<u32>,
+    limiter: Option<Arc<Mutex<RateLimiter>>>,
+}
+
+/// The configured bearer tokens. A reload swaps them in place, so a revoked
+/// token is refused from the next request on.
+pub struct Authenticator {
+    tokens: RwLock<Arc<Vec<TokenEntry>>>,
+    /// Set by a listener that has no client certificates to fall back on.
+    required: AtomicBool,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Self {
+        Self {
+            tokens: RwLock::new(Arc::new(entries(config, &[]))),
+            required: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.read().unwrap().is_empty()
+    }
+
+    /// Marks the tokens as the only protection of a listener, so a reload may
+    /// not remove them all.
+    pub(crate) fn require_tokens(&self) {
+        self.required.store(true, Ordering::Relaxed);
+    }
+
+    /// Replaces the tokens with those of `config`. Tokens kept with the same
+    /// secret and limit keep their rate limit state.
+    pub fn reload(&self, config: &AuthConfig) -> Result<()> {
+        if config.tokens.is_empty() && self.required.load(Ordering::Relaxed) {
+            bail!("Refusing to remove every bearer token from a listener without client certificates");
+        }
+        let mut tokens = self.tokens.write().unwrap();
+        *tokens = Arc::new(entries(config, &tokens));
+        Ok(())
+    }
+
+    fn authenticate(&self, authorization: Option<&str>) -> Result<Identity, (StatusCode, &'static str)> {
+        let presented = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or((StatusCode::UNAUTHORIZED, "Missing bearer token"))?;
+        let tokens = self.tokens.read().unwrap().clone();
+        let entry = tokens
+            .iter()
+            .find(|entry| constant_time_eq(entry.secret.as_bytes(), presented.as_bytes()))
+            .ok_or((StatusCode::UNAUTHORIZED, "Invalid bearer token"))?;
+        if let Some(limiter) = &entry.limiter {
+            if !limiter.lock().unwrap().try_acquire() {
+                return Err((StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded"));
+            }
+        }
+        Ok(entry.identity.clone())
+    }
+}
+
+/// The entries for `config`, reusing the rate limiters of matching `previous` ones.
+fn entries(config: &AuthConfig, previous: &[TokenEntry]) -> Vec<TokenEntry> {
+    config
+        .tokens
+        .iter()
+        .map(|token| {
+            let limiter = previous
+                .iter()
+                .find(|entry| entry.secret == token.token && entry.limit == token.requests_per_minute)
+                .map(|entry| entry.limiter.clone())
+                .unwrap_or_else(|| {
+                    token
+                        .requests_per_minute
+                        .map(|limit| Arc::new(Mutex::new(RateLimiter::new(limit))))
+                });
+            TokenEntry {
+                secret: token.token.clone(),
+                identity: Identity {
+                    name: token.name.clone(),
+                    tools: token.tools.as_ref().map(|tools| tools.iter().cloned().collect()),
+                },
+                limit: token.requests_per_minute,
+                limiter,
+            }
+        })
+        .collect()
+}
+
+/// Rejects requests without a valid bearer token and attaches the caller's [`Identity`].
+///
+/// With no tokens configured the listener relies on client certificates alone,
+/// which the TLS handshake has already verified.
+pub async fn require_auth(
+    State(authenticator): State<Arc<Authenticator>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if authenticator.is_empty() {
+        return next.run(request).await;
+    }
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    match authenticator.authenticate(authorization) {
+        Ok(identity) => {
+            request.extensions_mut().insert(identity);
+            next.run(request).await
+        }
+        Err((status, message)) => {
+            tracing::warn!(%status, reason = message, "Rejected HTTP request");
+            metrics().rejection(if status == StatusCode::TOO_MANY_REQUESTS {
+                "token_rate_limit"
+            } else {
+                "unauthorized"
+            });
+            let mut response = (status, message).into_response();
+            if status == StatusCode::UNAUTHORIZED {
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+            }
+            response
+        }
+    }
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TokenConfig;
+
+    fn config(tokens: &[(&str, &str, Option<u32>)]) -> AuthConfig {
+        AuthConfig {
+            tokens: tokens
+                .iter()
+                .map(|(name, token, requests_per_minute)| TokenConfig {
+                    name: name.to_string(),
+                    token: token.to_string(),
+                    tools: None,
+                    requests_per_minute: *requests_per_minute,
+                })
+                .collect(),
+        }
+    }
+
+    fn name(authenticator: &Authenticator, token: &str) -> Result<String, StatusCode> {
+        authenticator
+            .authenticate(Some(&format!("Bearer {}", token)))
+            .map(|identity| identity.name)
+            .map_err(|(status, _)| status)
+    }
+
+    #[test]
+    fn reload_revokes_removed_tokens() {
+        let authenticator = Authenticator::new(&config(&[("a", "secret-a", None), ("b", "secret-b", None)]));
+        assert_eq!(name(&authenticator, "secret-b"), Ok("b".into()));
+        authenticator.reload(&config(&[("a", "secret-a", None)])).unwrap();
+        assert_eq!(name(&authenticator, "secret-b"), Err(StatusCode::UNAUTHORIZED));
+        assert_eq!(name(&authenticator, "secret-a"), Ok("a".into()));
+    }
+
+    #[test]
+    fn reload_keeps_the_rate_limit_of_unchanged_tokens() {
+        let authenticator = Authenticator::new(&config(&[("a", "secret-a", Some(1))]));
+        assert!(name(&authenticator, "secret-a").is_ok());
+        authenticator.reload(&config(&[("a", "secret-a", Some(1)), ("b", "secret-b", None)])).unwrap();
+        assert_eq!(name(&authenticator, "secret-a"), Err(StatusCode::TOO_MANY_REQUESTS));
+    }
+
+    #[test]
+    fn reload_refuses_to_leave_a_listener_unprotected() {
+        let authenticator = Authenticator::new(&config(&[("a", "secret-a", None)]));
+        authenticator.require_tokens();
+        assert!(authenticator.reload(&config(&[])).is_err());
+        assert_eq!(name(&authenticator, "secret-a"), Ok("a".into()));
+    }
+}
@@ -0,0 +1,600 @@
+//! Workspace snapshots taken before tool calls that may change files.
+//!
+//! File contents are stored once under `objects/`, named by their SHA-256.
+//! A checkpoint is a manifest at `sessions/<session>/<turn>.json` that maps
+//! every tracked file to its hash, so it can be diffed against or restored.
+//!
+//! Snapshots hold a shared lock on the store and pruning an exclusive one, so
+//! servers sharing a store never delete contents a snapshot is still writing.
+
+use anyhow::{anyhow, Result};
+use fd_lock::RwLock;
+use ignore::WalkBuilder;
+use rmcp::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, Metadata};
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{state_dir, CheckpointsConfig};
+use crate::workspace::Workspace;
+
+/// Identifies one MCP session and numbers its tool calls.
+pub struct Turns {
+    session: String,
+    next: AtomicU64,
+    isolated: bool,
+}
+
+impl Turns {
+    pub fn new() -> Self {
+        let now = SystemTime::now();
+        let secs = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        Self {
+            session: format!("{}-{:04x}", secs, RandomState::new().hash_one(now) & 0xffff),
+            next: AtomicU64::new(1),
+            isolated: false,
+        }
+    }
+
+    /// A session that may only see its own checkpoints, for clients sharing a server.
+    pub fn isolated() -> Self {
+        Self {
+            isolated: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    pub fn is_isolated(&self) -> bool {
+        self.isolated
+    }
+
+    pub fn next(&self) -> u64 {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl Default for Turns {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    session: String,
+    turn: u64,
+    tool: String,
+    created: u64,
+    roots: Vec<PathBuf>,
+    files: BTreeMap<PathBuf, String>,
+    /// Files left out for their size; restoring never deletes them.
+    skipped: Vec<PathBuf>,
+    /// Whether `max_files` cut the snapshot short, in which case untracked files are never deleted.
+    truncated: bool,
+}
+
+impl Manifest {
+    fn id(&self) -> String {
+        format!("{}-{}", self.session, self.turn)
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CheckpointInfo {
+    /// Pass to `diff_checkpoint` or `restore_checkpoint`.
+    pub id: String,
+    pub session: String,
+    pub turn: u64,
+    /// The tool call the snapshot was taken before.
+    pub tool: String,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    pub files: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CheckpointList {
+    /// The session of the connection that asked.
+    pub current_session: String,
+    /// Newest first.
+    pub checkpoints: Vec<CheckpointInfo>,
+}
+
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Modified,
+    Added,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Modified => "modified",
+            ChangeKind::Added => "added",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FileChange {
+    pub path: String,
+    /// What happened to the file since the checkpoint.
+    pub change: ChangeKind,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CheckpointDiff {
+    pub id: String,
+    pub changes: Vec<FileChange>,
+    /// Unified diff from the checkpoint to the current files.
+    pub diff: String,
+    /// Pass to `read_more` for the rest of the diff; missing when nothing was cut off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RestoreResult {
+    pub id: String,
+    /// The changes that were undone.
+    pub restored: Vec<FileChange>,
+}
+
+/// Snapshots between two pruning passes.
+const PRUNE_EVERY: u64 = 20;
+
+pub struct CheckpointStore {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    keep: usize,
+    max_store_bytes: u64,
+    /// Snapshots taken by this server, to prune every `PRUNE_EVERY`.
+    snapshots: AtomicU64,
+    /// Hashes of files already stored, reused while their size and mtime are unchanged.
+    hashes: Mutex<HashMap<PathBuf, (u64, SystemTime, String)>>,
+}
+
+impl CheckpointStore {
+    pub fn new(config: &CheckpointsConfig) -> Result<Self> {
+        let dir = config.dir.clone().unwrap_or_else(|| state_dir("checkpoints"));
+        fs::create_dir_all(dir.join("objects"))
+            .map_err(|e| anyhow!("Failed to create checkpoint store '{}': {}", dir.display(), e))?;
+        Ok(Self {
+            dir,
+            max_file_bytes: config.max_file_bytes,
+            max_files: config.max_files,
+            keep: config.keep,
+            max_store_bytes: config.max_store_bytes,
+            snapshots: AtomicU64::new(0),
+            hashes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Records the current state of every file under `roots` and returns the checkpoint's id.
+    pub fn snapshot(&self, turns: &Turns, turn: u64, tool: &str, roots: &[PathBuf]) -> Result<String> {
+        let id = {
+            let lock = self.lock()?;
+            let _shared = lock
+                .read()
+                .map_err(|e| anyhow!("Failed to lock checkpoint store '{}': {}", self.dir.display(), e))?;
+            self.write_snapshot(turns, turn, tool, roots)?
+        };
+        if self.snapshots.fetch_add(1, Ordering::Relaxed).is_multiple_of(PRUNE_EVERY) {
+            match self.prune() {
+                Ok(0) => {}
+                Ok(pruned) => tracing::info!(pruned, "Pruned old checkpoints"),
+                Err(e) => tracing::warn!(error = %e, "Failed to prune checkpoints"),
+            }
+        }
+        Ok(id)
+    }
+
+    fn write_snapshot(&self, turns: &Turns, turn: u64, tool: &str, roots: &[PathBuf]) -> Result<String> {
+        let mut files = BTreeMap::new();
+        let mut skipped = Vec::new();
+        let mut truncated = false;
+        for (path, metadata) in self.walk(roots) {
+            if metadata.len() > self.max_file_bytes {
+                skipped.push(path);
+            } else if files.len() >= self.max_files {
+                truncated = true;
+            } else {
+                match self.store(&path, &metadata) {
+                    Ok(hash) => {
+                        files.insert(path, hash);
+                    }
+                    Err(e) => tracing::warn!(path = %path.display(), error = %e, "Failed to snapshot file"),
+                }
+            }
+        }
+        if truncated {
+            tracing::warn!(limit = self.max_files, "Checkpoint file limit reached; later files are not tracked");
+        }
+
+        let manifest = Manifest {
+            session: turns.session.clone(),
+            turn,
+            tool: tool.to_string(),
+            created: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            roots: roots.to_vec(),
+            files,
+            skipped,
+            truncated,
+        };
+        let dir = self.dir.join("sessions").join(&manifest.session);
+        fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Failed to create checkpoint directory '{}': {}", dir.display(), e))?;
+        let path = dir.join(format!("{}.json", turn));
+        fs::write(&path, serde_json::to_vec(&manifest)?)
+            .map_err(|e| anyhow!("Failed to write checkpoint '{}': {}", path.display(), e))?;
+        Ok(manifest.id())
+    }
+
+    /// Deletes the oldest checkpoints beyond `keep`, and beyond `max_store_bytes`
+    /// of contents, then the contents no remaining checkpoint refers to. The
+    /// newest checkpoint is always kept. Returns how many were deleted.
+    pub fn prune(&self) -> Result<usize> {
+        let mut lock = self.lock()?;
+        let _exclusive = lock
+            .write()
+            .map_err(|e| anyhow!("Failed to lock checkpoint store '{}': {}", self.dir.display(), e))?;
+
+        let mut manifests = Vec::new();
+        for dir in fs::read_dir(self.dir.join("sessions")).into_iter().flatten().flatten() {
+            for file in fs::read_dir(dir.path())?.flatten() {
+                if let Ok(manifest) = read_manifest(&file.path()) {
+                    manifests.push((file.path(), manifest));
+                }
+            }
+        }
+        manifests.sort_by_key(|(_, m)| std::cmp::Reverse((m.created, m.turn)));
+
+        let mut sizes = HashMap::new();
+        for prefix in fs::read_dir(self.dir.join("objects"))?.flatten() {
+            for object in fs::read_dir(prefix.path())?.flatten() {
+                let name = format!("{}{}", prefix.file_name().to_string_lossy(), object.file_name().to_string_lossy());
+                if let Ok(metadata) = object.metadata() {
+                    sizes.insert(name, (object.path(), metadata.len()));
+                }
+            }
+        }
+
+        let mut referenced = HashSet::new();
+        let mut total = 0;
+        let mut pruning = false;
+        let mut pruned = 0;
+        for (i, (path, manifest)) in manifests.iter().enumerate() {
+            let added: u64 = manifest
+                .files
+                .values()
+                .filter(|hash| !referenced.contains(*hash))
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .filter_map(|hash| sizes.get(hash).map(|(_, len)| len))
+                .sum();
+            pruning = pruning
+                || i > 0
+                    && (self.keep > 0 && i >= self.keep
+                        || self.max_store_bytes > 0 && total + added > self.max_store_bytes);
+            if pruning {
+                fs::remove_file(path)
+                    .map_err(|e| anyhow!("Failed to remove checkpoint '{}': {}", path.display(), e))?;
+                if let Some(dir) = path.parent() {
+                    // Only succeeds once the session has no checkpoints left.
+                    let _ = fs::remove_dir(dir);
+                }
+                pruned += 1;
+            } else {
+                total += added;
+                referenced.extend(manifest.files.values());
+            }
+        }
+        for (hash, (path, _)) in &sizes {
+            if !referenced.contains(hash) {
+                fs::remove_file(path)
+                    .map_err(|e| anyhow!("Failed to remove checkpoint object '{}': {}", path.display(), e))?;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Lists checkpoints, newest first, optionally for one session only.
+    pub fn list(&self, session: Option<&str>) -> Result<Vec<CheckpointInfo>> {
+        let sessions = self.dir.join("sessions");
+        let mut checkpoints = Vec::new();
+        let Ok(dirs) = fs::read_dir(&sessions) else {
+            return Ok(checkpoints);
+        };
+        for dir in dirs.flatten() {
+            if session.is_some_and(|session| dir.file_name() != session) {
+                continue;
+            }
+            for file in fs::read_dir(dir.path())?.flatten() {
+                let Ok(manifest) = read_manifest(&file.path()) else {
+                    continue;
+                };
+                checkpoints.push(CheckpointInfo {
+                    id: manifest.id(),
+                    session: manifest.session,
+                    turn: manifest.turn,
+                    tool: manifest.tool,
+                    created: manifest.created,
+                    files: manifest.files.len(),
+                });
+            }
+        }
+        checkpoints.sort_by_key(|c| std::cmp::Reverse((c.created, c.turn)));
+        Ok(checkpoints)
+    }
+
+    /// Returns what changed since the checkpoint, with a unified diff of it.
+    pub fn diff(&self, id: &str, scope: Option<&Path>, workspace: &Workspace) -> Result<(Vec<FileChange>, String)> {
+        let manifest = self.load(id)?;
+        let changes = self.changes(&manifest, scope, workspace)?;
+        let mut diff = String::new();
+        for (path, change) in &changes {
+            let old = match change {
+                ChangeKind::Added => Vec::new(),
+                _ => self.object(&manifest.files[path])?,
+            };
+            let new = match change {
+                ChangeKind::Deleted => Vec::new(),
+                _ => fs::read(path).map_err(|e| anyhow!("Failed to read file '{}': {}", path.display(), e))?,
+            };
+            let (Ok(old), Ok(new)) = (std::str::from_utf8(&old), std::str::from_utf8(&new)) else {
+                diff.push_str(&format!("Binary file {} differs\n", path.display()));
+                continue;
+            };
+            let from = match change {
+                ChangeKind::Added => "/dev/null".to_string(),
+                _ => format!("a{}", path.display()),
+            };
+            let to = match change {
+                ChangeKind::Deleted => "/dev/null".to_string(),
+                _ => format!("b{}", path.display()),
+            };
+            diff.push_str(&TextDiff::from_lines(old, new).unified_diff().header(&from, &to).to_string());
+        }
+        Ok((file_changes(changes), diff))
+    }
+
+    /// Puts every changed file back the way the checkpoint recorded it.
+    pub fn restore(&self, id: &str, scope: Option<&Path>, workspace: &Workspace) -> Result<Vec<FileChange>> {
+        let manifest = self.load(id)?;
+        let changes = self.changes(&manifest, scope, workspace)?;
+        for (path, change) in &changes {
+            match change {
+                ChangeKind::Added => fs::remove_file(path)
+                    .map_err(|e| anyhow!("Failed to remove file '{}': {}", path.display(), e))?,
+                ChangeKind::Modified | ChangeKind::Deleted => {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(path, self.object(&manifest.files[path])?)
+                        .map_err(|e| anyhow!("Failed to restore file '{}': {}", path.display(), e))?;
+                }
+            }
+        }
+        Ok(file_changes(changes))
+    }
+
+    /// Compares the checkpoint with the files on disk, limited to `scope` and the current workspace.
+    fn changes(
+        &self,
+        manifest: &Manifest,
+        scope: Option<&Path>,
+        workspace: &Workspace,
+    ) -> Result<Vec<(PathBuf, ChangeKind)>> {
+        let in_scope = |path: &Path| {
+            scope.is_none_or(|scope| path.starts_with(scope))
+                && workspace.resolve(&path.to_string_lossy()).is_ok()
+        };
+        let mut changes = Vec::new();
+        for (path, hash) in manifest.files.iter().filter(|(path, _)| in_scope(path)) {
+            match fs::metadata(path) {
+                Ok(metadata) if metadata.is_file() => {
+                    if self.store(path, &metadata)? != *hash {
+                        changes.push((path.clone(), ChangeKind::Modified));
+                    }
+                }
+                _ => changes.push((path.clone(), ChangeKind::Deleted)),
+            }
+        }
+        if !manifest.truncated {
+            for (path, metadata) in self.walk(&manifest.roots) {
+                if in_scope(&path)
+                    && metadata.len() <= self.max_file_bytes
+                    && !manifest.files.contains_key(&path)
+                    && !manifest.skipped.contains(&path)
+                {
+                    changes.push((path, ChangeKind::Added));
+                }
+            }
+        }
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(changes)
+    }
+
+    /// Regular files under `roots`, honoring ignore files but including hidden ones.
+    fn walk(&self, roots: &[PathBuf]) -> Vec<(PathBuf, Metadata)> {
+        let Some((first, rest)) = roots.split_first() else {
+            return Vec::new();
+        };
+        let mut builder = WalkBuilder::new(first);
+        for root in rest {
+            builder.add(root);
+        }
+        let store = self.dir.clone();
+        builder
+            .hidden(false)
+            .filter_entry(move |entry| entry.file_name() != ".git" && !entry.path().starts_with(&store))
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((entry.into_path(), metadata))
+            })
+            .collect()
+    }
+
+    /// Copies the file into the object store unless it is already there, returning its hash.
+    fn store(&self, path: &Path, metadata: &Metadata) -> Result<String> {
+        let modified = metadata.modified()?;
+        if let Some((len, mtime, hash)) = self.hashes.lock().unwrap().get(path) {
+            // Pruning may have removed the object since.
+            if *len == metadata.len() && *mtime == modified && self.object_path(hash).exists() {
+                return Ok(hash.clone());
+            }
+        }
+        let content = fs::read(path).map_err(|e| anyhow!("Failed to read file '{}': {}", path.display(), e))?;
+        let hash = format!("{:x}", Sha256::digest(&content));
+        let object = self.object_path(&hash);
+        if !object.exists() {
+            fs::create_dir_all(object.parent().unwrap())?;
+            let partial = object.with_extension(format!("{:016x}.partial", RandomState::new().hash_one(path)));
+            fs::write(&partial, &content)?;
+            fs::rename(&partial, &object)?;
+        }
+        self.hashes
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (metadata.len(), modified, hash.clone()));
+        Ok(hash)
+    }
+
+    /// The store's lock file; snapshots take it shared and pruning exclusively.
+    fn lock(&self) -> Result<RwLock<File>> {
+        let path = self.dir.join("lock");
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| anyhow!("Failed to open '{}': {}", path.display(), e))?;
+        Ok(RwLock::new(file))
+    }
+
+    fn object(&self, hash: &str) -> Result<Vec<u8>> {
+        fs::read(self.object_path(hash)).map_err(|e| anyhow!("Checkpoint object {} is missing: {}", hash, e))
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.dir.join("objects").join(&hash[..2]).join(&hash[2..])
+    }
+
+    fn load(&self, id: &str) -> Result<Manifest> {
+        let (session, turn) = id
+            .rsplit_once('-')
+            .filter(|(session, turn)| {
+                session.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                    && turn.chars().all(|c| c.is_ascii_digit())
+            })
+            .ok_or_else(|| anyhow!("Invalid checkpoint id '{}'", id))?;
+        read_manifest(&self.dir.join("sessions").join(session).join(format!("{}.json", turn)))
+            .map_err(|_| anyhow!("Checkpoint '{}' does not exist", id))
+    }
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+fn file_changes(changes: Vec<(PathBuf, ChangeKind)>) -> Vec<FileChange> {
+    changes
+        .into_iter()
+        .map(|(path, change)| FileChange {
+            path: path.display().to_string(),
+            change,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(dir: &Path, keep: usize, max_store_bytes: u64) -> CheckpointStore {
+        CheckpointStore::new(&CheckpointsConfig {
+            dir: Some(dir.to_path_buf()),
+            keep,
+            max_store_bytes,
+            ..CheckpointsConfig::default()
+        })
+        .unwrap()
+    }
+
+    fn objects(dir: &Path) -> usize {
+        fs::read_dir(dir.join("objects")).unwrap().flatten().map(|d| fs::read_dir(d.path()).unwrap().count()).sum()
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_checkpoints_and_their_contents() {
+        let (workspace, state) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let roots = [workspace.path().to_path_buf()];
+        let file = workspace.path().join("notes.txt");
+        let store = store(state.path(), 2, 0);
+        let turns = Turns::new();
+        for turn in 1..=4 {
+            fs::write(&file, format!("version {}", turn)).unwrap();
+            store.write_snapshot(&turns, turn, "edit", &roots).unwrap();
+        }
+        assert_eq!(objects(state.path()), 4);
+
+        assert_eq!(store.prune().unwrap(), 2);
+        let turns_left: Vec<u64> = store.list(None).unwrap().iter().map(|c| c.turn).collect();
+        assert_eq!(turns_left, [4, 3]);
+        assert_eq!(objects(state.path()), 2);
+        assert!(store.load(&format!("{}-1", turns.session())).is_err());
+    }
+
+    #[test]
+    fn prune_by_size_keeps_at_least_the_newest_checkpoint() {
+        let (workspace, state) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let roots = [workspace.path().to_path_buf()];
+        let file = workspace.path().join("data.txt");
+        let store = store(state.path(), 0, 10);
+        let turns = Turns::new();
+        for turn in 1..=3 {
+            fs::write(&file, "x".repeat(8 * turn as usize)).unwrap();
+            store.write_snapshot(&turns, turn, "edit", &roots).unwrap();
+        }
+        assert_eq!(store.prune().unwrap(), 2);
+        assert_eq!(store.list(None).unwrap()[0].turn, 3);
+    }
+
+    #[test]
+    fn files_are_stored_again_after_their_object_was_pruned() {
+        let (workspace, state) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let roots = [workspace.path().to_path_buf()];
+        let file = workspace.path().join("a.txt");
+        fs::write(&file, "unchanged").unwrap();
+        let store = store(state.path(), 1, 0);
+        let turns = Turns::new();
+        store.write_snapshot(&turns, 1, "edit", &roots).unwrap();
+        fs::remove_file(state.path().join("sessions").join(turns.session()).join("1.json")).unwrap();
+        store.prune().unwrap();
+        assert_eq!(objects(state.path()), 0);
+
+        // The file's hash is still cached, but its object is gone.
+        store.write_snapshot(&turns, 2, "edit", &roots).unwrap();
+        let manifest = store.load(&format!("{}-2", turns.session())).unwrap();
+        assert_eq!(store.object(&manifest.files[&file]).unwrap(), b"unchanged");
+    }
+}
@@ -0,0 +1,69 @@
+//! Argument completion for `completion/complete`.
+//!
+//! Completion is keyed on the argument name, so `path` and `cmd` complete the
+//! same way whichever prompt or resource template the client asks about.
+
+use ignore::WalkBuilder;
+use rmcp::model::CompletionInfo;
+
+use crate::workspace::Workspace;
+
+/// Completes a workspace path. Entries excluded by `.gitignore` and similar
+/// files are skipped, as are hidden ones unless the prefix starts with a dot.
+/// Directories end with `/` so the client can keep descending.
+pub fn complete_path(workspace: &Workspace, value: &str) -> Vec<String> {
+    let (dir, prefix) = match value.rfind('/') {
+        Some(i) => value.split_at(i + 1),
+        None => ("", value),
+    };
+    let Ok(resolved) = workspace.resolve(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+
+    let mut values: Vec<String> = WalkBuilder::new(&resolved)
+        .max_depth(Some(1))
+        .hidden(!prefix.starts_with('.'))
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.depth() == 1)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?;
+            if !name.starts_with(prefix) {
+                return None;
+            }
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    values.sort();
+    values
+}
+
+/// Completes the program name from the `allowed` commands, then path arguments.
+pub fn complete_command(workspace: &Workspace, allowed: &[String], value: &str) -> Vec<String> {
+    match value.rfind(char::is_whitespace) {
+        None => allowed
+            .iter()
+            .filter(|command| command.starts_with(value))
+            .cloned()
+            .collect(),
+        Some(i) => {
+            let (head, last) = value.split_at(i + 1);
+            complete_path(workspace, last)
+                .into_iter()
+                .map(|path| format!("{}{}", head, path))
+                .collect()
+        }
+    }
+}
+
+/// Truncates to the number of values the spec allows in one response.
+pub fn completion_info(mut values: Vec<String>) -> CompletionInfo {
+    let total = values.len();
+    values.truncate(CompletionInfo::MAX_VALUES);
+    CompletionInfo {
+        values,
+        total: Some(total as u32),
+        has_more: Some(total > CompletionInfo::MAX_VALUES),
+    }
+}
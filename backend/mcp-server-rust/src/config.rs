@@ -0,0 +1,489 @@
+use anyhow::{anyhow, bail, Result};
+use rmcp::model::LoggingLevel;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// Environment variable pointing at the server's TOML config file.
+pub const CONFIG_ENV: &str = "MCP_SERVER_CONFIG";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub plugins: PluginsConfig,
+    pub http: HttpConfig,
+    pub auth: AuthConfig,
+    pub confirm: ConfirmConfig,
+    pub shell: ShellConfig,
+    pub logging: LoggingConfig,
+    pub pagination: PaginationConfig,
+    /// Other MCP servers whose tools, resources and prompts this server proxies.
+    pub downstream: Vec<DownstreamConfig>,
+    pub checkpoints: CheckpointsConfig,
+    pub redaction: RedactionConfig,
+    pub audit: AuditConfig,
+    pub search: SearchConfig,
+    pub scratchpad: ScratchpadConfig,
+    pub metrics: MetricsConfig,
+    pub openwebui: OpenWebUiConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PluginsConfig {
+    /// Directory scanned for `*.wasm` plugins. Plugins are disabled when unset.
+    pub dir: Option<PathBuf>,
+    /// Fuel granted to every plugin invocation.
+    pub fuel: u64,
+    /// Wall-clock limit for every plugin invocation.
+    pub timeout_secs: u64,
+    /// Maximum bytes a plugin may write to stdout or stderr.
+    pub max_output_bytes: usize,
+    /// Whether plugins may modify files in the workspace.
+    pub writable: bool,
+}
+
+impl Default for PluginsConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            fuel: 1_000_000_000,
+            timeout_secs: 10,
+            max_output_bytes: 1024 * 1024,
+            writable: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Serves MCP over streamable HTTP on this address instead of stdio.
+    pub listen: Option<SocketAddr>,
+    pub tls: Option<TlsConfig>,
+    pub sessions: SessionsConfig,
+}
+
+/// Limits on each client session served over HTTP. Every session has its
+/// own roots, result pages, shell sessions and checkpoints.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionsConfig {
+    /// Sessions without a request for this long are closed and their state dropped.
+    pub idle_timeout_secs: u64,
+    /// Tool calls allowed per minute in one session. Unlimited when unset.
+    pub requests_per_minute: Option<u32>,
+    /// Directories a session's announced roots must lie in; other roots are
+    /// ignored. When empty, only the server's working directory and below.
+    pub allowed_roots: Vec<PathBuf>,
+}
+
+impl Default for SessionsConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 30 * 60,
+            requests_per_minute: None,
+            allowed_roots: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain presented by the server.
+    pub cert: PathBuf,
+    /// PEM private key for `cert`.
+    pub key: PathBuf,
+    /// PEM CA bundle; when set, clients must present a certificate it signed.
+    pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub tokens: Vec<TokenConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenConfig {
+    /// Label used in errors and logs instead of the secret.
+    pub name: String,
+    pub token: String,
+    /// Tools this token may list and call. All tools when unset.
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+    /// Requests allowed per minute. Unlimited when unset.
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfirmConfig {
+    /// Calls matching any rule need the user's approval through MCP elicitation.
+    pub rules: Vec<ConfirmRule>,
+    /// What to do when the client cannot ask the user.
+    pub fallback: ConfirmFallback,
+    /// What to do for calls made without any client, over REST or with
+    /// `call`; `fallback` when unset.
+    pub non_interactive: Option<ConfirmFallback>,
+    /// How long to wait for the user before denying the call.
+    pub timeout_secs: u64,
+}
+
+impl Default for ConfirmConfig {
+    fn default() -> Self {
+        // Every tool that may change something needs approval unless the
+        // config says otherwise.
+        Self {
+            rules: vec![ConfirmRule {
+                tool: "*".into(),
+                writes: true,
+                argument: None,
+                pattern: None,
+            }],
+            fallback: ConfirmFallback::Deny,
+            non_interactive: None,
+            timeout_secs: 120,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfirmRule {
+    /// Tool name, or `*` for every tool.
+    pub tool: String,
+    /// Only match tools that may change something: those annotated
+    /// destructive or not annotated read-only.
+    #[serde(default)]
+    pub writes: bool,
+    /// Argument matched against `pattern`. The rule matches any call when unset.
+    #[serde(default)]
+    pub argument: Option<String>,
+    /// Regex the argument's value must match.
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShellConfig {
+    /// Programs `shell_command` and `session_exec` may run, by name.
+    pub allowed_commands: Vec<String>,
+    /// Commands still running after this long are killed.
+    pub timeout_secs: u64,
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        Self {
+            allowed_commands: ["ls", "cat", "grep", "pwd", "echo", "find", "whoami"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            timeout_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmFallback {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Filter for the stderr and file sinks in `RUST_LOG` syntax; `RUST_LOG` overrides it.
+    pub level: String,
+    /// Off by default: clients such as the TUI share the server's stderr with their own display.
+    pub stderr: bool,
+    /// Appends log lines to this file.
+    pub file: Option<PathBuf>,
+    /// Level forwarded to clients until they send `logging/setLevel`.
+    pub client_level: LoggingLevel,
+    /// Name of the auth token whose HTTP sessions also receive events outside
+    /// any session, such as reloads and downstream servers. The stdio client
+    /// always does; other sessions only see their own events.
+    pub admin_token: Option<String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".into(),
+            stderr: false,
+            file: None,
+            client_level: LoggingLevel::Warning,
+            admin_token: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaginationConfig {
+    /// Tool output longer than this is returned one page at a time through `read_more`.
+    pub page_bytes: usize,
+    /// How long stored output stays readable after it was last paged through.
+    pub ttl_secs: u64,
+    /// Output kept for `read_more` per session; the least recently read is
+    /// dropped to make room.
+    pub max_stored_bytes: usize,
+    /// Larger files are refused by `read_file` instead of being read and stored.
+    pub max_read_bytes: u64,
+    /// Tools returned per `tools/list` page.
+    pub tools_per_page: usize,
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        Self {
+            page_bytes: 64 * 1024,
+            ttl_secs: 15 * 60,
+            max_stored_bytes: 64 * 1024 * 1024,
+            max_read_bytes: 16 * 1024 * 1024,
+            tools_per_page: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DownstreamConfig {
+    /// Namespace for the server's tools and prompts (`<name>__<tool>`) and resource URIs (`<name>+<uri>`).
+    pub name: String,
+    /// Program to spawn and talk to over stdio.
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Extra environment variables for `command`.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Streamable HTTP endpoint to connect to instead of spawning `command`.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Bearer token sent to `url`.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckpointsConfig {
+    /// Snapshots the workspace before every tool call not annotated read-only.
+    pub enabled: bool,
+    /// Where snapshots are kept; `$XDG_STATE_HOME/mcp-server-rust/checkpoints` when unset.
+    pub dir: Option<PathBuf>,
+    /// Larger files are left out of snapshots.
+    pub max_file_bytes: u64,
+    /// Files beyond this many are left out of snapshots.
+    pub max_files: usize,
+    /// Most checkpoints kept across sessions; older ones are pruned. 0 for no limit.
+    pub keep: usize,
+    /// Most bytes of file contents kept; the oldest checkpoints are pruned past it. 0 for no limit.
+    pub max_store_bytes: u64,
+    /// Refuses a tool call whose checkpoint can't be taken, instead of warning in its result.
+    pub required: bool,
+}
+
+impl Default for CheckpointsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+            max_file_bytes: 1024 * 1024,
+            max_files: 10_000,
+            keep: 500,
+            max_store_bytes: 1024 * 1024 * 1024,
+            required: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactionConfig {
+    /// Runs the built-in detectors; custom `patterns` apply either way.
+    pub enabled: bool,
+    /// Bits per character above which a long token counts as a secret; 0 disables the check.
+    pub entropy_threshold: f64,
+    /// Shorter tokens are never checked for entropy.
+    pub min_entropy_length: usize,
+    pub patterns: Vec<RedactionPattern>,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            entropy_threshold: 4.0,
+            min_entropy_length: 32,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactionPattern {
+    /// Shown in the `[REDACTED:<name>]` marker and the audit log.
+    pub name: String,
+    /// Regex to redact; when it has a capture group only the group is replaced.
+    pub pattern: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Appends one JSON line per tool call to this file.
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    /// Offers `search_code`, backed by a full-text index of each workspace root.
+    pub enabled: bool,
+    /// Where indexes are kept; `$XDG_STATE_HOME/mcp-server-rust/index` when unset.
+    pub dir: Option<PathBuf>,
+    /// Larger files are not indexed.
+    pub max_file_bytes: u64,
+    /// Files beyond this many per root are not indexed.
+    pub max_files: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+            max_file_bytes: 1024 * 1024,
+            max_files: 50_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScratchpadConfig {
+    /// Offers the `todo_*` and `memory_*` tools, backed by a file per workspace.
+    pub enabled: bool,
+    /// Where the files are kept; `$XDG_STATE_HOME/mcp-server-rust/scratchpad` when unset.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for ScratchpadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Serves Prometheus metrics at `/metrics` on this address. Off when unset.
+    pub listen: Option<SocketAddr>,
+    /// Bearer token scrapers must present. Anyone who can reach `listen` may scrape when unset.
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenWebUiConfig {
+    /// Base URL of an Open WebUI instance, e.g. `https://chat.example.com`.
+    /// The `openwebui_*` tools are offered when set.
+    pub url: Option<String>,
+    /// API key from the Open WebUI account whose knowledge, notes and memories the tools use.
+    pub api_key: Option<String>,
+    pub timeout_secs: u64,
+}
+
+impl Default for OpenWebUiConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            api_key: None,
+            timeout_secs: 30,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the file named by `MCP_SERVER_CONFIG`, or the defaults when it is unset.
+    pub fn load() -> Result<Self> {
+        match std::env::var_os(CONFIG_ENV) {
+            Some(path) => Self::from_file(Path::new(&path)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read config '{}': {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| anyhow!("Invalid config '{}': {}", path.display(), e))
+    }
+
+    /// Checks what parsing can't: patterns compile, referenced files load and
+    /// a listener would be protected. Connects to nothing and writes nothing.
+    pub fn validate(&self) -> Result<()> {
+        tracing_subscriber::EnvFilter::try_new(&self.logging.level)
+            .map_err(|e| anyhow!("Invalid log level '{}': {}", self.logging.level, e))?;
+        crate::confirm::Confirmation::new(&self.confirm)?;
+        for command in &self.shell.allowed_commands {
+            if command.is_empty() || command.contains('/') {
+                bail!("Allowed command '{}' must be a program name, not a path", command);
+            }
+        }
+        if self.shell.timeout_secs == 0 {
+            bail!("`shell.timeout_secs` must be at least 1");
+        }
+        crate::redact::Redactor::new(&self.redaction)?;
+        crate::aggregator::validate(&self.downstream)?;
+        if let Some(dir) = &self.plugins.dir {
+            if !dir.is_dir() {
+                bail!("Plugins directory '{}' does not exist", dir.display());
+            }
+        }
+        if let Some(tls) = &self.http.tls {
+            crate::http::tls_acceptor(tls)?;
+        }
+        if let Some(addr) = self.http.listen {
+            let mutual_tls = self.http.tls.as_ref().is_some_and(|tls| tls.client_ca.is_some());
+            if self.auth.tokens.is_empty() && !mutual_tls {
+                bail!("`http.listen` = {} needs bearer tokens or client certificates", addr);
+            }
+        }
+        if self.openwebui.url.is_some() {
+            crate::openwebui::OpenWebUi::new(&self.openwebui)?;
+        }
+        let mut names = std::collections::HashSet::new();
+        for token in &self.auth.tokens {
+            if !names.insert(&token.name) {
+                bail!("Duplicate token name '{}'", token.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `name` under the server's state directory, `$XDG_STATE_HOME/mcp-server-rust`.
+pub(crate) fn state_dir(name: &str) -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("mcp-server-rust")
+        .join(name)
+}
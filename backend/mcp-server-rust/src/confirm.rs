@@ -0,0 +1,254 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use rmcp::{
+    model::{CallToolRequestParam, ToolAnnotations},
+    schemars::JsonSchema,
+    service::{ElicitationError, Peer},
+    ErrorData as McpError, RoleServer,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::config::{ConfirmConfig, ConfirmFallback};
+
+#[derive(Deserialize, JsonSchema)]
+struct Approval {
+    /// Run this tool call?
+    approve: bool,
+}
+
+rmcp::elicit_safe!(Approval);
+
+struct Rule {
+    tool: String,
+    writes: bool,
+    argument: Option<String>,
+    pattern: Option<Regex>,
+}
+
+impl Rule {
+    fn matches(&self, request: &CallToolRequestParam, annotations: &ToolAnnotations) -> bool {
+        if self.tool != "*" && self.tool != request.name {
+            return false;
+        }
+        if self.writes && annotations.destructive_hint != Some(true) && annotations.read_only_hint == Some(true) {
+            return false;
+        }
+        let value = |name: &str| {
+            request
+                .arguments
+                .as_ref()
+                .and_then(|args| args.get(name))
+                .map(display_value)
+        };
+        match (&self.argument, &self.pattern) {
+            (Some(argument), Some(pattern)) => value(argument).is_some_and(|v| pattern.is_match(&v)),
+            (Some(argument), None) => value(argument).is_some(),
+            (None, Some(pattern)) => request
+                .arguments
+                .iter()
+                .flatten()
+                .any(|(_, v)| pattern.is_match(&display_value(v))),
+            (None, None) => true,
+        }
+    }
+}
+
+/// Asks the user, through MCP elicitation, to approve tool calls matched by the configured rules.
+pub struct Confirmation {
+    rules: Vec<Rule>,
+    fallback: ConfirmFallback,
+    non_interactive: ConfirmFallback,
+    timeout: Duration,
+}
+
+impl Confirmation {
+    pub fn new(config: &ConfirmConfig) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                let pattern = rule
+                    .pattern
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .map_err(|e| anyhow!("Invalid confirmation pattern for '{}': {}", rule.tool, e))?;
+                Ok(Rule {
+                    tool: rule.tool.clone(),
+                    writes: rule.writes,
+                    argument: rule.argument.clone(),
+                    pattern,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            rules,
+            fallback: config.fallback,
+            non_interactive: config.non_interactive.unwrap_or(config.fallback),
+            timeout: Duration::from_secs(config.timeout_secs),
+        })
+    }
+
+    /// Returns `Ok` when the call may run, either because no rule matches or the user approved it.
+    /// `annotations` are the called tool's. Calls made without a client follow `non_interactive`.
+    pub async fn check(
+        &self,
+        peer: Option<&Peer<RoleServer>>,
+        request: &CallToolRequestParam,
+        annotations: &ToolAnnotations,
+    ) -> Result<(), McpError> {
+        if !self.rules.iter().any(|rule| rule.matches(request, annotations)) {
+            return Ok(());
+        }
+
+        let denied = |reason: &str| {
+            Err(McpError::invalid_request(
+                format!("Call to '{}' was not confirmed: {}", request.name, reason),
+                None,
+            ))
+        };
+        let Some(peer) = peer else {
+            return match self.non_interactive {
+                ConfirmFallback::Allow => Ok(()),
+                ConfirmFallback::Deny => denied("there is no client to ask for confirmation"),
+            };
+        };
+        let answer = peer
+            .elicit_with_timeout::<Approval>(describe(request), Some(self.timeout))
+            .await;
+        match answer {
+            Ok(Some(Approval { approve: true })) => Ok(()),
+            Ok(_) => denied("the user did not approve it"),
+            Err(ElicitationError::UserDeclined) => denied("the user declined"),
+            Err(ElicitationError::UserCancelled) => denied("the user cancelled"),
+            Err(ElicitationError::CapabilityNotSupported) => match self.fallback {
+                ConfirmFallback::Allow => Ok(()),
+                ConfirmFallback::Deny => denied("the client cannot ask for confirmation"),
+            },
+            Err(e) => denied(&e.to_string()),
+        }
+    }
+}
+
+/// The prompt shown to the user: the tool name followed by every argument verbatim.
+fn describe(request: &CallToolRequestParam) -> String {
+    let mut message = format!("The assistant wants to run `{}`.", request.name);
+    for (name, value) in request.arguments.iter().flatten() {
+        message.push_str(&format!("\n\n{}:\n{}", name, display_value(value)));
+    }
+    message
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfirmRule;
+    use serde_json::json;
+
+    fn request(name: &str, arguments: Value) -> CallToolRequestParam {
+        CallToolRequestParam {
+            name: name.to_string().into(),
+            arguments: arguments.as_object().cloned(),
+        }
+    }
+
+    fn rule(tool: &str, writes: bool, argument: Option<&str>, pattern: Option<&str>) -> Rule {
+        Rule {
+            tool: tool.into(),
+            writes,
+            argument: argument.map(Into::into),
+            pattern: pattern.map(|p| Regex::new(p).unwrap()),
+        }
+    }
+
+    fn read_only() -> ToolAnnotations {
+        ToolAnnotations::new().read_only(true).destructive(false)
+    }
+
+    #[test]
+    fn rules_match_by_tool_argument_and_pattern() {
+        let env = request("read_file", json!({"path": "app/.env"}));
+        let src = request("read_file", json!({"path": "src/main.rs"}));
+        let any = ToolAnnotations::new();
+
+        assert!(rule("read_file", false, None, None).matches(&src, &any));
+        assert!(rule("*", false, None, None).matches(&src, &any));
+        assert!(!rule("shell_command", false, None, None).matches(&src, &any));
+        assert!(rule("read_file", false, Some("path"), Some(r"\.env$")).matches(&env, &any));
+        assert!(!rule("read_file", false, Some("path"), Some(r"\.env$")).matches(&src, &any));
+        assert!(rule("read_file", false, Some("path"), None).matches(&src, &any));
+        assert!(!rule("read_file", false, Some("mode"), None).matches(&src, &any));
+        // Without `argument`, the pattern may match any argument's value.
+        assert!(rule("*", false, None, Some("^app/")).matches(&env, &any));
+        assert!(!rule("*", false, None, Some("^app/")).matches(&src, &any));
+    }
+
+    #[test]
+    fn write_rules_follow_the_annotations() {
+        let call = request("tool", json!({}));
+        let writes = rule("*", true, None, None);
+
+        assert!(!writes.matches(&call, &read_only()));
+        assert!(writes.matches(&call, &ToolAnnotations::new()));
+        assert!(writes.matches(&call, &ToolAnnotations::new().read_only(false).destructive(false)));
+        // A destructive hint wins over a read-only one.
+        assert!(writes.matches(&call, &ToolAnnotations::new().read_only(true).destructive(true)));
+    }
+
+    #[test]
+    fn default_rules_confirm_every_tool_that_may_write() {
+        let confirmation = Confirmation::new(&ConfirmConfig::default()).unwrap();
+        let matches = |annotations: &ToolAnnotations| {
+            let call = request("any_tool", json!({}));
+            confirmation.rules.iter().any(|rule| rule.matches(&call, annotations))
+        };
+        assert!(matches(&ToolAnnotations::new().read_only(false).destructive(true)));
+        assert!(matches(&ToolAnnotations::new()));
+        assert!(!matches(&read_only()));
+    }
+
+    #[tokio::test]
+    async fn calls_without_a_client_follow_non_interactive() {
+        let call = request("shell_command", json!({"cmd": "ls"}));
+        let annotations = ToolAnnotations::new().read_only(false).destructive(true);
+        let config = |fallback, non_interactive| ConfirmConfig {
+            fallback,
+            non_interactive,
+            ..ConfirmConfig::default()
+        };
+
+        let deny = Confirmation::new(&config(ConfirmFallback::Deny, None)).unwrap();
+        let error = deny.check(None, &call, &annotations).await.unwrap_err();
+        assert!(error.message.contains("no client"), "{}", error.message);
+        assert!(deny.check(None, &call, &read_only()).await.is_ok());
+
+        let inherited = Confirmation::new(&config(ConfirmFallback::Allow, None)).unwrap();
+        assert!(inherited.check(None, &call, &annotations).await.is_ok());
+
+        let overridden = Confirmation::new(&config(ConfirmFallback::Allow, Some(ConfirmFallback::Deny))).unwrap();
+        assert!(overridden.check(None, &call, &annotations).await.is_err());
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        let config = ConfirmConfig {
+            rules: vec![ConfirmRule {
+                tool: "read_file".into(),
+                writes: false,
+                argument: None,
+                pattern: Some("(".into()),
+            }],
+            ..ConfirmConfig::default()
+        };
+        assert!(Confirmation::new(&config).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{anyhow, bail, Result};
+use axum::{middleware, Router};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+    service::TowerToHyperService,
+};
+use rmcp::{
+    handler::server::ServerHandler,
+    transport::streamable_http_server::{
+        session::local::{LocalSessionManager, SessionConfig},
+        StreamableHttpServerConfig, StreamableHttpService,
+    },
+};
+use rustls::{server::WebPkiClientVerifier, RootCertStore, ServerConfig};
+use rustls_pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+use crate::auth::{self, Authenticator};
+use crate::config::{HttpConfig, TlsConfig};
+
+/// Serves MCP over streamable HTTP at `/mcp`, creating one handler per
+/// session. Sessions idle for `http.sessions.idle_timeout_secs` are closed.
+pub async fn serve<S: ServerHandler>(
+    factory: impl Fn() -> S + Send + Sync + 'static,
+    http: &HttpConfig,
+    authenticator: Arc<Authenticator>,
+) -> Result<()> {
+    let addr = http
+        .listen
+        .ok_or_else(|| anyhow!("No listen address configured"))?;
+    let sessions = LocalSessionManager {
+        sessions: Default::default(),
+        session_config: SessionConfig {
+            keep_alive: Some(Duration::from_secs(http.sessions.idle_timeout_secs)),
+            ..SessionConfig::default()
+        },
+    };
+    let service = StreamableHttpService::new(
+        move || Ok(factory()),
+        sessions.into(),
+        StreamableHttpServerConfig::default(),
+    );
+    let app = Router::new().nest_service("/mcp", service);
+    let app = authenticated(app, addr, http.tls.as_ref(), authenticator)?;
+    listen(app, addr, http.tls.as_ref(), "MCP").await
+}
+
+/// Puts `app` behind bearer-token authentication. Fails when neither tokens
+/// nor client certificates would protect the listener on `addr`.
+pub fn authenticated(
+    app: Router,
+    addr: SocketAddr,
+    tls: Option<&TlsConfig>,
+    authenticator: Arc<Authenticator>,
+) -> Result<Router> {
+    let mutual_tls = tls.is_some_and(|tls| tls.client_ca.is_some());
+    if !mutual_tls {
+        if authenticator.is_empty() {
+            bail!(
+                "Refusing to listen on {} without bearer tokens or client certificates",
+                addr
+            );
+        }
+        authenticator.require_tokens();
+    }
+    Ok(app.layer(middleware::from_fn_with_state(authenticator, auth::require_auth)))
+}
+
+/// Serves `app` on `addr`, over TLS when configured. `protocol` only names
+/// the listener in the log.
+pub async fn listen(app: Router, addr: SocketAddr, tls: Option<&TlsConfig>, protocol: &str) -> Result<()> {
+    let acceptor = tls.map(tls_acceptor).transpose()?;
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!(%addr, tls = acceptor.is_some(), protocol, "Listening over HTTP");
+
+    let Some(acceptor) = acceptor else {
+        axum::serve(listener, app).await?;
+        return Ok(());
+    };
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::warn!(%peer, error = %e, "TLS handshake failed");
+                    return;
+                }
+            };
+            let _ = Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(app))
+                .await;
+        });
+    }
+}
+
+pub fn tls_acceptor(config: &TlsConfig) -> Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(&config.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow!("Failed to load certificate '{}': {}", config.cert.display(), e))?;
+    let key = PrivateKeyDer::from_pem_file(&config.key)
+        .map_err(|e| anyhow!("Failed to load key '{}': {}", config.key.display(), e))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match &config.client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(path)
+                .map_err(|e| anyhow!("Failed to load client CA '{}': {}", path.display(), e))?
+            {
+                roots.add(cert?)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder.with_single_cert(certs, key)?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
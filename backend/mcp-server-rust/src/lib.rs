@@ -0,0 +1,58 @@
+//! The tools, policy and server behind `mcp-server-rust`, for embedding in
+//! other Rust programs.
+//!
+//! Tools implement [`Tool`] and are collected in a [`ToolRegistry`]. Calling
+//! one through [`Policy`] applies the same token permissions, confirmations,
+//! checkpoints, redaction and audit log as the server; calling it through the
+//! registry skips them. [`McpServer`] serves a registry over any rmcp transport.
+//!
+//! ```no_run
+//! use mcp_server_rust::{Policy, ToolContext, ToolRegistry, config::Config, workspace::Workspace};
+//! use std::sync::Arc;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let tools = ToolRegistry::builtin();
+//! let policy = Policy::new(&Config::default())?;
+//! let context = ToolContext::new(Arc::new(Workspace::new("/srv/project".into())));
+//! let read_file = tools.get("read_file").expect("built-in tool");
+//! let arguments = serde_json::json!({"path": "README.md"});
+//! let result = policy
+//!     .call(read_file.as_ref(), arguments.as_object().cloned(), context, None)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod aggregator;
+pub mod audit;
+pub mod auth;
+pub mod checkpoints;
+mod completion;
+pub mod config;
+pub mod confirm;
+pub mod http;
+pub mod logging;
+pub mod metrics;
+pub mod openapi;
+pub mod openwebui;
+pub mod pages;
+#[cfg(feature = "wasm-plugins")]
+pub mod plugins;
+pub mod policy;
+pub mod redact;
+pub mod registry;
+pub mod reload;
+pub mod sampling;
+pub mod scratchpad;
+pub mod search;
+pub mod server;
+pub mod shell_sessions;
+pub mod tables;
+pub mod tool;
+pub mod tools;
+pub mod workspace;
+
+pub use policy::Policy;
+pub use registry::ToolRegistry;
+pub use server::McpServer;
+pub use tool::{Tool, ToolContext, ToolFuture};
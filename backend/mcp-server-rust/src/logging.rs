@@ -0,0 +1,268 @@
+//! Structured logging through `tracing`.
+//!
+//! Events from this crate go to the optional stderr and file sinks and are
+//! forwarded to clients as MCP `notifications/message`, filtered by the level
+//! each client picked with `logging/setLevel`.
+//!
+//! An event belongs to the session named by its `session` field or that of
+//! an enclosing span, and only that session's client receives it. Events
+//! outside any session, such as reloads and downstream servers, go only to
+//! admin sessions: the stdio client and, over HTTP, sessions authenticated
+//! with the token named in `logging.admin_token`.
+
+use anyhow::{anyhow, Result};
+use rmcp::{
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+    service::Peer,
+    RoleServer,
+};
+use serde_json::{Map, Value};
+use std::fs::OpenOptions;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::mpsc;
+use tracing::{field::Field, span, Event, Level, Subscriber};
+use tracing_subscriber::{
+    filter::Targets, fmt, layer::Context, prelude::*, registry::LookupSpan, registry::Registry, EnvFilter,
+    Layer,
+};
+
+use crate::config::LoggingConfig;
+
+/// Log state of one MCP session: where to send messages and from which level on.
+pub struct SessionLog {
+    session: String,
+    /// Whether the session also receives events outside any session.
+    admin: AtomicBool,
+    peer: Mutex<Option<Peer<RoleServer>>>,
+    level: Mutex<LoggingLevel>,
+}
+
+impl SessionLog {
+    pub fn attach(&self, peer: Peer<RoleServer>) {
+        *self.peer.lock().unwrap() = Some(peer);
+    }
+
+    pub fn grant_admin(&self) {
+        self.admin.store(true, Ordering::Relaxed);
+    }
+
+    pub fn set_level(&self, level: LoggingLevel) {
+        *self.level.lock().unwrap() = level;
+    }
+
+    fn target(&self, level: LoggingLevel) -> Option<Peer<RoleServer>> {
+        if severity(level) < severity(*self.level.lock().unwrap()) {
+            return None;
+        }
+        self.peer
+            .lock()
+            .unwrap()
+            .clone()
+            .filter(|peer| !peer.is_transport_closed())
+    }
+}
+
+/// Tracks the live sessions that receive forwarded log messages.
+pub struct LogHub {
+    sessions: Mutex<Vec<Weak<SessionLog>>>,
+    default_level: LoggingLevel,
+    admin_token: Option<String>,
+}
+
+impl LogHub {
+    /// Creates the log state for the session with id `session`; it is
+    /// forgotten once dropped.
+    pub fn session(&self, session: &str) -> Arc<SessionLog> {
+        let session = Arc::new(SessionLog {
+            session: session.to_string(),
+            admin: AtomicBool::new(false),
+            peer: Mutex::new(None),
+            level: Mutex::new(self.default_level),
+        });
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|s| s.strong_count() > 0);
+        sessions.push(Arc::downgrade(&session));
+        session
+    }
+
+    /// Whether sessions authenticated with the token named `token` are admin sessions.
+    pub fn is_admin_token(&self, token: &str) -> bool {
+        self.admin_token.as_deref() == Some(token)
+    }
+
+    /// The peers to send a message from `session` at `level` to; for a
+    /// message outside any session, only the admin sessions.
+    fn targets(&self, session: Option<&str>, level: LoggingLevel) -> Vec<Peer<RoleServer>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|s| s.strong_count() > 0);
+        sessions
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|log| match session {
+                Some(session) => log.session == session,
+                None => log.admin.load(Ordering::Relaxed),
+            })
+            .filter_map(|log| log.target(level))
+            .collect()
+    }
+}
+
+/// A log message and the session it belongs to, if any.
+struct Message {
+    session: Option<String>,
+    param: LoggingMessageNotificationParam,
+}
+
+/// Installs the global subscriber and starts forwarding events to clients.
+pub fn init(config: &LoggingConfig) -> Result<Arc<LogHub>> {
+    let hub = Arc::new(LogHub {
+        sessions: Mutex::new(Vec::new()),
+        default_level: config.client_level,
+        admin_token: config.admin_token.clone(),
+    });
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let filter = || {
+        EnvFilter::try_from_default_env()
+            .or_else(|_| EnvFilter::try_new(&config.level))
+            .map_err(|e| anyhow!("Invalid log level '{}': {}", config.level, e))
+    };
+    let stderr = if config.stderr {
+        Some(
+            fmt::layer()
+                .with_ansi(std::io::stderr().is_terminal())
+                .with_writer(std::io::stderr)
+                .with_filter(filter()?),
+        )
+    } else {
+        None
+    };
+    let file = match &config.file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| anyhow!("Failed to open log file '{}': {}", path.display(), e))?;
+            Some(
+                fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(Mutex::new(file))
+                    .with_filter(filter()?),
+            )
+        }
+        None => None,
+    };
+    // Only this crate's events reach clients, which also keeps the transport's
+    // own logging about sending these notifications from feeding back into them.
+    let client = ClientLayer { tx }
+        .with_filter(Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::DEBUG));
+
+    Registry::default()
+        .with(stderr)
+        .with(file)
+        .with(client)
+        .try_init()
+        .map_err(|e| anyhow!("Failed to initialize logging: {}", e))?;
+
+    let forwarder = hub.clone();
+    tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            let Message { session, param } = message;
+            for peer in forwarder.targets(session.as_deref(), param.level) {
+                let _ = peer.notify_logging_message(param.clone()).await;
+            }
+        }
+    });
+    Ok(hub)
+}
+
+/// Queues events for delivery to clients.
+struct ClientLayer {
+    tx: mpsc::UnboundedSender<Message>,
+}
+
+/// The `session` field of a span, kept for the events inside it.
+struct SpanSession(String);
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for ClientLayer {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut fields = FieldVisitor(Map::new());
+        attrs.record(&mut fields);
+        if let (Some(session), Some(span)) = (fields.session(), ctx.span(id)) {
+            span.extensions_mut().insert(SpanSession(session));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut fields = FieldVisitor(Map::new());
+        event.record(&mut fields);
+        let session = fields.session().or_else(|| {
+            ctx.event_scope(event)?
+                .find_map(|span| span.extensions().get::<SpanSession>().map(|session| session.0.clone()))
+        });
+        let _ = self.tx.send(Message {
+            session,
+            param: LoggingMessageNotificationParam {
+                level: mcp_level(*metadata.level()),
+                logger: Some(metadata.target().to_string()),
+                data: Value::Object(fields.0),
+            },
+        });
+    }
+}
+
+struct FieldVisitor(Map<String, Value>);
+
+impl FieldVisitor {
+    fn session(&self) -> Option<String> {
+        self.0.get("session")?.as_str().map(str::to_string)
+    }
+}
+
+impl tracing::field::Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().into(), format!("{:?}", value).into());
+    }
+}
+
+fn mcp_level(level: Level) -> LoggingLevel {
+    match level {
+        Level::ERROR => LoggingLevel::Error,
+        Level::WARN => LoggingLevel::Warning,
+        Level::INFO => LoggingLevel::Info,
+        Level::DEBUG | Level::TRACE => LoggingLevel::Debug,
+    }
+}
+
+fn severity(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
@@ -1,128 +1,101 @@
-use anyhow::Result;
-use rmcp::{
-    ErrorData as McpError,
-    model::*,
-    tool, tool_router,
-    handler::server::{
-        tool::{ToolRouter, ToolCallContext},
-        wrapper::Parameters,
-        ServerHandler // Trait
-    },
-    service::{RequestContext, RoleServer},
-    ServiceExt,
-    transport,
-    schemars::JsonSchema,
+use anyhow::{anyhow, bail, Result};
+use clap::{Parser, Subcommand};
+use mcp_server_rust::{
+    config::{Config, CONFIG_ENV},
+    http, logging, metrics, openapi, reload, McpServer,
 };
-use serde::Deserialize;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-
-mod tools;
-
-#[derive(Clone)]
-struct MyMcpServer {
-    tool_router: ToolRouter<Self>,
-}
+use rmcp::{model::JsonObject, transport, ServiceExt};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
-#[derive(Deserialize, JsonSchema)]
-struct PathParams {
-    path: String,
+/// MCP server exposing workspace tools over stdio, streamable HTTP or REST.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Config file to use instead of `$MCP_SERVER_CONFIG`.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Serve the tools as an OpenAPI tool server on this address instead of MCP.
+    #[arg(long, value_name = "ADDR")]
+    openapi: Option<SocketAddr>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Deserialize, JsonSchema)]
-struct CmdParams {
-    cmd: String,
+#[derive(Subcommand)]
+enum Command {
+    /// Print every tool's definition, including its schemas, as JSON.
+    ListTools,
+    /// Call a tool through the same policy, redaction and audit log as a client would and print the result.
+    Call {
+        tool: String,
+        /// Arguments as a JSON object.
+        #[arg(long, value_name = "ARGS", default_value = "{}")]
+        json: String,
+    },
+    /// Check the config file without starting the server.
+    ValidateConfig,
 }
 
-#[tool_router]
-impl MyMcpServer {
-    fn new() -> Self {
-        Self {
-            tool_router: Self::tool_router(),
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = match &cli.config {
+        Some(path) => Config::from_file(path)?,
+        None => Config::load()?,
+    };
+    match cli.command {
+        Some(Command::ValidateConfig) => {
+            config.validate()?;
+            println!("Config is valid");
         }
-    }
-
-    #[tool(description = "Reads content from the local workspace.")]
-    async fn read_file(&self, params: Parameters<PathParams>) -> Result<CallToolResult, McpError> {
-        match tools::read_file(params.0.path) {
-            Ok(content) => Ok(CallToolResult::success(vec![Content::text(content)])),
-            Err(e) => Err(McpError {
-                code: rmcp::model::ErrorCode::INTERNAL_ERROR,
-                message: e.to_string().into(),
-                data: None,
-            }),
+        Some(Command::ListTools) => {
+            let tools = start(&config).await?.tools_for(None).await;
+            println!("{}", serde_json::to_string_pretty(&tools)?);
         }
-    }
-
-    #[tool(description = "Lists files in a directory.")]
-    async fn list_directory(&self, params: Parameters<PathParams>) -> Result<CallToolResult, McpError> {
-        match tools::list_directory(params.0.path) {
-            Ok(entries) => {
-                let text = entries.join("\n");
-                Ok(CallToolResult::success(vec![Content::text(text)]))
+        Some(Command::Call { tool, json }) => {
+            let arguments: JsonObject =
+                serde_json::from_str(&json).map_err(|e| anyhow!("Invalid --json arguments: {}", e))?;
+            let result = start(&config)
+                .await?
+                .call(&tool, Some(arguments), None, None)
+                .await
+                .map_err(|e| anyhow!("{}", e.message))?;
+            println!("{}", serde_json::to_string_pretty(&result)?);
+            if result.is_error == Some(true) {
+                bail!("Tool '{}' reported an error", tool);
             }
-            Err(e) => Err(McpError {
-                code: rmcp::model::ErrorCode::INTERNAL_ERROR,
-                message: e.to_string().into(),
-                data: None,
-            }),
-        }
-    }
-
-    #[tool(description = "Executes a safe terminal command.")]
-    async fn shell_command(&self, params: Parameters<CmdParams>) -> Result<CallToolResult, McpError> {
-        match tools::shell_command(params.0.cmd) {
-            Ok(output) => Ok(CallToolResult::success(vec![Content::text(output)])),
-            Err(e) => Err(McpError {
-                code: rmcp::model::ErrorCode::INTERNAL_ERROR,
-                message: e.to_string().into(),
-                data: None,
-            }),
         }
-    }
-}
-
-impl ServerHandler for MyMcpServer {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            server_info: Implementation {
-                name: "mcp-server-rust".into(),
-                version: "0.1.0".into(),
-                ..Default::default()
-            },
-            ..Default::default()
+        None => {
+            let server = start(&config).await?;
+            let path = cli
+                .config
+                .clone()
+                .or_else(|| std::env::var_os(CONFIG_ENV).map(PathBuf::from));
+            reload::watch(server.clone(), path)?;
+            if config.metrics.listen.is_some() {
+                let metrics_config = config.metrics.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = metrics::serve(&metrics_config).await {
+                        tracing::error!(error = %e, "Metrics listener failed");
+                    }
+                });
+            }
+            if let Some(addr) = cli.openapi {
+                openapi::serve(server, addr, &config.http).await?;
+            } else if config.http.listen.is_some() {
+                let authenticator = server.authenticator();
+                http::serve(move || server.for_session(), &config.http, authenticator).await?;
+            } else {
+                let transport = transport::stdio();
+                server.serve(transport).await?.waiting().await?;
+            }
         }
     }
-
-    async fn call_tool(
-        &self,
-        request: CallToolRequestParam,
-        context: RequestContext<RoleServer>,
-    ) -> Result<CallToolResult, McpError> {
-        let tool_call_context = ToolCallContext::new(self, request, context);
-        self.tool_router.call(tool_call_context).await
-    }
-
-    async fn list_tools(
-        &self,
-        request: Option<PaginatedRequestParam>,
-        _context: RequestContext<RoleServer>,
-    ) -> Result<ListToolsResult, McpError> {
-        let _ = request; // Consume unused
-        let tools = self.tool_router.list_all();
-        Ok(ListToolsResult {
-            tools,
-            next_cursor: None,
-            meta: None,
-        })
-    }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let transport = transport::stdio();
-
-    let server = MyMcpServer::new();
-    let _ = server.serve(transport).await?;
-    Ok(())
+async fn start(config: &Config) -> Result<McpServer> {
+    let logs = logging::init(&config.logging)?;
+    McpServer::new(config, logs).await
 }
@@ -0,0 +1,164 @@
+//! Prometheus metrics for tool calls, policy decisions, sessions and shell
+//! processes, served at `/metrics` on `metrics.listen`.
+//!
+//! The metrics are process-wide so every part of the server can record into
+//! them without threading a handle through; they are only exported when a
+//! listener is configured.
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use crate::audit::Outcome;
+use crate::auth::constant_time_eq;
+use crate::config::MetricsConfig;
+
+pub struct Metrics {
+    registry: Registry,
+    tool_calls: IntCounterVec,
+    tool_seconds: HistogramVec,
+    result_bytes: IntCounterVec,
+    rejections: IntCounterVec,
+    sessions: IntGauge,
+    processes: IntGauge,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// The server's metrics.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("mcp".into()), None).expect("valid metric prefix");
+        let tool_calls = IntCounterVec::new(
+            Opts::new("tool_calls_total", "Tool calls by tool and outcome (ok, error or denied)."),
+            &["tool", "outcome"],
+        )
+        .unwrap();
+        let tool_seconds = HistogramVec::new(
+            HistogramOpts::new("tool_call_duration_seconds", "Time tools took to run, excluding policy checks.")
+                .buckets(exponential_buckets(0.001, 4.0, 10).unwrap()),
+            &["tool"],
+        )
+        .unwrap();
+        let result_bytes = IntCounterVec::new(
+            Opts::new("tool_result_bytes_total", "Bytes of text content returned by tools."),
+            &["tool"],
+        )
+        .unwrap();
+        let rejections = IntCounterVec::new(
+            Opts::new("policy_rejections_total", "Requests and commands refused by policy, by reason."),
+            &["reason"],
+        )
+        .unwrap();
+        let sessions = IntGauge::new("active_sessions", "Open network sessions.").unwrap();
+        let processes = IntGauge::new("running_processes", "Commands started by shell tools that are still running.").unwrap();
+
+        registry.register(Box::new(tool_calls.clone())).unwrap();
+        registry.register(Box::new(tool_seconds.clone())).unwrap();
+        registry.register(Box::new(result_bytes.clone())).unwrap();
+        registry.register(Box::new(rejections.clone())).unwrap();
+        registry.register(Box::new(sessions.clone())).unwrap();
+        registry.register(Box::new(processes.clone())).unwrap();
+        Self {
+            registry,
+            tool_calls,
+            tool_seconds,
+            result_bytes,
+            rejections,
+            sessions,
+            processes,
+        }
+    }
+
+    /// Records a call that ran, or was denied when `elapsed` is `None`.
+    pub fn tool_call(&self, tool: &str, outcome: Outcome, elapsed: Option<Duration>, bytes: usize) {
+        let outcome = match outcome {
+            Outcome::Ok => "ok",
+            Outcome::Error => "error",
+            Outcome::Denied => "denied",
+        };
+        self.tool_calls.with_label_values(&[tool, outcome]).inc();
+        if let Some(elapsed) = elapsed {
+            self.tool_seconds.with_label_values(&[tool]).observe(elapsed.as_secs_f64());
+            self.result_bytes.with_label_values(&[tool]).inc_by(bytes as u64);
+        }
+    }
+
+    /// Counts a refusal, e.g. `confirmation` or `command_not_allowed`.
+    pub fn rejection(&self, reason: &str) {
+        self.rejections.with_label_values(&[reason]).inc();
+    }
+
+    pub fn session_started(&self) {
+        self.sessions.inc();
+    }
+
+    pub fn session_ended(&self) {
+        self.sessions.dec();
+    }
+
+    /// Counts a running process until the returned guard is dropped.
+    pub fn process(&self) -> ProcessGuard {
+        self.processes.inc();
+        ProcessGuard
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding cannot fail");
+        String::from_utf8(buffer).expect("text encoding is UTF-8")
+    }
+}
+
+pub struct ProcessGuard;
+
+impl Drop for ProcessGuard {
+    fn drop(&mut self) {
+        METRICS.processes.dec();
+    }
+}
+
+/// Serves `GET /metrics` on `config.listen`, requiring `config.token` as a bearer token when set.
+pub async fn serve(config: &MetricsConfig) -> Result<()> {
+    let addr = config
+        .listen
+        .ok_or_else(|| anyhow!("No metrics listen address configured"))?;
+    let app = Router::new()
+        .route("/metrics", get(scrape))
+        .with_state(Arc::new(config.token.clone()));
+    crate::http::listen(app, addr, None, "metrics").await
+}
+
+async fn scrape(State(token): State<Arc<Option<String>>>, headers: HeaderMap) -> impl IntoResponse {
+    if let Some(token) = token.as_deref() {
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if !presented.is_some_and(|presented| constant_time_eq(presented.as_bytes(), token.as_bytes())) {
+            return (StatusCode::UNAUTHORIZED, [(header::CONTENT_TYPE, "text/plain")], "Invalid bearer token".to_string());
+        }
+    }
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics().render(),
+    )
+}
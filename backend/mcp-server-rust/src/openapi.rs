@@ -0,0 +1,394 @@
+//! OpenAPI tool server mode, for clients such as Open WebUI that speak REST
+//! rather than MCP.
+//!
+//! Every tool the caller may use becomes `POST /tools/<name>`, taking the
+//! arguments object as its JSON body. `GET /openapi.json` describes them as an
+//! OpenAPI 3.1 document built from the tools' JSON Schemas. Calls go through
+//! the same policy as MCP calls; there is no client to confirm with, so
+//! confirmation rules resolve to `confirm.non_interactive`.
+//!
+//! Each token gets a session of its own, as an MCP client would: its result
+//! pages, shell sessions, checkpoints and call budget are not shared with
+//! other callers.
+
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::{Path, Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use rmcp::{
+    model::{CallToolResult, ErrorCode, JsonObject, RawContent, Tool},
+    ErrorData as McpError,
+};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::auth::{Authenticator, Identity};
+use crate::config::HttpConfig;
+use crate::http;
+use crate::server::McpServer;
+
+struct RestState {
+    server: McpServer,
+    authenticator: Arc<Authenticator>,
+    /// One session per token name; unauthenticated callers share the `None` one.
+    sessions: Mutex<HashMap<Option<String>, McpServer>>,
+}
+
+impl RestState {
+    /// The session of the caller, started on its first call.
+    fn session(&self, identity: Option<&Identity>) -> McpServer {
+        self.sessions
+            .lock()
+            .unwrap()
+            .entry(identity.map(|identity| identity.name.clone()))
+            .or_insert_with(|| self.server.for_session())
+            .clone()
+    }
+}
+
+/// Serves the tools of `server` as an OpenAPI tool server on `addr`, with the
+/// TLS settings of `http` and the server's bearer tokens.
+pub async fn serve(server: McpServer, addr: SocketAddr, http: &HttpConfig) -> Result<()> {
+    let authenticator = server.authenticator();
+    let state = Arc::new(RestState {
+        server,
+        authenticator: authenticator.clone(),
+        sessions: Mutex::new(HashMap::new()),
+    });
+    let app = Router::new()
+        .route("/openapi.json", get(openapi))
+        .route("/tools/{name}", post(call))
+        .with_state(state);
+    let app = http::authenticated(app, addr, http.tls.as_ref(), authenticator)?.layer(middleware::from_fn(cors));
+    http::listen(app, addr, http.tls.as_ref(), "OpenAPI").await
+}
+
+async fn openapi(State(state): State<Arc<RestState>>, identity: Option<Extension<Identity>>) -> Json<Value> {
+    let tools = state.server.tools_for(identity.as_deref()).await;
+    Json(document(&tools, !state.authenticator.is_empty()))
+}
+
+async fn call(
+    State(state): State<Arc<RestState>>,
+    Path(name): Path<String>,
+    identity: Option<Extension<Identity>>,
+    body: Bytes,
+) -> Response {
+    let arguments = if body.iter().all(u8::is_ascii_whitespace) {
+        JsonObject::new()
+    } else {
+        match serde_json::from_slice::<JsonObject>(&body) {
+            Ok(arguments) => arguments,
+            Err(e) => return failure(StatusCode::BAD_REQUEST, format!("Invalid JSON body: {}", e)),
+        }
+    };
+    if state.server.find_tool(&name).await.is_none() {
+        return failure(StatusCode::NOT_FOUND, format!("Unknown tool '{}'", name));
+    }
+    let session = state.session(identity.as_deref());
+    match session.call(&name, Some(arguments), identity.as_deref(), None).await {
+        Ok(result) if result.is_error == Some(true) => failure(StatusCode::INTERNAL_SERVER_ERROR, text(&result)),
+        Ok(result) => match result.structured_content {
+            Some(value) => Json(value).into_response(),
+            None => Json(Value::String(text(&result))).into_response(),
+        },
+        Err(e) => failure(status(&e), e.message.into_owned()),
+    }
+}
+
+fn status(error: &McpError) -> StatusCode {
+    match error.code {
+        ErrorCode::INVALID_PARAMS => StatusCode::BAD_REQUEST,
+        // Denied by token permissions or confirmation rules.
+        ErrorCode::INVALID_REQUEST => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn failure(status: StatusCode, detail: String) -> Response {
+    (status, Json(json!({ "detail": detail }))).into_response()
+}
+
+/// The text content of a result, one item per line.
+fn text(result: &CallToolResult) -> String {
+    result
+        .content
+        .iter()
+        .filter_map(|content| match &content.raw {
+            RawContent::Text(text) => Some(text.text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Lets browser-based clients call the server. Preflight requests carry no
+/// credentials, so they are answered before authentication.
+async fn cors(request: Request, next: Next) -> Response {
+    let mut response = if request.method() == Method::OPTIONS {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST"));
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("authorization, content-type"),
+        );
+        response
+    } else {
+        next.run(request).await
+    };
+    response
+        .headers_mut()
+        .insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    response
+}
+
+/// The OpenAPI 3.1 document with one `POST /tools/<name>` operation per tool.
+pub fn document(tools: &[Tool], bearer: bool) -> Value {
+    let mut schemas = Map::new();
+    schemas.insert(
+        "Error".into(),
+        json!({
+            "type": "object",
+            "properties": { "detail": { "type": "string" } },
+            "required": ["detail"],
+        }),
+    );
+
+    let mut paths = Map::new();
+    for tool in tools {
+        let input = hoist(&tool.input_schema, &tool.name, &mut schemas);
+        let output = match &tool.output_schema {
+            Some(schema) => hoist(schema, &tool.name, &mut schemas),
+            None => json!({ "type": "string" }),
+        };
+        let summary = tool
+            .title
+            .clone()
+            .or_else(|| tool.annotations.as_ref().and_then(|a| a.title.clone()))
+            .unwrap_or_else(|| tool.name.to_string());
+        paths.insert(
+            format!("/tools/{}", tool.name),
+            json!({
+                "post": {
+                    "operationId": tool.name,
+                    "summary": summary,
+                    "description": tool.description.as_deref().unwrap_or_default(),
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": input } },
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "The tool's structured result, or its text when it has none.",
+                            "content": { "application/json": { "schema": output } },
+                        },
+                        "default": {
+                            "description": "The call was invalid, denied or failed.",
+                            "content": {
+                                "application/json": { "schema": { "$ref": "#/components/schemas/Error" } },
+                            },
+                        },
+                    },
+                },
+            }),
+        );
+    }
+
+    let mut document = json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Workspace tools served over REST.",
+        },
+        "paths": paths,
+        "components": { "schemas": schemas },
+    });
+    if bearer {
+        document["components"]["securitySchemes"] = json!({ "bearer": { "type": "http", "scheme": "bearer" } });
+        document["security"] = json!([{ "bearer": [] }]);
+    }
+    document
+}
+
+/// Moves a schema's `$defs` into `components/schemas` and points its
+/// references there. A definition that clashes with a different one already
+/// registered is prefixed with the tool name.
+fn hoist(schema: &JsonObject, tool: &str, components: &mut Map<String, Value>) -> Value {
+    let mut schema = schema.clone();
+    schema.remove("$schema");
+    let defs = match schema.remove("$defs").or_else(|| schema.remove("definitions")) {
+        Some(Value::Object(defs)) => defs,
+        _ => Map::new(),
+    };
+
+    let rename = |prefixed: bool| -> HashMap<String, String> {
+        defs.keys()
+            .map(|name| {
+                let target = if prefixed {
+                    format!("{}.{}", tool, name)
+                } else {
+                    name.clone()
+                };
+                (name.clone(), target)
+            })
+            .collect()
+    };
+    let rewritten = |names: &HashMap<String, String>| -> Vec<(String, Value)> {
+        defs.iter()
+            .map(|(name, def)| {
+                let mut def = def.clone();
+                rewrite_refs(&mut def, names);
+                (names[name].clone(), def)
+            })
+            .collect()
+    };
+
+    let mut names = rename(false);
+    let mut hoisted = rewritten(&names);
+    let clashes = hoisted
+        .iter()
+        .any(|(name, def)| components.get(name).is_some_and(|existing| existing != def));
+    if clashes {
+        names = rename(true);
+        hoisted = rewritten(&names);
+    }
+    components.extend(hoisted);
+
+    let mut schema = Value::Object(schema);
+    rewrite_refs(&mut schema, &names);
+    schema
+}
+
+/// Points `$defs` references at `components/schemas` and turns the OpenAPI 3.0
+/// `nullable` keyword into the type list 3.1 expects.
+fn rewrite_refs(value: &mut Value, names: &HashMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            if map.get("nullable") == Some(&Value::Bool(true)) {
+                if let Some(Value::String(kind)) = map.get("type").cloned() {
+                    map.remove("nullable");
+                    map.insert("type".into(), json!([kind, "null"]));
+                }
+            }
+            if let Some(Value::String(reference)) = map.get_mut("$ref") {
+                let name = reference
+                    .strip_prefix("#/$defs/")
+                    .or_else(|| reference.strip_prefix("#/definitions/"));
+                if let Some(target) = name.and_then(|name| names.get(name)) {
+                    *reference = format!("#/components/schemas/{}", target);
+                }
+            }
+            map.values_mut().for_each(|item| rewrite_refs(item, names));
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| rewrite_refs(item, names)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str, schema: Value) -> Tool {
+        let Value::Object(schema) = schema else {
+            panic!("schema must be an object");
+        };
+        Tool::new(name.to_string(), format!("The {} tool", name), Arc::new(schema))
+    }
+
+    fn range(kind: &str) -> Value {
+        json!({ "type": "object", "properties": { "start": { "type": kind } } })
+    }
+
+    fn input<'a>(document: &'a Value, tool: &str) -> &'a Value {
+        &document["paths"][format!("/tools/{}", tool)]["post"]["requestBody"]["content"]["application/json"]["schema"]
+    }
+
+    #[test]
+    fn definitions_are_hoisted_into_components() {
+        let edit = tool(
+            "edit",
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "range": { "$ref": "#/$defs/Range" },
+                    "ranges": { "type": "array", "items": { "$ref": "#/$defs/Range" } },
+                },
+                "$defs": { "Range": range("integer") },
+            }),
+        );
+        let legacy = tool(
+            "legacy",
+            json!({ "properties": { "span": { "$ref": "#/definitions/Span" } }, "definitions": { "Span": range("integer") } }),
+        );
+        let document = document(&[edit, legacy], false);
+
+        let schema = input(&document, "edit");
+        assert!(schema.get("$schema").is_none() && schema.get("$defs").is_none());
+        assert_eq!(schema["properties"]["range"]["$ref"], "#/components/schemas/Range");
+        assert_eq!(schema["properties"]["ranges"]["items"]["$ref"], "#/components/schemas/Range");
+        assert_eq!(input(&document, "legacy")["properties"]["span"]["$ref"], "#/components/schemas/Span");
+        let schemas = &document["components"]["schemas"];
+        assert_eq!(schemas["Range"], range("integer"));
+        assert_eq!(schemas["Span"], range("integer"));
+        assert!(schemas["Error"].is_object());
+        assert!(document.get("security").is_none());
+    }
+
+    #[test]
+    fn clashing_definitions_are_prefixed_with_the_tool_name() {
+        let schema = |kind: &str| {
+            json!({
+                "properties": { "range": { "$ref": "#/$defs/Range" } },
+                "$defs": {
+                    "Range": range(kind),
+                    "Ranges": { "type": "array", "items": { "$ref": "#/$defs/Range" } },
+                },
+            })
+        };
+        let tools = [tool("edit", schema("integer")), tool("copy", schema("integer")), tool("search", schema("string"))];
+        let document = document(&tools, true);
+
+        // An identical definition is shared; a different one is kept apart.
+        assert_eq!(input(&document, "copy")["properties"]["range"]["$ref"], "#/components/schemas/Range");
+        assert_eq!(input(&document, "search")["properties"]["range"]["$ref"], "#/components/schemas/search.Range");
+        let schemas = &document["components"]["schemas"];
+        assert_eq!(schemas["Range"], range("integer"));
+        assert_eq!(schemas["search.Range"], range("string"));
+        assert_eq!(schemas["search.Ranges"]["items"]["$ref"], "#/components/schemas/search.Range");
+        assert_eq!(schemas["Ranges"]["items"]["$ref"], "#/components/schemas/Range");
+        assert_eq!(document["security"], json!([{ "bearer": [] }]));
+    }
+
+    #[test]
+    fn nullable_becomes_a_type_list() {
+        let notes = tool(
+            "notes",
+            json!({
+                "properties": {
+                    "title": { "type": "string", "nullable": true },
+                    "tags": { "type": "array", "items": { "type": "integer", "nullable": true } },
+                    "body": { "type": "string", "nullable": false },
+                },
+            }),
+        );
+        let document = document(&[notes], false);
+        let properties = &input(&document, "notes")["properties"];
+        assert_eq!(properties["title"], json!({ "type": ["string", "null"] }));
+        assert_eq!(properties["tags"]["items"], json!({ "type": ["integer", "null"] }));
+        assert_eq!(properties["body"], json!({ "type": "string", "nullable": false }));
+        let output = &document["paths"]["/tools/notes"]["post"]["responses"]["200"]["content"]["application/json"]["schema"];
+        assert_eq!(output, &json!({ "type": "string" }));
+    }
+}
@@ -0,0 +1,366 @@
+//! A client for the parts of the Open WebUI REST API the `openwebui_*` tools
+//! use: knowledge collections and retrieval over them, notes and memories.
+//!
+//! Requests carry the configured API key, so the tools see and change what
+//! that account can. Responses are read field by field, ignoring what the
+//! tools don't use and defaulting what is missing or null, to tolerate
+//! differences between Open WebUI versions.
+
+use anyhow::{anyhow, bail, Result};
+use reqwest::{Client, Method, Url};
+use rmcp::schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::config::OpenWebUiConfig;
+
+pub struct OpenWebUi {
+    client: Client,
+    base: Url,
+    api_key: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Collection {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    /// The collection's files; only filled in when reading one collection.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<KnowledgeFile>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct KnowledgeFile {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RetrievalHit {
+    pub content: String,
+    /// The file or URL the passage came from, when Open WebUI recorded one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Relevance as Open WebUI reports it: a distance or a score depending on its search settings.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Note {
+    pub id: String,
+    pub title: String,
+    /// Markdown; left out when listing notes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Memory {
+    pub id: String,
+    pub content: String,
+}
+
+impl OpenWebUi {
+    pub fn new(config: &OpenWebUiConfig) -> Result<Self> {
+        let url = config.url.as_deref().ok_or_else(|| anyhow!("`openwebui.url` is not set"))?;
+        let mut base = Url::parse(url).map_err(|e| anyhow!("Invalid `openwebui.url` '{}': {}", url, e))?;
+        if !matches!(base.scheme(), "http" | "https") {
+            bail!("`openwebui.url` must be an http or https URL");
+        }
+        // Joining keeps a path prefix only when it ends in a slash.
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        let api_key = config
+            .api_key
+            .clone()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| anyhow!("`openwebui.api_key` is required when `openwebui.url` is set"))?;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| anyhow!("Failed to create the Open WebUI client: {}", e))?;
+        Ok(Self { client, base, api_key })
+    }
+
+    pub async fn collections(&self) -> Result<Vec<Collection>> {
+        let collections = self.request(Method::GET, "api/v1/knowledge/", None).await?;
+        Ok(items(&collections)
+            .iter()
+            .map(|collection| Collection {
+                files: Vec::new(),
+                ..parse_collection(collection)
+            })
+            .collect())
+    }
+
+    pub async fn collection(&self, id: &str) -> Result<Collection> {
+        let collection = self.request(Method::GET, &format!("api/v1/knowledge/{}", segment(id)?), None).await?;
+        Ok(parse_collection(&collection))
+    }
+
+    /// The extracted text of a file in a collection.
+    pub async fn file_content(&self, id: &str) -> Result<String> {
+        let value = self
+            .request(Method::GET, &format!("api/v1/files/{}/data/content", segment(id)?), None)
+            .await?;
+        Ok(value["content"].as_str().unwrap_or_default().to_string())
+    }
+
+    /// The passages of a collection that best match `query`, best first.
+    pub async fn search(&self, collection: &str, query: &str, limit: usize) -> Result<Vec<RetrievalHit>> {
+        let body = json!({ "collection_names": [collection], "query": query, "k": limit });
+        let value = self
+            .request(Method::POST, "api/v1/retrieval/query/collection", Some(body))
+            .await?;
+        // Results come as parallel lists, one per queried collection.
+        let documents = value["documents"][0].as_array().cloned().unwrap_or_default();
+        let metadatas = &value["metadatas"][0];
+        let distances = &value["distances"][0];
+        Ok(documents
+            .into_iter()
+            .enumerate()
+            .map(|(i, document)| {
+                let metadata = &metadatas[i];
+                RetrievalHit {
+                    content: document.as_str().unwrap_or_default().to_string(),
+                    source: metadata["name"]
+                        .as_str()
+                        .or(metadata["source"].as_str())
+                        .map(str::to_string),
+                    score: distances[i].as_f64(),
+                }
+            })
+            .collect())
+    }
+
+    pub async fn notes(&self) -> Result<Vec<Note>> {
+        let notes = self.request(Method::GET, "api/v1/notes/", None).await?;
+        Ok(items(&notes)
+            .iter()
+            .map(|note| Note {
+                content: None,
+                ..parse_note(note)
+            })
+            .collect())
+    }
+
+    pub async fn note(&self, id: &str) -> Result<Note> {
+        let note = self.request(Method::GET, &format!("api/v1/notes/{}", segment(id)?), None).await?;
+        Ok(parse_note(&note))
+    }
+
+    pub async fn create_note(&self, title: &str, content: &str) -> Result<Note> {
+        let body = json!({ "title": title, "data": { "content": { "md": content } } });
+        let note = self.request(Method::POST, "api/v1/notes/create", Some(body)).await?;
+        Ok(parse_note(&note))
+    }
+
+    pub async fn add_memory(&self, content: &str) -> Result<Memory> {
+        let body = json!({ "content": content });
+        let memory = self.request(Method::POST, "api/v1/memories/add", Some(body)).await?;
+        Ok(Memory {
+            id: string(&memory["id"]),
+            content: string(&memory["content"]),
+        })
+    }
+
+    async fn request(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value> {
+        let url = self.base.join(path)?;
+        let mut request = self.client.request(method, url).bearer_auth(&self.api_key);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow!("Open WebUI request to /{} failed: {}", path, e))?;
+        let status = response.status();
+        if !status.is_success() {
+            // Open WebUI explains errors in a `detail` field.
+            let text = response.text().await.unwrap_or_default();
+            let detail = serde_json::from_str::<Value>(&text)
+                .ok()
+                .and_then(|value| value["detail"].as_str().map(str::to_string))
+                .unwrap_or(text);
+            bail!("Open WebUI returned {} for /{}: {}", status, path, detail.trim());
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Unexpected response from Open WebUI for /{}: {}", path, e))
+    }
+}
+
+fn parse_collection(value: &Value) -> Collection {
+    Collection {
+        id: string(&value["id"]),
+        name: string(&value["name"]),
+        description: string(&value["description"]),
+        // Files carry their name in `meta`, or at the top level in older versions.
+        files: value["files"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|file| KnowledgeFile {
+                id: string(&file["id"]),
+                name: file["meta"]["name"]
+                    .as_str()
+                    .or(file["filename"].as_str())
+                    .map(str::to_string),
+            })
+            .collect(),
+    }
+}
+
+fn parse_note(value: &Value) -> Note {
+    Note {
+        id: string(&value["id"]),
+        title: string(&value["title"]),
+        content: value["data"]["content"]["md"].as_str().map(str::to_string),
+    }
+}
+
+/// A list response, bare or in the `items` of a page.
+fn items(value: &Value) -> &[Value] {
+    value
+        .as_array()
+        .or(value["items"].as_array())
+        .map_or(&[], Vec::as_slice)
+}
+
+fn string(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+/// Checks an id before it goes into a URL path.
+fn segment(id: &str) -> Result<&str> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        bail!("Invalid Open WebUI id '{}'", id);
+    }
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        extract::Path,
+        http::{HeaderMap, StatusCode},
+        routing::{get, post},
+        Json, Router,
+    };
+
+    /// Serves a fake Open WebUI under `/owui` and returns a client for it.
+    async fn client() -> OpenWebUi {
+        fn authorized(headers: &HeaderMap) -> bool {
+            headers.get("authorization").is_some_and(|value| value == "Bearer k1")
+        }
+        let app = Router::new()
+            .route(
+                "/owui/api/v1/retrieval/query/collection",
+                post(|headers: HeaderMap, Json(body): Json<Value>| async move {
+                    assert!(authorized(&headers));
+                    assert_eq!(body["collection_names"], json!(["c1"]));
+                    assert_eq!(body["k"], 2);
+                    Json(json!({
+                        "documents": [["first passage", "second passage"]],
+                        "metadatas": [[{"name": "guide.md"}, {"source": "https://example.com"}]],
+                        "distances": [[0.1, 0.4]],
+                    }))
+                }),
+            )
+            .route(
+                "/owui/api/v1/knowledge/{id}",
+                get(|Path(id): Path<String>| async move {
+                    Json(json!({
+                        "id": id,
+                        "name": "Docs",
+                        "description": null,
+                        "files": [
+                            {"id": "f1", "meta": {"name": "guide.md"}},
+                            {"id": "f2", "filename": "old.txt"},
+                        ],
+                    }))
+                }),
+            )
+            .route(
+                "/owui/api/v1/notes/create",
+                post(|Json(body): Json<Value>| async move {
+                    Json(json!({"id": "n1", "title": body["title"], "data": body["data"]}))
+                }),
+            )
+            .route(
+                "/owui/api/v1/notes/{id}",
+                get(|| async { (StatusCode::NOT_FOUND, Json(json!({"detail": "Note not found"}))) }),
+            )
+            .route(
+                "/owui/api/v1/memories/add",
+                post(|Json(body): Json<Value>| async move { Json(json!({"id": "m1", "content": body["content"]})) }),
+            )
+            .route(
+                "/owui/api/v1/notes/",
+                get(|| async { (StatusCode::INTERNAL_SERVER_ERROR, "database is locked") }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        OpenWebUi::new(&OpenWebUiConfig {
+            url: Some(format!("http://{}/owui", address)),
+            api_key: Some("k1".into()),
+            ..OpenWebUiConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn search_pairs_documents_with_metadata_and_distances() {
+        let hits = client().await.search("c1", "setup", 2).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].content, "first passage");
+        assert_eq!(hits[0].source.as_deref(), Some("guide.md"));
+        assert_eq!(hits[0].score, Some(0.1));
+        assert_eq!(hits[1].source.as_deref(), Some("https://example.com"));
+    }
+
+    #[tokio::test]
+    async fn collection_reads_file_names_from_either_place() {
+        let collection = client().await.collection("c1").await.unwrap();
+        assert_eq!(collection.id, "c1");
+        assert_eq!(collection.description, "");
+        let names: Vec<_> = collection.files.iter().map(|file| file.name.as_deref()).collect();
+        assert_eq!(names, [Some("guide.md"), Some("old.txt")]);
+    }
+
+    #[tokio::test]
+    async fn create_note_and_add_memory_return_what_was_stored() {
+        let client = client().await;
+        let note = client.create_note("Plan", "# Steps").await.unwrap();
+        assert_eq!((note.id.as_str(), note.title.as_str()), ("n1", "Plan"));
+        assert_eq!(note.content.as_deref(), Some("# Steps"));
+        let memory = client.add_memory("Prefers tabs").await.unwrap();
+        assert_eq!((memory.id.as_str(), memory.content.as_str()), ("m1", "Prefers tabs"));
+    }
+
+    #[tokio::test]
+    async fn errors_carry_the_detail_or_the_body() {
+        let client = client().await;
+        let Err(error) = client.note("n9").await else {
+            panic!("a missing note was found");
+        };
+        assert_eq!(error.to_string(), "Open WebUI returned 404 Not Found for /api/v1/notes/n9: Note not found");
+        let Err(error) = client.notes().await else {
+            panic!("a failed listing succeeded");
+        };
+        assert!(error.to_string().ends_with(": database is locked"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn ids_are_checked_before_they_reach_a_url() {
+        assert!(client().await.note("../admin").await.is_err());
+    }
+}
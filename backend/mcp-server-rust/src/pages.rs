@@ -0,0 +1,290 @@
+//! Server-side storage for tool output too large to return in one result.
+//!
+//! The first page goes back with the tool result; the rest stays here under a
+//! random handle until `read_more` pages through it or it expires.
+
+use anyhow::{anyhow, Result};
+use rmcp::schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::PaginationConfig;
+
+#[derive(Serialize, JsonSchema)]
+pub struct Page {
+    pub text: String,
+    /// Pass to `read_more` for the next page; missing on the last one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+impl Page {
+    /// The page followed by a note telling the model how to continue.
+    pub fn render(&self) -> String {
+        format!("{}{}", self.text, continuation(&self.next_cursor))
+    }
+}
+
+/// The note appended to a truncated result, or nothing on the last page.
+pub fn continuation(next_cursor: &Option<String>) -> String {
+    match next_cursor {
+        Some(cursor) => format!(
+            "\n\n[Output truncated. Call `read_more` with cursor \"{}\" for the next page.]",
+            cursor
+        ),
+        None => String::new(),
+    }
+}
+
+struct StoredResult {
+    text: String,
+    expires: Instant,
+}
+
+pub struct ResultStore {
+    page_bytes: usize,
+    ttl: Duration,
+    max_stored_bytes: usize,
+    max_read_bytes: u64,
+    results: Mutex<HashMap<String, StoredResult>>,
+}
+
+impl ResultStore {
+    pub fn new(config: &PaginationConfig) -> Self {
+        Self {
+            page_bytes: config.page_bytes.max(1),
+            ttl: Duration::from_secs(config.ttl_secs),
+            max_stored_bytes: config.max_stored_bytes,
+            max_read_bytes: config.max_read_bytes,
+            results: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// An empty store with the same page size and expiry.
+    pub fn fresh(&self) -> Self {
+        Self {
+            page_bytes: self.page_bytes,
+            ttl: self.ttl,
+            max_stored_bytes: self.max_stored_bytes,
+            max_read_bytes: self.max_read_bytes,
+            results: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The largest file worth reading whole to page through.
+    pub fn max_read_bytes(&self) -> u64 {
+        self.max_read_bytes
+    }
+
+    /// Returns `text` whole when it fits in a page, otherwise stores it and returns the first page.
+    pub fn first_page(&self, text: String) -> Page {
+        if text.len() <= self.page_bytes {
+            return Page { text, next_cursor: None };
+        }
+        let handle = self.handle();
+        let page = self.page(&handle, &text, 0);
+        self.store(handle, text);
+        page
+    }
+
+    /// Joins `lines`, one per item, and returns as many whole lines as fit in
+    /// the first page (at least one), with how many that is. The rest is
+    /// stored for `read_more` like any other output.
+    pub fn first_page_of_lines(&self, lines: &[String]) -> (Page, usize) {
+        let mut end = 0;
+        let mut count = 0;
+        for line in lines {
+            if count > 0 && end + line.len() > self.page_bytes {
+                break;
+            }
+            end += line.len() + 1;
+            count += 1;
+        }
+        let text = lines.join("\n");
+        if count == lines.len() {
+            return (Page { text, next_cursor: None }, count);
+        }
+        let handle = self.handle();
+        let page = Page {
+            text: text[..end].to_string(),
+            next_cursor: Some(format!("{}:{}", handle, end)),
+        };
+        self.store(handle, text);
+        (page, count)
+    }
+
+    fn handle(&self) -> String {
+        format!("{:016x}", RandomState::new().hash_one(Instant::now()))
+    }
+
+    /// Keeps `text` under `handle`, dropping the least recently read results
+    /// while the store would go over its budget. A result larger than the
+    /// whole budget is still kept, on its own.
+    fn store(&self, handle: String, text: String) {
+        let mut results = self.results.lock().unwrap();
+        let now = Instant::now();
+        results.retain(|_, result| result.expires > now);
+        let mut total: usize = results.values().map(|result| result.text.len()).sum();
+        while total + text.len() > self.max_stored_bytes {
+            let Some(oldest) = results.iter().min_by_key(|(_, result)| result.expires).map(|(handle, _)| handle.clone())
+            else {
+                break;
+            };
+            total -= results.remove(&oldest).map_or(0, |result| result.text.len());
+        }
+        results.insert(
+            handle,
+            StoredResult {
+                text,
+                expires: now + self.ttl,
+            },
+        );
+    }
+
+    /// Returns the page a cursor from an earlier page points at.
+    pub fn read(&self, cursor: &str) -> Result<Page> {
+        let (handle, offset) = cursor
+            .split_once(':')
+            .and_then(|(handle, offset)| Some((handle, offset.parse::<usize>().ok()?)))
+            .ok_or_else(|| anyhow!("Invalid cursor '{}'", cursor))?;
+
+        let mut results = self.results.lock().unwrap();
+        let now = Instant::now();
+        results.retain(|_, result| result.expires > now);
+        let result = results
+            .get_mut(handle)
+            .ok_or_else(|| anyhow!("Cursor '{}' has expired or is unknown", cursor))?;
+        if offset > result.text.len() || !result.text.is_char_boundary(offset) {
+            return Err(anyhow!("Invalid cursor '{}'", cursor));
+        }
+        result.expires = now + self.ttl;
+        Ok(self.page(handle, &result.text, offset))
+    }
+
+    /// Cuts the page starting at `offset`, preferring to end it after a newline.
+    fn page(&self, handle: &str, text: &str, offset: usize) -> Page {
+        if text.len() - offset <= self.page_bytes {
+            return Page {
+                text: text[offset..].to_string(),
+                next_cursor: None,
+            };
+        }
+        let mut end = offset + self.page_bytes;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        if let Some(newline) = text[offset..end].rfind('\n') {
+            end = offset + newline + 1;
+        }
+        if end == offset {
+            // A single character wider than the page.
+            end = offset + text[offset..].chars().next().map_or(0, char::len_utf8);
+        }
+        Page {
+            text: text[offset..end].to_string(),
+            next_cursor: Some(format!("{}:{}", handle, end)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(page_bytes: usize) -> ResultStore {
+        ResultStore::new(&PaginationConfig {
+            page_bytes,
+            ..PaginationConfig::default()
+        })
+    }
+
+    /// Every page of `text`, following cursors to the end.
+    fn pages(store: &ResultStore, text: &str) -> Vec<String> {
+        let mut page = store.first_page(text.to_string());
+        let mut pages = vec![page.text];
+        while let Some(cursor) = page.next_cursor {
+            page = store.read(&cursor).unwrap();
+            pages.push(page.text);
+        }
+        pages
+    }
+
+    #[test]
+    fn short_text_is_returned_whole() {
+        let page = store(10).first_page("0123456789".into());
+        assert_eq!(page.text, "0123456789");
+        assert_eq!(page.next_cursor, None);
+        assert_eq!(page.render(), "0123456789");
+    }
+
+    #[test]
+    fn pages_end_after_a_newline_when_they_can() {
+        let store = store(10);
+        assert_eq!(pages(&store, "one\ntwo\nthree\nfour\n"), ["one\ntwo\n", "three\n", "four\n"]);
+        assert_eq!(pages(&store, "abcdefghijklmnopqrstuvwxy"), ["abcdefghij", "klmnopqrst", "uvwxy"]);
+    }
+
+    #[test]
+    fn line_pages_hold_whole_items() {
+        let store = store(12);
+        let lines: Vec<String> = ["alpha", "beta", "gamma", "a-very-long-name"].map(String::from).into();
+        let (page, count) = store.first_page_of_lines(&lines);
+        assert_eq!((page.text.as_str(), count), ("alpha\nbeta\n", 2));
+        let rest = store.read(&page.next_cursor.unwrap()).unwrap();
+        assert!(rest.text.starts_with("gamma\n"));
+
+        // An item wider than the page still gets one of its own.
+        let (page, count) = store.first_page_of_lines(&lines[3..]);
+        assert_eq!((page.text.as_str(), count, page.next_cursor), ("a-very-long-name", 1, None));
+        let (page, count) = store.first_page_of_lines(&[]);
+        assert_eq!((page.text.as_str(), count), ("", 0));
+    }
+
+    #[test]
+    fn pages_never_split_a_character() {
+        let store = store(5);
+        let text = "ééééé";
+        let pages = pages(&store, text);
+        assert!(pages.iter().all(|page| page.len() <= 5));
+        assert_eq!(pages.concat(), text);
+    }
+
+    #[test]
+    fn least_recently_read_results_make_room() {
+        let store = ResultStore::new(&PaginationConfig {
+            page_bytes: 4,
+            max_stored_bytes: 20,
+            ..PaginationConfig::default()
+        });
+        let first = store.first_page("aaaaaaaaaa".into()).next_cursor.unwrap();
+        let second = store.first_page("bbbbbbbbbb".into()).next_cursor.unwrap();
+        // Reading the first result makes the second the least recently read.
+        store.read(&first).unwrap();
+        let third = store.first_page("cccccccccc".into()).next_cursor.unwrap();
+
+        assert!(store.read(&first).is_ok());
+        assert!(store.read(&second).is_err());
+        assert!(store.read(&third).is_ok());
+
+        // Output over the whole budget is still stored, alone.
+        let large = store.first_page("d".repeat(30)).next_cursor.unwrap();
+        assert!(store.read(&large).is_ok());
+        assert!(store.read(&first).is_err());
+    }
+
+    #[test]
+    fn bad_cursors_are_rejected() {
+        let store = store(4);
+        let page = store.first_page("ééééé".into());
+        let handle = page.next_cursor.unwrap().split_once(':').unwrap().0.to_string();
+        assert!(store.read(&format!("{}:1", handle)).is_err(), "inside a character");
+        assert!(store.read(&format!("{}:99", handle)).is_err(), "past the end");
+        assert!(store.read("0000000000000000:0").is_err(), "unknown handle");
+        assert!(store.read("garbage").is_err());
+        assert!(store.fresh().read(&format!("{}:0", handle)).is_err(), "another session's store");
+    }
+}
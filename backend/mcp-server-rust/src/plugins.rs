@@ -0,0 +1,539 @@
+//! Sandboxed tools provided by WASI plugins.
+//!
+//! Every `*.wasm` file in the plugins directory is a WASI command, either a
+//! core module (preview 1) or a component (preview 2). The host talks to it
+//! through argv and stdio only:
+//!
+//! * `describe` prints `{"tools": [{"name", "title"?, "description", "input_schema",
+//!   "output_schema"?, "annotations"?}]}`
+//! * `call <tool>` reads the JSON arguments from stdin and prints the result,
+//!   which must be JSON when the tool declares an output schema
+//!
+//! A non-zero exit status marks the call as failed. Plugins get no environment
+//! and no network. During a call, the calling session's primary workspace
+//! root is their only preopened directory; `describe` gets none.
+
+use anyhow::{anyhow, Result};
+use rmcp::{
+    model::{CallToolResult, Content, JsonObject, Tool, ToolAnnotations},
+    ErrorData as McpError,
+};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use wasmtime::component::{Component, Linker as ComponentLinker, ResourceTable};
+use wasmtime::{Engine, Linker, Module, Store};
+use wasmtime_wasi::{
+    bindings::Command,
+    pipe::{MemoryInputPipe, MemoryOutputPipe},
+    preview1::{self, WasiP1Ctx},
+    DirPerms, FilePerms, I32Exit, IoView, WasiCtx, WasiCtxBuilder, WasiView,
+};
+
+use crate::config::PluginsConfig;
+use crate::tool::{self, ToolContext, ToolFuture};
+
+/// How often running plugins yield back to the runtime.
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+#[derive(Deserialize)]
+struct Manifest {
+    tools: Vec<ManifestTool>,
+}
+
+#[derive(Deserialize)]
+struct ManifestTool {
+    name: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    input_schema: Option<JsonObject>,
+    #[serde(default)]
+    output_schema: Option<JsonObject>,
+    /// MCP tool annotations (`readOnlyHint`, ...). Derived from the sandbox when missing.
+    #[serde(default)]
+    annotations: Option<ToolAnnotations>,
+}
+
+enum Artifact {
+    Module(Module),
+    Component(Component),
+}
+
+struct Plugin {
+    name: String,
+    artifact: Artifact,
+}
+
+struct PluginOutput {
+    success: bool,
+    stdout: String,
+    stderr: String,
+}
+
+struct ComponentState {
+    ctx: WasiCtx,
+    table: ResourceTable,
+}
+
+impl IoView for ComponentState {
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+}
+
+impl WasiView for ComponentState {
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.ctx
+    }
+}
+
+struct PluginHost {
+    engine: Engine,
+    module_linker: Linker<WasiP1Ctx>,
+    component_linker: ComponentLinker<ComponentState>,
+    config: PluginsConfig,
+}
+
+/// A tool declared by a plugin's manifest.
+pub struct PluginTool {
+    host: Arc<PluginHost>,
+    plugin: Arc<Plugin>,
+    definition: Tool,
+}
+
+/// Loads every plugin in `config.dir` and returns each tool it declares.
+pub async fn load_tools(config: &PluginsConfig) -> Result<Vec<PluginTool>> {
+    let Some(dir) = &config.dir else {
+        return Ok(Vec::new());
+    };
+    let host = Arc::new(PluginHost::new(config.clone())?);
+
+    let mut paths = fs::read_dir(dir)
+        .map_err(|e| {
+            anyhow!(
+                "Failed to read plugins directory '{}': {}",
+                dir.display(),
+                e
+            )
+        })?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut tools = Vec::new();
+    for path in paths {
+        let plugin = match host.load(&path) {
+            Ok(plugin) => Arc::new(plugin),
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "Skipping plugin");
+                continue;
+            }
+        };
+        let manifest = match host.describe(&plugin).await {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                tracing::warn!(plugin = %plugin.name, error = %e, "Skipping plugin");
+                continue;
+            }
+        };
+        tracing::info!(plugin = %plugin.name, tools = manifest.tools.len(), "Loaded plugin");
+        for tool in manifest.tools {
+            tools.push(PluginTool::new(host.clone(), plugin.clone(), tool));
+        }
+    }
+    Ok(tools)
+}
+
+impl PluginTool {
+    fn new(host: Arc<PluginHost>, plugin: Arc<Plugin>, tool: ManifestTool) -> Self {
+        let mut definition = Tool::new(
+            tool.name,
+            tool.description,
+            tool.input_schema.unwrap_or_else(|| {
+                serde_json::json!({"type": "object"})
+                    .as_object()
+                    .cloned()
+                    .unwrap_or_default()
+            }),
+        );
+        definition.title = tool.title;
+        let mut annotations = tool.annotations.unwrap_or_else(|| {
+            ToolAnnotations::new()
+                .read_only(!host.config.writable)
+                .open_world(false)
+        });
+        // A plugin that can write is never taken at its word that it doesn't,
+        // so it is still checkpointed and never auto-approved as read-only.
+        if host.config.writable {
+            annotations.read_only_hint = Some(false);
+        }
+        definition.annotations = Some(annotations);
+        definition.output_schema = tool.output_schema.map(Arc::new);
+        Self {
+            host,
+            plugin,
+            definition,
+        }
+    }
+}
+
+impl tool::Tool for PluginTool {
+    fn name(&self) -> &str {
+        &self.definition.name
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.definition.title.as_deref()
+    }
+
+    fn description(&self) -> &str {
+        self.definition.description.as_deref().unwrap_or_default()
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        self.definition.input_schema.clone()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        self.definition.output_schema.clone()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        self.definition.annotations.clone().unwrap_or_default()
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let stdin = serde_json::to_vec(&arguments).unwrap_or_default();
+            let structured = self.definition.output_schema.is_some();
+            let root = context.workspace.primary();
+            match self.host.run(&self.plugin, &["call", &self.definition.name], stdin, Some(&root)).await {
+                Ok(output) if output.success && structured => {
+                    match serde_json::from_str(&output.stdout) {
+                        Ok(value) => Ok(CallToolResult::structured(value)),
+                        Err(e) => Err(McpError {
+                            code: rmcp::model::ErrorCode::INTERNAL_ERROR,
+                            message: format!("Plugin returned invalid JSON: {}", e).into(),
+                            data: None,
+                        }),
+                    }
+                }
+                Ok(output) if output.success => {
+                    Ok(CallToolResult::success(vec![Content::text(output.stdout)]))
+                }
+                Ok(output) => {
+                    let message = if output.stderr.is_empty() {
+                        output.stdout
+                    } else {
+                        output.stderr
+                    };
+                    Ok(CallToolResult::error(vec![Content::text(message)]))
+                }
+                Err(e) => Err(tool::internal_error(e)),
+            }
+        })
+    }
+
+    fn definition(&self) -> Tool {
+        self.definition.clone()
+    }
+}
+
+impl PluginHost {
+    fn new(config: PluginsConfig) -> Result<Self> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config
+            .async_support(true)
+            .consume_fuel(true)
+            .epoch_interruption(true);
+        let engine = Engine::new(&engine_config)?;
+
+        // Ticks the epoch so long-running guests yield and can be timed out.
+        let ticker = engine.weak();
+        std::thread::spawn(move || {
+            while let Some(engine) = ticker.upgrade() {
+                engine.increment_epoch();
+                drop(engine);
+                std::thread::sleep(EPOCH_TICK);
+            }
+        });
+
+        let mut module_linker = Linker::new(&engine);
+        preview1::add_to_linker_async(&mut module_linker, |ctx| ctx)?;
+        let mut component_linker = ComponentLinker::new(&engine);
+        wasmtime_wasi::add_to_linker_async(&mut component_linker)?;
+
+        Ok(Self {
+            engine,
+            module_linker,
+            component_linker,
+            config,
+        })
+    }
+
+    fn load(&self, path: &Path) -> Result<Plugin> {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow!("Invalid plugin file name"))?;
+        let bytes = fs::read(path)?;
+        // Core modules are version 1; components use the layer-1 header.
+        let artifact = if bytes.get(4..8) == Some(&[1, 0, 0, 0]) {
+            Artifact::Module(Module::new(&self.engine, &bytes)?)
+        } else {
+            Artifact::Component(Component::new(&self.engine, &bytes)?)
+        };
+        Ok(Plugin { name, artifact })
+    }
+
+    async fn describe(&self, plugin: &Plugin) -> Result<Manifest> {
+        let output = self.run(plugin, &["describe"], Vec::new(), None).await?;
+        if !output.success {
+            return Err(anyhow!("describe failed: {}", output.stderr));
+        }
+        serde_json::from_str(&output.stdout).map_err(|e| anyhow!("Invalid manifest: {}", e))
+    }
+
+    /// The guest's context: `root`, if any, preopened at `.` and the pipes for its output.
+    fn wasi_builder(
+        &self,
+        plugin: &Plugin,
+        args: &[&str],
+        stdin: Vec<u8>,
+        root: Option<&Path>,
+    ) -> Result<(WasiCtxBuilder, MemoryOutputPipe, MemoryOutputPipe)> {
+        let stdout = MemoryOutputPipe::new(self.config.max_output_bytes);
+        let stderr = MemoryOutputPipe::new(self.config.max_output_bytes);
+        let (dir_perms, file_perms) = if self.config.writable {
+            (DirPerms::all(), FilePerms::all())
+        } else {
+            (DirPerms::READ, FilePerms::READ)
+        };
+
+        let mut builder = WasiCtxBuilder::new();
+        builder
+            .arg(&plugin.name)
+            .args(args)
+            .stdin(MemoryInputPipe::new(stdin))
+            .stdout(stdout.clone())
+            .stderr(stderr.clone());
+        if let Some(root) = root {
+            builder.preopened_dir(root, ".", dir_perms, file_perms)?;
+        }
+        Ok((builder, stdout, stderr))
+    }
+
+    async fn run(&self, plugin: &Plugin, args: &[&str], stdin: Vec<u8>, root: Option<&Path>) -> Result<PluginOutput> {
+        let (mut builder, stdout, stderr) = self.wasi_builder(plugin, args, stdin, root)?;
+        let limit = Duration::from_secs(self.config.timeout_secs);
+
+        let execution = async {
+            match &plugin.artifact {
+                Artifact::Module(module) => {
+                    let mut store = Store::new(&self.engine, builder.build_p1());
+                    store.set_fuel(self.config.fuel)?;
+                    store.epoch_deadline_async_yield_and_update(1);
+                    let instance = self
+                        .module_linker
+                        .instantiate_async(&mut store, module)
+                        .await?;
+                    let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+                    match start.call_async(&mut store, ()).await {
+                        Ok(()) => Ok(true),
+                        Err(e) => match e.downcast_ref::<I32Exit>() {
+                            Some(exit) => Ok(exit.0 == 0),
+                            None => Err(e),
+                        },
+                    }
+                }
+                Artifact::Component(component) => {
+                    let state = ComponentState {
+                        ctx: builder.build(),
+                        table: ResourceTable::new(),
+                    };
+                    let mut store = Store::new(&self.engine, state);
+                    store.set_fuel(self.config.fuel)?;
+                    store.epoch_deadline_async_yield_and_update(1);
+                    let command =
+                        Command::instantiate_async(&mut store, component, &self.component_linker)
+                            .await?;
+                    match command.wasi_cli_run().call_run(&mut store).await {
+                        Ok(result) => Ok(result.is_ok()),
+                        Err(e) => match e.downcast_ref::<I32Exit>() {
+                            Some(exit) => Ok(exit.0 == 0),
+                            None => Err(e),
+                        },
+                    }
+                }
+            }
+        };
+
+        tracing::debug!(plugin = %plugin.name, ?args, "Running plugin");
+        let success = tokio::time::timeout(limit, execution)
+            .await
+            .map_err(|_| anyhow!("Plugin '{}' timed out after {:?}", plugin.name, limit))?
+            .map_err(|e| anyhow!("Plugin '{}' failed: {}", plugin.name, e))?;
+
+        Ok(PluginOutput {
+            success,
+            stdout: String::from_utf8_lossy(&stdout.contents()).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr.contents()).into_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::Tool as _;
+    use crate::workspace::Workspace;
+    use serde_json::json;
+
+    /// A preview 1 module whose `describe` declares one tool, `name`, and
+    /// whose `call` runs `call`.
+    fn plugin(name: &str, call: &str) -> Vec<u8> {
+        let manifest = json!({"tools": [{"name": name, "description": "Test tool"}]}).to_string();
+        let wat = format!(
+            r#"(module
+                (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "args_get" (func $args_get (param i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "path_open"
+                    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 1024) "{manifest}")
+                (data (i32.const 2048) "out.txt")
+                (func $write (param $fd i32) (param $ptr i32) (param $len i32) (result i32)
+                    (i32.store (i32.const 512) (local.get $ptr))
+                    (i32.store (i32.const 516) (local.get $len))
+                    (call $fd_write (local.get $fd) (i32.const 512) (i32.const 1) (i32.const 520)))
+                (func (export "_start")
+                    (drop (call $args_sizes_get (i32.const 0) (i32.const 4)))
+                    (drop (call $args_get (i32.const 16) (i32.const 256)))
+                    ;; argv[1] starts with 'd' for `describe`.
+                    (if (i32.eq (i32.load8_u (i32.load (i32.const 20))) (i32.const 100))
+                        (then
+                            (drop (call $write (i32.const 1) (i32.const 1024) (i32.const {length})))
+                            (return)))
+                    {call}))"#,
+            manifest = manifest.replace('"', "\\\""),
+            length = manifest.len(),
+        );
+        wat::parse_str(wat).unwrap()
+    }
+
+    /// Copies stdin to stdout.
+    const ECHO: &str = r#"
+        (i32.store (i32.const 512) (i32.const 4096))
+        (i32.store (i32.const 516) (i32.const 4096))
+        (drop (call $fd_read (i32.const 0) (i32.const 512) (i32.const 1) (i32.const 520)))
+        (drop (call $write (i32.const 1) (i32.const 4096) (i32.load (i32.const 520))))"#;
+
+    const SPIN: &str = "(loop $spin (br $spin))";
+
+    /// Creates `out.txt` in the preopened directory, or exits with 1.
+    const WRITE: &str = r#"
+        (if (call $path_open (i32.const 3) (i32.const 0) (i32.const 2048) (i32.const 7)
+                (i32.const 1) (i64.const 64) (i64.const 0) (i32.const 0) (i32.const 600))
+            (then (call $proc_exit (i32.const 1))))
+        (drop (call $write (i32.load (i32.const 600)) (i32.const 1024) (i32.const 2)))"#;
+
+    struct Fixture {
+        _plugins: tempfile::TempDir,
+        workspace: tempfile::TempDir,
+        tools: Vec<PluginTool>,
+    }
+
+    impl Fixture {
+        async fn new(name: &str, call: &str, config: PluginsConfig) -> Self {
+            let plugins = tempfile::tempdir().unwrap();
+            fs::write(plugins.path().join("test.wasm"), plugin(name, call)).unwrap();
+            let config = PluginsConfig {
+                dir: Some(plugins.path().to_path_buf()),
+                ..config
+            };
+            let tools = load_tools(&config).await.unwrap();
+            Self {
+                _plugins: plugins,
+                workspace: tempfile::tempdir().unwrap(),
+                tools,
+            }
+        }
+
+        async fn call(&self, arguments: serde_json::Value) -> Result<CallToolResult, McpError> {
+            let context = ToolContext::new(Arc::new(Workspace::new(self.workspace.path().to_path_buf())));
+            self.tools[0].execute(arguments.as_object().cloned().unwrap(), context).await
+        }
+    }
+
+    fn text(result: &CallToolResult) -> &str {
+        &result.content[0].as_text().unwrap().text
+    }
+
+    #[tokio::test]
+    async fn describe_declares_tools_and_call_gets_the_arguments() {
+        let fixture = Fixture::new("echo", ECHO, PluginsConfig::default()).await;
+        assert_eq!(fixture.tools.len(), 1);
+        assert_eq!(fixture.tools[0].name(), "echo");
+        assert_eq!(fixture.tools[0].description(), "Test tool");
+        // Without annotations, a read-only sandbox makes a read-only tool.
+        assert_eq!(fixture.tools[0].annotations().read_only_hint, Some(true));
+
+        let result = fixture.call(json!({"message": "hi"})).await.unwrap();
+        assert_eq!(result.is_error, Some(false));
+        assert_eq!(text(&result), r#"{"message":"hi"}"#);
+    }
+
+    #[tokio::test]
+    async fn plugins_stop_when_their_fuel_runs_out() {
+        let config = PluginsConfig {
+            fuel: 100_000,
+            ..PluginsConfig::default()
+        };
+        let fixture = Fixture::new("spin", SPIN, config).await;
+        let started = std::time::Instant::now();
+        assert!(fixture.call(json!({})).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn plugins_stop_at_the_timeout() {
+        let config = PluginsConfig {
+            fuel: u64::MAX,
+            timeout_secs: 1,
+            ..PluginsConfig::default()
+        };
+        let fixture = Fixture::new("spin", SPIN, config).await;
+        let error = fixture.call(json!({})).await.unwrap_err();
+        assert!(error.message.contains("timed out"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn read_only_plugins_cannot_write_to_the_workspace() {
+        let fixture = Fixture::new("write", WRITE, PluginsConfig::default()).await;
+        let result = fixture.call(json!({})).await.unwrap();
+        assert_eq!(result.is_error, Some(true));
+        assert!(!fixture.workspace.path().join("out.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn writable_plugins_can_write_to_the_workspace() {
+        let config = PluginsConfig {
+            writable: true,
+            ..PluginsConfig::default()
+        };
+        let fixture = Fixture::new("write", WRITE, config).await;
+        assert_eq!(fixture.tools[0].annotations().read_only_hint, Some(false));
+        let result = fixture.call(json!({})).await.unwrap();
+        assert_eq!(result.is_error, Some(false));
+        assert!(fixture.workspace.path().join("out.txt").exists());
+    }
+}
@@ -0,0 +1,215 @@
+//! The checks and bookkeeping around every tool call, whichever way it arrives.
+//!
+//! In order: the caller's token permissions, confirmation rules, a workspace
+//! checkpoint for tools that may change files (a failed one refuses the call
+//! or adds a warning to its result), the call itself, redaction of
+//! the result and an audit record. Resource reads and prompt requests get the
+//! same permissions, redaction and audit; they change nothing, so neither
+//! confirmation nor checkpoints apply.
+
+use anyhow::Result;
+use rmcp::{
+    model::{CallToolRequestParam, CallToolResult, Content, JsonObject},
+    ErrorData as McpError,
+};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::audit::{AuditEvent, AuditLog, Kind, Outcome};
+use crate::auth::Identity;
+use crate::config::Config;
+use crate::confirm::Confirmation;
+use crate::metrics::metrics;
+use crate::redact::{Findings, Redactor};
+use crate::tool::{self, Tool, ToolContext};
+
+pub struct Policy {
+    confirmation: Confirmation,
+    redactor: Arc<Redactor>,
+    audit: AuditLog,
+    /// Refuse calls whose checkpoint failed rather than warn in the result.
+    require_checkpoints: bool,
+}
+
+impl Policy {
+    pub fn new(config: &Config) -> Result<Self> {
+        Ok(Self {
+            confirmation: Confirmation::new(&config.confirm)?,
+            redactor: Arc::new(Redactor::new(&config.redaction)?),
+            audit: AuditLog::new(&config.audit)?,
+            require_checkpoints: config.checkpoints.required,
+        })
+    }
+
+    /// Runs `tool` if the policy allows it. `identity` is the authenticated
+    /// caller, if any; calls without one are not limited by token permissions.
+    pub async fn call(
+        &self,
+        tool: &dyn Tool,
+        arguments: Option<JsonObject>,
+        mut context: ToolContext,
+        identity: Option<&Identity>,
+    ) -> Result<CallToolResult, McpError> {
+        let name = tool.name();
+        let session = context.turns.clone();
+        let audit = |outcome, elapsed_ms, error: Option<&str>, redactions: &Findings| {
+            self.audit.record(&AuditEvent {
+                session: session.session(),
+                identity: identity.map(|identity| identity.name.as_str()),
+                kind: Kind::Tool,
+                tool: name,
+                outcome,
+                elapsed_ms,
+                error,
+                redactions,
+            })
+        };
+        if let Some(identity) = identity {
+            if !identity.can_use(name) {
+                let error = McpError::invalid_request(
+                    format!("Token '{}' may not call tool '{}'", identity.name, name),
+                    None,
+                );
+                audit(Outcome::Denied, 0, Some(&error.message), &Findings::new());
+                metrics().tool_call(name, Outcome::Denied, None, 0);
+                metrics().rejection("permission");
+                return Err(error);
+            }
+        }
+        let request = CallToolRequestParam {
+            name: name.to_string().into(),
+            arguments,
+        };
+        if let Err(error) = self.confirmation.check(context.peer.as_ref(), &request, &tool.annotations()).await {
+            audit(Outcome::Denied, 0, Some(&error.message), &Findings::new());
+            metrics().tool_call(name, Outcome::Denied, None, 0);
+            metrics().rejection("confirmation");
+            return Err(error);
+        }
+        let checkpoint_error = checkpoint(tool, &context).await.err();
+        if let Some(e) = checkpoint_error.as_ref().filter(|_| self.require_checkpoints) {
+            let mut error = tool::internal_error(anyhow::anyhow!(
+                "The call was not run because the workspace checkpoint failed: {}",
+                e
+            ));
+            let mut findings = Findings::new();
+            error.message = self.redactor.redact(&error.message, &mut findings).into();
+            audit(Outcome::Error, 0, Some(&error.message), &findings);
+            metrics().tool_call(name, Outcome::Error, None, 0);
+            return Err(error);
+        }
+        context.redactor = Some(self.redactor.clone());
+        let redacted = context.redacted.clone();
+
+        let started = Instant::now();
+        let mut result = tool.execute(request.arguments.unwrap_or_default(), context).await;
+        let elapsed = started.elapsed();
+        let elapsed_ms = elapsed.as_millis() as u64;
+
+        if let (Ok(output), Some(e)) = (&mut result, &checkpoint_error) {
+            output.content.push(Content::text(format!(
+                "Warning: no checkpoint was taken before this call, so restore_checkpoint cannot undo it: {}",
+                e
+            )));
+        }
+        let mut findings = std::mem::take(&mut *redacted.lock().unwrap());
+        match &mut result {
+            Ok(output) => {
+                for (name, count) in self.redactor.redact_result(output) {
+                    *findings.entry(name).or_default() += count;
+                }
+            }
+            Err(e) => e.message = self.redactor.redact(&e.message, &mut findings).into(),
+        }
+        let outcome = match &result {
+            Ok(output) if output.is_error == Some(true) => Outcome::Error,
+            Ok(_) => Outcome::Ok,
+            Err(_) => Outcome::Error,
+        };
+        let error = result.as_ref().err().map(|e| e.message.as_ref());
+        audit(outcome, elapsed_ms, error, &findings);
+        let bytes = result.as_ref().map_or(0, |output| {
+            output
+                .content
+                .iter()
+                .filter_map(|content| content.as_text())
+                .map(|text| text.text.len())
+                .sum()
+        });
+        metrics().tool_call(name, outcome, Some(elapsed), bytes);
+        result
+    }
+
+    /// Runs `request` for the resource or prompt `name` if the caller's
+    /// token allows it, then redacts what it returned with `redact`.
+    pub async fn access<T>(
+        &self,
+        kind: Kind,
+        name: &str,
+        session: &str,
+        identity: Option<&Identity>,
+        request: impl Future<Output = Result<T, McpError>>,
+        redact: impl FnOnce(&Redactor, &mut T) -> Findings,
+    ) -> Result<T, McpError> {
+        let audit = |outcome, elapsed_ms, error: Option<&str>, redactions: &Findings| {
+            self.audit.record(&AuditEvent {
+                session,
+                identity: identity.map(|identity| identity.name.as_str()),
+                kind,
+                tool: name,
+                outcome,
+                elapsed_ms,
+                error,
+                redactions,
+            })
+        };
+        if let Some(identity) = identity {
+            if !identity.can_use(name) {
+                let error = McpError::invalid_request(
+                    format!("Token '{}' may not use '{}'", identity.name, name),
+                    None,
+                );
+                audit(Outcome::Denied, 0, Some(&error.message), &Findings::new());
+                metrics().rejection("permission");
+                return Err(error);
+            }
+        }
+
+        let started = Instant::now();
+        let mut result = request.await;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        let mut findings = Findings::new();
+        match &mut result {
+            Ok(output) => findings = redact(&self.redactor, output),
+            Err(e) => e.message = self.redactor.redact(&e.message, &mut findings).into(),
+        }
+        let error = result.as_ref().err().map(|e| e.message.as_ref());
+        let outcome = if error.is_some() { Outcome::Error } else { Outcome::Ok };
+        audit(outcome, elapsed_ms, error, &findings);
+        result
+    }
+}
+
+/// Snapshots the workspace before a call to a tool that asks for checkpoints.
+async fn checkpoint(tool: &dyn Tool, context: &ToolContext) -> Result<()> {
+    let turn = context.turns.next();
+    let Some(store) = context.checkpoints.clone() else {
+        return Ok(());
+    };
+    if !tool.checkpoints() {
+        return Ok(());
+    }
+    let turns = context.turns.clone();
+    let roots = context.workspace.roots();
+    let tool = tool.name().to_string();
+    let result = tokio::task::spawn_blocking(move || store.snapshot(&turns, turn, &tool, &roots))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+    match &result {
+        Ok(id) => tracing::debug!(checkpoint = %id, "Workspace checkpoint taken"),
+        Err(e) => tracing::warn!(error = %e, "Failed to take workspace checkpoint"),
+    }
+    result.map(drop)
+}
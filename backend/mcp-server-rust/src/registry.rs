@@ -0,0 +1,90 @@
+//! The tools a server offers, by name.
+
+use rmcp::{
+    model::{CallToolResult, JsonObject},
+    ErrorData as McpError,
+};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::tool::{Tool, ToolContext};
+use crate::tools;
+
+#[derive(Default, Clone)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The tools this crate ships: files, notebooks, tables, shell, search, logs, summaries, the scratchpad, result paging and checkpoints.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(tools::files::ReadFile);
+        registry.register(tools::files::ListDirectory);
+        registry.register(tools::notebook::ReadNotebook);
+        registry.register(tools::notebook::EditNotebook);
+        registry.register(tools::tables::QueryTable);
+        registry.register(tools::shell::ShellCommand);
+        registry.register(tools::shell::SessionOpen);
+        registry.register(tools::shell::SessionExec);
+        registry.register(tools::shell::SessionClose);
+        registry.register(tools::search::SearchCode);
+        registry.register(tools::logs::TailLog);
+        registry.register(tools::summarize::SummarizeFile);
+        registry.register(tools::summarize::SummarizeDirectory);
+        registry.register(tools::summarize::ExplainDiff);
+        registry.register(tools::scratchpad::TodoRead);
+        registry.register(tools::scratchpad::TodoWrite);
+        registry.register(tools::scratchpad::MemoryGet);
+        registry.register(tools::scratchpad::MemorySet);
+        registry.register(tools::scratchpad::MemorySearch);
+        registry.register(tools::read_more::ReadMore);
+        registry.register(tools::checkpoints::ListCheckpoints);
+        registry.register(tools::checkpoints::DiffCheckpoint);
+        registry.register(tools::checkpoints::RestoreCheckpoint);
+        registry
+    }
+
+    /// Adds a tool, replacing any other with the same name.
+    pub fn register(&mut self, tool: impl Tool + 'static) {
+        self.register_arc(Arc::new(tool));
+    }
+
+    pub fn register_arc(&mut self, tool: Arc<dyn Tool>) {
+        self.tools.insert(tool.name().to_string(), tool);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.get(name).cloned()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Definitions of every tool, sorted by name.
+    pub fn definitions(&self) -> Vec<rmcp::model::Tool> {
+        self.tools.values().map(|tool| tool.definition()).collect()
+    }
+
+    /// Calls a tool directly, bypassing [`Policy`](crate::policy::Policy).
+    pub async fn call(
+        &self,
+        name: &str,
+        arguments: JsonObject,
+        context: ToolContext,
+    ) -> Result<CallToolResult, McpError> {
+        let tool = self
+            .get(name)
+            .ok_or_else(|| McpError::invalid_params(format!("Unknown tool '{}'", name), None))?;
+        tool.execute(arguments, context).await
+    }
+}
@@ -0,0 +1,86 @@
+//! Reloads the config when its file changes or the process receives SIGHUP.
+//!
+//! An edit that fails to parse, validate or load leaves the running config in
+//! place and is logged; the next change is tried afresh.
+
+use anyhow::{anyhow, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+use crate::server::McpServer;
+
+/// Editors often write a file in several steps; changes this close together cause one reload.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Reloads `server` from `path` on every change to it and on SIGHUP. Without
+/// a path, SIGHUP reloads from `$MCP_SERVER_CONFIG` or the defaults.
+pub fn watch(server: McpServer, path: Option<PathBuf>) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let watcher = match &path {
+        Some(path) => Some(watch_file(path, tx.clone())?),
+        None => None,
+    };
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangups = signal(SignalKind::hangup())?;
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                tracing::info!("Received SIGHUP");
+                if tx.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    tokio::spawn(async move {
+        // Keeps watching for as long as the task runs.
+        let _watcher = watcher;
+        while rx.recv().await.is_some() {
+            tokio::time::sleep(DEBOUNCE).await;
+            while rx.try_recv().is_ok() {}
+            let config = match &path {
+                Some(path) => Config::from_file(path),
+                None => Config::load(),
+            };
+            if let Err(e) = async { server.reload(&config?).await }.await {
+                tracing::error!(error = %e, "Config reload failed; keeping the previous config");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Watches the file's directory, since editors replace files rather than write them in place.
+fn watch_file(path: &Path, tx: mpsc::UnboundedSender<()>) -> Result<notify::RecommendedWatcher> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Config path '{}' has no file name", path.display()))?
+        .to_os_string();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        let relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            && event.paths.iter().any(|changed| changed.file_name() == Some(name.as_os_str()));
+        if relevant {
+            let _ = tx.send(());
+        }
+    })
+    .map_err(|e| anyhow!("Failed to watch config '{}': {}", path.display(), e))?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(|e| anyhow!("Failed to watch config '{}': {}", path.display(), e))?;
+    Ok(watcher)
+}
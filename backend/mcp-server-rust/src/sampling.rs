@@ -0,0 +1,242 @@
+//! Asking the client's model for help through `sampling/createMessage`.
+//!
+//! Input too large for one request is split into chunks at line breaks. Each
+//! chunk is summarized on its own, then the partial summaries are combined
+//! the same way until they fit one request, which writes the answer. Only
+//! that answer goes back into the conversation.
+
+use anyhow::{anyhow, bail, Result};
+use rmcp::{
+    model::{Content, CreateMessageRequestParam, ModelPreferences, Role, SamplingMessage},
+    service::Peer,
+    RoleServer,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+use crate::redact::{Findings, Redactor};
+
+/// Input per sampling request, roughly 8k tokens.
+pub const CHUNK_BYTES: usize = 32 * 1024;
+/// Largest input a tool may summarize.
+pub const MAX_INPUT_BYTES: usize = 1024 * 1024;
+/// Tokens the model may spend on the summary of one chunk.
+const PARTIAL_TOKENS: u32 = 800;
+/// Sampling requests in flight at once.
+const CONCURRENCY: usize = 4;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(180);
+
+const SYSTEM_PROMPT: &str = "You condense material for another assistant that has little room in its context. \
+     Be accurate and specific: keep the names of files, functions, types, commands and values that matter. \
+     Do not add greetings, caveats or anything the material does not support.";
+
+/// The model's answer and how many requests it took.
+pub struct Summary {
+    pub text: String,
+    /// The model the client used for the final request.
+    pub model: String,
+    pub chunks: usize,
+    pub requests: usize,
+}
+
+/// Sends sampling requests to one client.
+#[derive(Clone)]
+pub struct Sampler {
+    peer: Peer<RoleServer>,
+    redactor: Option<Arc<Redactor>>,
+    /// Where secrets redacted from prompts are counted, for the audit record.
+    redacted: Arc<Mutex<Findings>>,
+}
+
+impl Sampler {
+    /// A sampler for the calling client; fails when there is none or it did
+    /// not declare the `sampling` capability. Prompts are redacted with
+    /// `redactor` before they leave the server, and what was redacted is
+    /// added to `redacted`.
+    pub fn new(
+        peer: Option<&Peer<RoleServer>>,
+        redactor: Option<Arc<Redactor>>,
+        redacted: Arc<Mutex<Findings>>,
+    ) -> Result<Self> {
+        let peer = peer
+            .filter(|peer| peer.peer_info().is_some_and(|info| info.capabilities.sampling.is_some()))
+            .ok_or_else(|| anyhow!("This tool needs a client that supports sampling"))?;
+        Ok(Self {
+            peer: peer.clone(),
+            redactor,
+            redacted,
+        })
+    }
+
+    /// Sends one prompt and returns the text of the answer and the model that wrote it.
+    pub async fn ask(&self, prompt: &str, max_tokens: u32) -> Result<(String, String)> {
+        let prompt = match &self.redactor {
+            Some(redactor) => redactor.redact(prompt, &mut self.redacted.lock().unwrap()),
+            None => prompt.to_string(),
+        };
+        let request = CreateMessageRequestParam {
+            messages: vec![SamplingMessage {
+                role: Role::User,
+                content: Content::text(prompt),
+            }],
+            // Condensing text needs speed more than the strongest model.
+            model_preferences: Some(ModelPreferences {
+                hints: None,
+                cost_priority: Some(0.5),
+                speed_priority: Some(0.8),
+                intelligence_priority: Some(0.4),
+            }),
+            system_prompt: Some(SYSTEM_PROMPT.to_string()),
+            include_context: None,
+            temperature: Some(0.2),
+            max_tokens,
+            stop_sequences: None,
+            metadata: None,
+        };
+        let result = tokio::time::timeout(REQUEST_TIMEOUT, self.peer.create_message(request))
+            .await
+            .map_err(|_| anyhow!("The client did not answer the sampling request in time"))?
+            .map_err(|e| anyhow!("Sampling request failed: {}", e))?;
+        let text = result
+            .message
+            .content
+            .as_text()
+            .map(|text| text.text.clone())
+            .ok_or_else(|| anyhow!("The client's model did not answer with text"))?;
+        Ok((text, result.model))
+    }
+
+    /// Answers `instructions` about `input`, which is described by `subject`
+    /// (e.g. "the file src/main.rs"), with at most `max_tokens` tokens.
+    pub async fn summarize(&self, subject: &str, input: &str, instructions: &str, max_tokens: u32) -> Result<Summary> {
+        if input.trim().is_empty() {
+            bail!("There is nothing to summarize in {}", subject);
+        }
+        if input.len() > MAX_INPUT_BYTES {
+            bail!(
+                "{} is {} bytes; at most {} can be summarized",
+                subject,
+                input.len(),
+                MAX_INPUT_BYTES
+            );
+        }
+        let mut parts = chunks(input, CHUNK_BYTES);
+        let chunk_count = parts.len();
+        let mut requests = 0;
+        let mut partial = false;
+        while parts.len() > 1 {
+            let before: usize = parts.iter().map(String::len).sum();
+            let total = parts.len();
+            let prompts: Vec<String> = parts
+                .into_iter()
+                .enumerate()
+                .map(|(i, part)| {
+                    let what = if partial {
+                        format!("summaries of consecutive parts of {}, group {} of {}", subject, i + 1, total)
+                    } else {
+                        format!("part {} of {} of {}", i + 1, total, subject)
+                    };
+                    format!(
+                        "Below are {}. Summarize them in a few short paragraphs or bullets. Your summary will be \
+                         combined with the others to answer this request:\n{}\n\n<material>\n{}\n</material>",
+                        what, instructions, part
+                    )
+                })
+                .collect();
+            let summaries = self.ask_all(prompts).await?;
+            requests += total;
+            let combined = summaries.join("\n\n");
+            if combined.len() >= before {
+                bail!("The client's summaries did not get shorter than the input");
+            }
+            parts = chunks(&combined, CHUNK_BYTES);
+            partial = true;
+        }
+
+        let what = if partial {
+            format!("summaries of consecutive parts of {}, in order", subject)
+        } else {
+            subject.to_string()
+        };
+        let prompt = format!(
+            "{}\n\nBelow is {}.\n\n<material>\n{}\n</material>",
+            instructions, what, parts[0]
+        );
+        let (text, model) = self.ask(&prompt, max_tokens).await?;
+        Ok(Summary {
+            text: text.trim().to_string(),
+            model,
+            chunks: chunk_count,
+            requests: requests + 1,
+        })
+    }
+
+    /// Sends the prompts a few at a time and returns the answers in order.
+    async fn ask_all(&self, prompts: Vec<String>) -> Result<Vec<String>> {
+        let mut answers = vec![String::new(); prompts.len()];
+        let mut prompts = prompts.into_iter().enumerate();
+        let mut running = JoinSet::new();
+        loop {
+            while running.len() < CONCURRENCY {
+                let Some((i, prompt)) = prompts.next() else { break };
+                let sampler = self.clone();
+                running.spawn(async move { (i, sampler.ask(&prompt, PARTIAL_TOKENS).await) });
+            }
+            let Some(joined) = running.join_next().await else { break };
+            let (i, answer) = joined?;
+            answers[i] = answer?.0;
+        }
+        Ok(answers)
+    }
+}
+
+/// Splits `text` into pieces of at most `max_bytes`, at line breaks where possible.
+pub fn chunks(text: &str, max_bytes: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for mut line in text.split_inclusive('\n') {
+        while !line.is_empty() {
+            if current.len() + line.len() <= max_bytes {
+                current.push_str(line);
+                break;
+            }
+            if !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+                continue;
+            }
+            // A single line longer than a chunk.
+            let mut end = max_bytes;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            chunks.push(line[..end].to_string());
+            line = &line[end..];
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_break_between_lines() {
+        assert_eq!(chunks("aaa\nbbb\nccc\n", 8), ["aaa\nbbb\n", "ccc\n"]);
+        assert_eq!(chunks("short", 8), ["short"]);
+        assert!(chunks("", 8).is_empty());
+    }
+
+    #[test]
+    fn chunks_split_long_lines_on_character_boundaries() {
+        let text = "ab\n".to_string() + &"é".repeat(5) + "\ncd";
+        let chunks = chunks(&text, 4);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 4), "{:?}", chunks);
+        assert_eq!(chunks.concat(), text);
+        assert_eq!(chunks[0], "ab\n");
+    }
+}
@@ -0,0 +1,323 @@
+//! A durable plan and notes for agents: the todo list and memories behind
+//! the `todo_*` and `memory_*` tools.
+//!
+//! Each workspace, identified by its primary root, has one JSON file under
+//! the store directory. Every operation reads the file and writes it back
+//! whole under an advisory lock on `<file>.lock`, so servers sharing a store
+//! see each other's changes without losing any, and nothing is lost when the
+//! server restarts or the client's context is cut.
+
+use anyhow::{anyhow, bail, Result};
+use fd_lock::RwLock;
+use rmcp::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{state_dir, ScratchpadConfig};
+
+const FORMAT: u32 = 1;
+const MAX_TODOS: usize = 200;
+const MAX_MEMORIES: usize = 1000;
+/// Longest todo or memory text.
+const MAX_TEXT_BYTES: usize = 16 * 1024;
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoStatus {
+    Pending,
+    InProgress,
+    Completed,
+    Cancelled,
+}
+
+impl TodoStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TodoStatus::Pending => "pending",
+            TodoStatus::InProgress => "in_progress",
+            TodoStatus::Completed => "completed",
+            TodoStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Todo {
+    /// Pass back to `todo_write` to update this item.
+    pub id: String,
+    pub content: String,
+    pub status: TodoStatus,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    /// Seconds since the Unix epoch of the last change to content or status.
+    pub updated: u64,
+}
+
+/// An item as written by a client; without an id it is a new item.
+#[derive(Deserialize, JsonSchema)]
+pub struct TodoUpdate {
+    pub id: Option<String>,
+    pub content: String,
+    /// `pending` when omitted.
+    pub status: Option<TodoStatus>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Memory {
+    pub key: String,
+    pub value: String,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    /// Seconds since the Unix epoch.
+    pub updated: u64,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct Pad {
+    format: u32,
+    /// The workspace root the pad belongs to, for anyone reading the store.
+    root: PathBuf,
+    next_id: u64,
+    todos: Vec<Todo>,
+    memories: BTreeMap<String, Memory>,
+}
+
+pub struct Scratchpad {
+    dir: PathBuf,
+}
+
+impl Scratchpad {
+    pub fn new(config: &ScratchpadConfig) -> Result<Self> {
+        let dir = config.dir.clone().unwrap_or_else(|| state_dir("scratchpad"));
+        fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Failed to create scratchpad directory '{}': {}", dir.display(), e))?;
+        Ok(Self { dir })
+    }
+
+    pub fn todos(&self, root: &Path) -> Result<Vec<Todo>> {
+        self.locked(root, false, || Ok(self.load(root)?.todos))
+    }
+
+    /// Replaces the todo list with `items`, or with `merge` only adds and
+    /// updates them. Items keep their id and creation time across writes.
+    pub fn write_todos(&self, root: &Path, items: Vec<TodoUpdate>, merge: bool) -> Result<Vec<Todo>> {
+        self.locked(root, true, || {
+            let mut pad = self.load(root)?;
+            let now = now();
+            let previous = std::mem::take(&mut pad.todos);
+            let mut todos = if merge { previous.clone() } else { Vec::new() };
+            for item in items {
+                check_text("Todo", &item.content)?;
+                let status = item.status.unwrap_or(TodoStatus::Pending);
+                let Some(id) = item.id else {
+                    pad.next_id += 1;
+                    todos.push(Todo {
+                        id: pad.next_id.to_string(),
+                        content: item.content,
+                        status,
+                        created: now,
+                        updated: now,
+                    });
+                    continue;
+                };
+                let mut todo = previous
+                    .iter()
+                    .find(|todo| todo.id == id)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("There is no todo with id '{}'", id))?;
+                if todo.content != item.content || todo.status != status {
+                    todo.content = item.content;
+                    todo.status = status;
+                    todo.updated = now;
+                }
+                match todos.iter_mut().find(|existing| existing.id == id) {
+                    Some(existing) if merge => *existing = todo,
+                    Some(_) => bail!("Todo '{}' is listed twice", id),
+                    None => todos.push(todo),
+                }
+            }
+            if todos.len() > MAX_TODOS {
+                bail!("At most {} todos can be kept", MAX_TODOS);
+            }
+            pad.todos = todos;
+            self.save(root, &pad)?;
+            Ok(pad.todos)
+        })
+    }
+
+    /// Stores `value` under `key`, or deletes the entry when `value` is `None`.
+    /// Returns the stored entry, or the deleted one.
+    pub fn set_memory(&self, root: &Path, key: &str, value: Option<String>) -> Result<Option<Memory>> {
+        let key = key.trim();
+        if key.is_empty() || key.len() > 200 {
+            bail!("Memory keys must be 1 to 200 bytes");
+        }
+        self.locked(root, true, || {
+            let mut pad = self.load(root)?;
+            let Some(value) = value else {
+                let removed = pad.memories.remove(key);
+                if removed.is_some() {
+                    self.save(root, &pad)?;
+                }
+                return Ok(removed);
+            };
+            check_text("Memory", &value)?;
+            if !pad.memories.contains_key(key) && pad.memories.len() >= MAX_MEMORIES {
+                bail!("At most {} memories can be kept; delete some first", MAX_MEMORIES);
+            }
+            let now = now();
+            let memory = pad.memories.entry(key.to_string()).or_insert_with(|| Memory {
+                key: key.to_string(),
+                value: String::new(),
+                created: now,
+                updated: now,
+            });
+            memory.value = value;
+            memory.updated = now;
+            let memory = memory.clone();
+            self.save(root, &pad)?;
+            Ok(Some(memory))
+        })
+    }
+
+    pub fn memory(&self, root: &Path, key: &str) -> Result<Option<Memory>> {
+        self.locked(root, false, || Ok(self.load(root)?.memories.remove(key.trim())))
+    }
+
+    /// Memories whose key or value contains the words of `query`, ignoring
+    /// case, most matching words first and then most recently updated. An
+    /// empty query returns the most recently updated memories.
+    pub fn search_memories(&self, root: &Path, query: &str, limit: usize) -> Result<Vec<Memory>> {
+        self.locked(root, false, || {
+            let pad = self.load(root)?;
+            let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+            let mut scored: Vec<(usize, Memory)> = pad
+                .memories
+                .into_values()
+                .filter_map(|memory| {
+                    let key = memory.key.to_lowercase();
+                    let value = memory.value.to_lowercase();
+                    // A word in the key counts twice.
+                    let score: usize = words
+                        .iter()
+                        .map(|word| 2 * usize::from(key.contains(word)) + usize::from(value.contains(word)))
+                        .sum();
+                    (words.is_empty() || score > 0).then_some((score, memory))
+                })
+                .collect();
+            scored.sort_by(|(a, x), (b, y)| b.cmp(a).then(y.updated.cmp(&x.updated)));
+            Ok(scored.into_iter().take(limit).map(|(_, memory)| memory).collect())
+        })
+    }
+
+    fn path(&self, root: &Path) -> PathBuf {
+        let hash = format!("{:x}", Sha256::digest(root.to_string_lossy().as_bytes()));
+        self.dir.join(format!("{}.json", &hash[..16]))
+    }
+
+    /// Runs `f` holding the advisory lock on the workspace's pad, shared or
+    /// exclusive. Every server using the store takes it, so their
+    /// read-modify-write cycles never interleave.
+    fn locked<T>(&self, root: &Path, exclusive: bool, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let path = self.path(root).with_extension("lock");
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| anyhow!("Failed to open '{}': {}", path.display(), e))?;
+        let mut lock = RwLock::new(file);
+        let failed = |e: std::io::Error| anyhow!("Failed to lock scratchpad '{}': {}", path.display(), e);
+        if exclusive {
+            let _guard = lock.write().map_err(failed)?;
+            f()
+        } else {
+            let _guard = lock.read().map_err(failed)?;
+            f()
+        }
+    }
+
+    fn load(&self, root: &Path) -> Result<Pad> {
+        let path = self.path(root);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Pad {
+                    format: FORMAT,
+                    root: root.to_path_buf(),
+                    ..Pad::default()
+                })
+            }
+            Err(e) => bail!("Failed to read scratchpad '{}': {}", path.display(), e),
+        };
+        let pad: Pad = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Scratchpad '{}' is corrupt: {}", path.display(), e))?;
+        if pad.format != FORMAT {
+            bail!("Scratchpad '{}' has unknown format {}", path.display(), pad.format);
+        }
+        Ok(pad)
+    }
+
+    /// Writes the pad through a temporary file of its own, so a crash leaves the old or the new one.
+    fn save(&self, root: &Path, pad: &Pad) -> Result<()> {
+        let path = self.path(root);
+        let partial = path.with_extension(format!("{:016x}.partial", RandomState::new().hash_one(root)));
+        fs::write(&partial, serde_json::to_vec_pretty(pad)?)
+            .and_then(|()| fs::rename(&partial, &path))
+            .map_err(|e| anyhow!("Failed to write scratchpad '{}': {}", path.display(), e))
+    }
+}
+
+fn check_text(what: &str, text: &str) -> Result<()> {
+    if text.trim().is_empty() {
+        bail!("{} text is empty", what);
+    }
+    if text.len() > MAX_TEXT_BYTES {
+        bail!("{} text is {} bytes; at most {} are kept", what, text.len(), MAX_TEXT_BYTES);
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scratchpads_sharing_a_store_lose_no_writes() {
+        let store = tempfile::tempdir().unwrap();
+        let config = ScratchpadConfig {
+            enabled: true,
+            dir: Some(store.path().to_path_buf()),
+        };
+        let root = Path::new("/workspace");
+        std::thread::scope(|scope| {
+            for server in 0..4 {
+                // Each thread stands in for another server with its own handle on the store.
+                let pad = Scratchpad::new(&config).unwrap();
+                scope.spawn(move || {
+                    for i in 0..10 {
+                        pad.set_memory(root, &format!("{}-{}", server, i), Some("note".into())).unwrap();
+                    }
+                });
+            }
+        });
+        let pad = Scratchpad::new(&config).unwrap();
+        assert_eq!(pad.search_memories(root, "", 100).unwrap().len(), 40);
+        let leftovers = fs::read_dir(store.path())
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".partial"))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+}
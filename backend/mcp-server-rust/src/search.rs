@@ -0,0 +1,599 @@
+//! Ranked full-text search over the workspace.
+//!
+//! Every root gets its own tantivy index under `index/<hash of the root>`,
+//! built the first time the root is searched. Files are indexed in chunks of
+//! [`CHUNK_LINES`] lines, which are ranked with BM25. A file watcher marks the
+//! index stale and the next search re-reads only the files whose size or mtime
+//! changed. Those are kept in the commit payload, so they always describe
+//! exactly what the index contains, even when several servers share it.
+//! Up to [`MAX_OPEN_ROOTS`] indexes and their watchers stay open; the least
+//! recently searched is closed to make room, and reopened from disk if needed.
+
+use anyhow::{anyhow, Result};
+use ignore::WalkBuilder;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rmcp::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, UNIX_EPOCH};
+use tantivy::collector::TopDocs;
+use tantivy::directory::{error::LockError, MmapDirectory};
+use tantivy::query::{BooleanQuery, ConstScoreQuery, Occur, QueryParser, RegexQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, STORED, STRING};
+use tantivy::tokenizer::{Token, TokenStream, Tokenizer};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, TantivyError, Term};
+
+use crate::config::{state_dir, SearchConfig};
+
+/// Lines per indexed chunk; results are ranked by chunk.
+const CHUNK_LINES: usize = 30;
+/// Bumped whenever the schema or tokenizer changes, so older indexes are rebuilt.
+const FORMAT: u32 = 1;
+const TOKENIZER: &str = "code";
+/// Longer words, such as base64 blobs, are not indexed.
+const MAX_WORD_BYTES: usize = 64;
+const WRITER_MEMORY_BYTES: usize = 50 * 1024 * 1024;
+/// Most root indexes kept open, each with its own file watcher; opening
+/// another drops the least recently searched.
+const MAX_OPEN_ROOTS: usize = 16;
+
+#[derive(Serialize, JsonSchema)]
+pub struct SearchHit {
+    pub path: String,
+    /// The line that best matches the query, counting from 1.
+    pub line: u64,
+    /// BM25 relevance; higher is better.
+    pub score: f32,
+    /// The best line with up to two lines around it, each prefixed with its
+    /// number and `:` for the best line or `-` for context.
+    pub snippet: String,
+}
+
+pub struct SearchIndex {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    roots: Mutex<HashMap<PathBuf, OpenRoot>>,
+}
+
+struct OpenRoot {
+    index: Arc<Mutex<RootIndex>>,
+    last_used: Instant,
+}
+
+impl SearchIndex {
+    pub fn new(config: &SearchConfig) -> Result<Self> {
+        let dir = config.dir.clone().unwrap_or_else(|| state_dir("index"));
+        fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Failed to create search index directory '{}': {}", dir.display(), e))?;
+        Ok(Self {
+            dir,
+            max_file_bytes: config.max_file_bytes,
+            max_files: config.max_files,
+            roots: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the chunks under `roots` that best match `query`, limited to
+    /// `scope` when given. Blocks while indexes are built or brought up to date.
+    pub fn search(&self, roots: &[PathBuf], scope: Option<&Path>, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        for root in roots {
+            let filter = match scope {
+                Some(scope) if !root.starts_with(scope) => match scope.strip_prefix(root) {
+                    Ok(relative) => Some(relative),
+                    Err(_) => continue,
+                },
+                _ => None,
+            };
+            let index = self.root(root)?;
+            let index = index.lock().unwrap();
+            index.sync()?;
+            hits.extend(index.search(query, filter, limit)?);
+        }
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    fn root(&self, root: &Path) -> Result<Arc<Mutex<RootIndex>>> {
+        let mut roots = self.roots.lock().unwrap();
+        if let Some(open) = roots.get_mut(root) {
+            open.last_used = Instant::now();
+            return Ok(open.index.clone());
+        }
+        if roots.len() >= MAX_OPEN_ROOTS {
+            // A search still using the dropped index keeps it until it is done.
+            let oldest = roots.iter().min_by_key(|(_, open)| open.last_used).map(|(root, _)| root.clone());
+            if let Some(oldest) = oldest {
+                roots.remove(&oldest);
+            }
+        }
+        let hash = format!("{:x}", Sha256::digest(root.to_string_lossy().as_bytes()));
+        let index = RootIndex::open(root, &self.dir.join(&hash[..16]), self.max_file_bytes, self.max_files)?;
+        let index = Arc::new(Mutex::new(index));
+        roots.insert(
+            root.to_path_buf(),
+            OpenRoot {
+                index: index.clone(),
+                last_used: Instant::now(),
+            },
+        );
+        Ok(index)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Fields {
+    /// Relative to the root.
+    path: Field,
+    /// First line of the chunk, counting from 1.
+    line: Field,
+    text: Field,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    format: u32,
+    /// Size and mtime (nanoseconds since the epoch) of every indexed file, by relative path.
+    files: BTreeMap<String, (u64, u64)>,
+}
+
+struct RootIndex {
+    root: PathBuf,
+    dir: PathBuf,
+    index: Index,
+    reader: IndexReader,
+    fields: Fields,
+    max_file_bytes: u64,
+    max_files: usize,
+    /// Set by the watcher when files change.
+    stale: Arc<AtomicBool>,
+    /// Kept for its events. Without a watcher every search checks for changes.
+    watcher: Option<RecommendedWatcher>,
+}
+
+impl RootIndex {
+    fn open(root: &Path, dir: &Path, max_file_bytes: u64, max_files: usize) -> Result<Self> {
+        let (schema, fields) = schema();
+        let current = open_index(dir, &schema)
+            .ok()
+            .filter(|index| read_payload(index).is_ok_and(|payload| payload.format == FORMAT));
+        let index = match current {
+            Some(index) => index,
+            None => {
+                // Missing, unreadable or from an older version: start over.
+                if dir.exists() {
+                    fs::remove_dir_all(dir)
+                        .map_err(|e| anyhow!("Failed to remove search index '{}': {}", dir.display(), e))?;
+                }
+                open_index(dir, &schema)?
+            }
+        };
+        let reader = index.reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?;
+
+        let stale = Arc::new(AtomicBool::new(true));
+        let watcher = match watch(root, dir, stale.clone()) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                tracing::warn!(root = %root.display(), error = %e, "Failed to watch files; the search index will be checked on every search");
+                None
+            }
+        };
+        Ok(Self {
+            root: root.to_path_buf(),
+            dir: dir.to_path_buf(),
+            index,
+            reader,
+            fields,
+            max_file_bytes,
+            max_files,
+            stale,
+            watcher,
+        })
+    }
+
+    /// Re-indexes the files that changed since the last commit, if the watcher saw any changes.
+    fn sync(&self) -> Result<()> {
+        if !self.stale.swap(false, Ordering::Relaxed) && self.watcher.is_some() {
+            return Ok(());
+        }
+        let indexed = read_payload(&self.index)?.files;
+        let mut files = self.walk();
+        let changed: Vec<String> = files
+            .iter()
+            .filter(|(path, stamp)| indexed.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .collect();
+        let removed: Vec<&String> = indexed.keys().filter(|path| !files.contains_key(*path)).collect();
+        if changed.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        let mut writer: IndexWriter = match self.index.writer_with_num_threads(1, WRITER_MEMORY_BYTES) {
+            Ok(writer) => writer,
+            Err(TantivyError::LockFailure(LockError::LockBusy, _)) => {
+                // Another server is updating the index; search what it has committed so far.
+                self.stale.store(true, Ordering::Relaxed);
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        for path in removed.iter().copied().chain(&changed) {
+            writer.delete_term(Term::from_field_text(self.fields.path, path));
+        }
+        for path in &changed {
+            if let Err(e) = self.add_file(&writer, path) {
+                tracing::warn!(path = %self.root.join(path).display(), error = %e, "Failed to index file");
+                files.remove(path);
+            }
+        }
+        let mut commit = writer.prepare_commit()?;
+        commit.set_payload(&serde_json::to_string(&Payload { format: FORMAT, files })?);
+        commit.commit()?;
+        writer.wait_merging_threads()?;
+        tracing::info!(
+            root = %self.root.display(),
+            changed = changed.len(),
+            removed = removed.len(),
+            "Search index updated"
+        );
+        Ok(())
+    }
+
+    /// Size and mtime of every regular file under the root, by relative path, honoring ignore files.
+    fn walk(&self) -> BTreeMap<String, (u64, u64)> {
+        let dir = self.dir.clone();
+        let entries = WalkBuilder::new(&self.root)
+            .hidden(false)
+            .filter_entry(move |entry| entry.file_name() != ".git" && !entry.path().starts_with(&dir))
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()));
+        let mut files = BTreeMap::new();
+        for entry in entries {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.len() > self.max_file_bytes {
+                continue;
+            }
+            if files.len() >= self.max_files {
+                tracing::warn!(root = %self.root.display(), limit = self.max_files, "Search index file limit reached; later files are not indexed");
+                break;
+            }
+            let Ok(relative) = entry.path().strip_prefix(&self.root) else {
+                continue;
+            };
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos() as u64);
+            files.insert(relative.to_string_lossy().into_owned(), (metadata.len(), modified));
+        }
+        files
+    }
+
+    fn add_file(&self, writer: &IndexWriter, path: &str) -> Result<()> {
+        let bytes = fs::read(self.root.join(path))?;
+        // Binary files are recorded as indexed without adding any chunks.
+        let Ok(text) = String::from_utf8(bytes) else {
+            return Ok(());
+        };
+        if text.contains('\0') {
+            return Ok(());
+        }
+        let lines: Vec<&str> = text.lines().collect();
+        for (i, chunk) in lines.chunks(CHUNK_LINES).enumerate() {
+            writer.add_document(doc!(
+                self.fields.path => path,
+                self.fields.line => (i * CHUNK_LINES + 1) as u64,
+                self.fields.text => chunk.join("\n"),
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn search(&self, query: &str, scope: Option<&Path>, limit: usize) -> Result<Vec<SearchHit>> {
+        // Picks up commits made by other servers sharing the index.
+        self.reader.reload()?;
+        let searcher = self.reader.searcher();
+        let (mut parsed, _) = QueryParser::for_index(&self.index, vec![self.fields.text]).parse_query_lenient(query);
+        if let Some(scope) = scope {
+            let pattern = format!("{}(/.*)?", regex::escape(&scope.to_string_lossy()));
+            let filter = RegexQuery::from_pattern(&pattern, self.fields.path)?;
+            parsed = Box::new(BooleanQuery::new(vec![
+                (Occur::Must, parsed),
+                (Occur::Must, Box::new(ConstScoreQuery::new(Box::new(filter), 0.0))),
+            ]));
+        }
+
+        let terms: HashSet<String> = tokenize(query).into_iter().map(|token| token.text).collect();
+        let mut hits = Vec::new();
+        for (score, address) in searcher.search(&parsed, &TopDocs::with_limit(limit))? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let path = doc.get_first(self.fields.path).and_then(|v| v.as_str()).unwrap_or_default();
+            let first = doc.get_first(self.fields.line).and_then(|v| v.as_u64()).unwrap_or(1);
+            let text = doc.get_first(self.fields.text).and_then(|v| v.as_str()).unwrap_or_default();
+            let (line, snippet) = snippet(text, first, &terms);
+            hits.push(SearchHit {
+                path: self.root.join(path).display().to_string(),
+                line,
+                score,
+                snippet,
+            });
+        }
+        Ok(hits)
+    }
+}
+
+fn schema() -> (Schema, Fields) {
+    let mut builder = Schema::builder();
+    let path = builder.add_text_field("path", STRING | STORED);
+    let line = builder.add_u64_field("line", STORED);
+    let indexing = TextFieldIndexing::default()
+        .set_tokenizer(TOKENIZER)
+        .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+    let text = builder.add_text_field("text", TextOptions::default().set_indexing_options(indexing).set_stored());
+    (builder.build(), Fields { path, line, text })
+}
+
+fn open_index(dir: &Path, schema: &Schema) -> Result<Index> {
+    fs::create_dir_all(dir).map_err(|e| anyhow!("Failed to create search index '{}': {}", dir.display(), e))?;
+    let index = Index::open_or_create(MmapDirectory::open(dir)?, schema.clone())?;
+    index.tokenizers().register(TOKENIZER, CodeTokenizer);
+    Ok(index)
+}
+
+fn read_payload(index: &Index) -> Result<Payload> {
+    match index.load_metas()?.payload {
+        Some(payload) => Ok(serde_json::from_str(&payload)?),
+        None => Ok(Payload {
+            format: FORMAT,
+            files: BTreeMap::new(),
+        }),
+    }
+}
+
+/// Marks the index stale on any change under `root` other than in `.git` or the index itself.
+fn watch(root: &Path, index_dir: &Path, stale: Arc<AtomicBool>) -> notify::Result<RecommendedWatcher> {
+    let index_dir = index_dir.to_path_buf();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let relevant = match event {
+            // Indexing reads every file; those reads are not changes.
+            Ok(event) if event.kind.is_access() => false,
+            Ok(event) => {
+                event.paths.is_empty()
+                    || event.paths.iter().any(|path| {
+                        !path.starts_with(&index_dir) && !path.components().any(|c| c.as_os_str() == ".git")
+                    })
+            }
+            Err(_) => true,
+        };
+        if relevant {
+            stale.store(true, Ordering::Relaxed);
+        }
+    })?;
+    watcher.watch(root, RecursiveMode::Recursive)?;
+    Ok(watcher)
+}
+
+/// The line of `chunk` containing the most query terms, numbered from
+/// `first`, and a snippet of the lines around it.
+fn snippet(chunk: &str, first: u64, terms: &HashSet<String>) -> (u64, String) {
+    let lines: Vec<&str> = chunk.lines().collect();
+    let best = lines
+        .iter()
+        .enumerate()
+        .max_by_key(|(i, line)| {
+            let matched: HashSet<String> = tokenize(line)
+                .into_iter()
+                .map(|token| token.text)
+                .filter(|word| terms.contains(word))
+                .collect();
+            (matched.len(), Reverse(*i))
+        })
+        .map_or(0, |(i, _)| i);
+    let start = best.saturating_sub(2);
+    let end = (best + 3).min(lines.len());
+    let snippet = (start..end)
+        .map(|i| {
+            let marker = if i == best { ':' } else { '-' };
+            format!("{}{}{}", first + i as u64, marker, lines[i])
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    (first + best as u64, snippet)
+}
+
+/// Splits text into words, and identifiers into their parts as well, so
+/// `refreshAuthToken` and `refresh_auth_token` both match `auth refresh`.
+/// Every token is lowercased.
+#[derive(Clone)]
+struct CodeTokenizer;
+
+impl Tokenizer for CodeTokenizer {
+    type TokenStream<'a> = CodeTokens;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> CodeTokens {
+        CodeTokens {
+            tokens: tokenize(text),
+            next: 0,
+        }
+    }
+}
+
+struct CodeTokens {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl TokenStream for CodeTokens {
+    fn advance(&mut self) -> bool {
+        self.next += 1;
+        self.next <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.next - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.next - 1]
+    }
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    let mut start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (start, c.is_alphanumeric() || c == '_') {
+            (None, true) => start = Some(i),
+            (Some(from), false) => {
+                start = None;
+                let word = &text[from..i];
+                if word.len() > MAX_WORD_BYTES {
+                    continue;
+                }
+                let parts = identifier_parts(word);
+                // The whole identifier shares the position of its first part.
+                if parts.len() > 1 {
+                    tokens.push(token(from, word, position));
+                }
+                for (offset, part) in parts {
+                    tokens.push(token(from + offset, part, position));
+                    position += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// Splits an identifier at underscores, lower-to-upper case changes and the
+/// end of acronyms (`HTTPServer` is `HTTP`, `Server`), with byte offsets.
+fn identifier_parts(word: &str) -> Vec<(usize, &str)> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (k, &(i, c)) in chars.iter().enumerate() {
+        if c == '_' {
+            if i > start {
+                parts.push((start, &word[start..i]));
+            }
+            start = i + 1;
+            continue;
+        }
+        if i == start {
+            continue;
+        }
+        let previous = chars[k - 1].1;
+        let next = chars.get(k + 1).map(|&(_, c)| c);
+        let boundary = c.is_uppercase()
+            && (previous.is_lowercase()
+                || previous.is_numeric()
+                || (previous.is_uppercase() && next.is_some_and(char::is_lowercase)));
+        if boundary {
+            parts.push((start, &word[start..i]));
+            start = i;
+        }
+    }
+    if start < word.len() {
+        parts.push((start, &word[start..]));
+    }
+    parts
+}
+
+fn token(offset: usize, text: &str, position: usize) -> Token {
+    Token {
+        offset_from: offset,
+        offset_to: offset + text.len(),
+        position,
+        text: text.to_lowercase(),
+        position_length: 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        tokenize(text).into_iter().map(|token| token.text).collect()
+    }
+
+    #[test]
+    fn identifiers_are_indexed_whole_and_in_parts() {
+        assert_eq!(words("refreshAuthToken"), ["refreshauthtoken", "refresh", "auth", "token"]);
+        assert_eq!(words("refresh_auth_token"), ["refresh_auth_token", "refresh", "auth", "token"]);
+        assert_eq!(words("HTTPServer v2Api"), ["httpserver", "http", "server", "v2api", "v2", "api"]);
+        assert_eq!(words("fn main() { x += 1; }"), ["fn", "main", "x", "1"]);
+    }
+
+    #[test]
+    fn identifier_parts_share_the_position_of_the_first() {
+        let positions: Vec<usize> = tokenize("call fooBar baz").iter().map(|token| token.position).collect();
+        assert_eq!(positions, [0, 1, 1, 2, 3]);
+        let token = &tokenize("  fooBar")[2];
+        assert_eq!((token.offset_from, token.offset_to), (5, 8));
+    }
+
+    #[test]
+    fn long_words_are_not_indexed() {
+        let blob = "a".repeat(MAX_WORD_BYTES + 1);
+        assert_eq!(words(&format!("key {} value", blob)), ["key", "value"]);
+    }
+
+    #[test]
+    fn least_recently_searched_roots_are_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::new(&SearchConfig {
+            dir: Some(dir.path().join("index")),
+            ..SearchConfig::default()
+        })
+        .unwrap();
+        let roots: Vec<PathBuf> = (0..=MAX_OPEN_ROOTS).map(|i| dir.path().join(format!("root{}", i))).collect();
+        for root in &roots {
+            fs::create_dir(root).unwrap();
+        }
+        for root in &roots[..MAX_OPEN_ROOTS] {
+            index.root(root).unwrap();
+        }
+        // Searching the first root again makes the second the oldest.
+        index.root(&roots[0]).unwrap();
+        index.root(&roots[MAX_OPEN_ROOTS]).unwrap();
+
+        let open = index.roots.lock().unwrap();
+        assert_eq!(open.len(), MAX_OPEN_ROOTS);
+        assert!(open.contains_key(&roots[0]));
+        assert!(!open.contains_key(&roots[1]));
+    }
+
+    #[test]
+    fn snippet_centres_the_line_with_most_terms() {
+        let chunk = "one\ntwo\nthree auth\nrefresh auth token\nfive\nsix\nseven";
+        let terms = ["refresh", "auth"].map(String::from).into();
+        let (line, snippet) = snippet(chunk, 11, &terms);
+        assert_eq!(line, 14);
+        assert_eq!(snippet, "12-two\n13-three auth\n14:refresh auth token\n15-five\n16-six");
+    }
+
+    #[test]
+    fn snippet_stops_at_the_chunk_edges() {
+        let terms = ["first"].map(String::from).into();
+        let (line, snippet) = snippet("first\nsecond", 1, &terms);
+        assert_eq!(line, 1);
+        assert_eq!(snippet, "1:first\n2-second");
+        // Without any match the first line is shown.
+        assert_eq!(super::snippet("a\nb", 1, &HashSet::new()).0, 1);
+    }
+}
@@ -0,0 +1,625 @@
+//! The MCP server: serves a [`ToolRegistry`] through [`Policy`] alongside
+//! any downstream servers, plus completions, logging and workspace roots.
+
+use anyhow::Result;
+use rmcp::{
+    handler::server::ServerHandler,
+    model::*,
+    service::{NotificationContext, Peer, RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+
+use crate::aggregator::{self, Aggregator};
+use crate::audit::Kind;
+use crate::auth::{Authenticator, Identity, RateLimiter};
+use crate::checkpoints::{CheckpointStore, Turns};
+use crate::completion;
+use crate::config::{Config, ShellConfig};
+use crate::logging::{LogHub, SessionLog};
+use crate::metrics::metrics;
+use crate::openwebui::OpenWebUi;
+use crate::pages::ResultStore;
+use crate::policy::Policy;
+use crate::redact::{Findings, Redactor};
+use crate::registry::ToolRegistry;
+use crate::scratchpad::Scratchpad;
+use crate::search::SearchIndex;
+use crate::shell_sessions::ShellSessions;
+use crate::tool::{Tool, ToolContext};
+use crate::tools;
+use crate::workspace::Workspace;
+
+/// Connected clients with the session each belongs to.
+type Peers = Arc<Mutex<Vec<(String, Peer<RoleServer>)>>>;
+
+#[derive(Clone)]
+pub struct McpServer {
+    /// The tools before plugins and config are applied, kept for reloads.
+    base: Arc<ToolRegistry>,
+    loaded: Arc<RwLock<Arc<Loaded>>>,
+    /// Serializes reloads.
+    reloading: Arc<tokio::sync::Mutex<()>>,
+    /// Connected clients by session, told when the tool list changes.
+    peers: Peers,
+    /// Sent to when a downstream server's tool list changes.
+    tools_changed: mpsc::UnboundedSender<()>,
+    /// Bearer tokens of the HTTP and OpenAPI listeners, replaced on reload.
+    authenticator: Arc<Authenticator>,
+    workspace: Arc<Workspace>,
+    results: Arc<ResultStore>,
+    turns: Arc<Turns>,
+    shells: Arc<ShellSessions>,
+    logs: Arc<LogHub>,
+    log: Arc<SessionLog>,
+    tools_per_page: usize,
+    /// This network session's remaining tool calls; `None` when unlimited.
+    budget: Option<Arc<Mutex<RateLimiter>>>,
+    /// Logs the end of a network session once its last handle is dropped.
+    _ended: Option<Arc<SessionEnd>>,
+}
+
+/// Everything built from the config that a reload replaces. Calls already
+/// running keep the state they started with.
+struct Loaded {
+    config: Config,
+    tools: Arc<ToolRegistry>,
+    plugins: Vec<Arc<dyn Tool>>,
+    policy: Arc<Policy>,
+    downstream: Arc<Aggregator>,
+    checkpoints: Option<Arc<CheckpointStore>>,
+    search: Option<Arc<SearchIndex>>,
+    scratchpad: Option<Arc<Scratchpad>>,
+    shell: Arc<ShellConfig>,
+}
+
+impl Loaded {
+    /// Builds the state for `config`, reusing the parts of `previous` whose section did not change.
+    async fn build(
+        base: &ToolRegistry,
+        config: &Config,
+        tools_changed: &mpsc::UnboundedSender<()>,
+        previous: Option<&Loaded>,
+    ) -> Result<Self> {
+        let unchanged = |same: &dyn Fn(&Config) -> bool| previous.filter(|previous| same(&previous.config));
+        let mut tools = base.clone();
+
+        let plugins = match unchanged(&|old| old.plugins == config.plugins) {
+            Some(previous) => previous.plugins.clone(),
+            None => load_plugins(config).await?,
+        };
+
+        let downstream = match unchanged(&|old| old.downstream == config.downstream) {
+            Some(previous) => previous.downstream.clone(),
+            None => Arc::new(Aggregator::connect(&config.downstream, tools_changed.clone()).await?),
+        };
+        let checkpoints = if !config.checkpoints.enabled {
+            for tool in ["list_checkpoints", "diff_checkpoint", "restore_checkpoint"] {
+                tools.remove(tool);
+            }
+            None
+        } else if let Some(previous) = unchanged(&|old| old.checkpoints == config.checkpoints) {
+            previous.checkpoints.clone()
+        } else {
+            Some(Arc::new(CheckpointStore::new(&config.checkpoints)?))
+        };
+        let search = if !config.search.enabled {
+            tools.remove("search_code");
+            None
+        } else if let Some(previous) = unchanged(&|old| old.search == config.search) {
+            previous.search.clone()
+        } else {
+            Some(Arc::new(SearchIndex::new(&config.search)?))
+        };
+        let scratchpad = if !config.scratchpad.enabled {
+            for tool in ["todo_read", "todo_write", "memory_get", "memory_set", "memory_search"] {
+                tools.remove(tool);
+            }
+            None
+        } else if let Some(previous) = unchanged(&|old| old.scratchpad == config.scratchpad) {
+            previous.scratchpad.clone()
+        } else {
+            Some(Arc::new(Scratchpad::new(&config.scratchpad)?))
+        };
+        if config.openwebui.url.is_some() {
+            let client = Arc::new(OpenWebUi::new(&config.openwebui)?);
+            for tool in tools::openwebui::tools(client) {
+                tools.register_arc(tool);
+            }
+        }
+        // Registered last and never over another tool, built-in or not,
+        // so a plugin can't stand in for one. Names with the downstream
+        // separator are refused too, as they would shadow downstream tools.
+        for plugin in &plugins {
+            if plugin.name().contains(aggregator::SEPARATOR) {
+                tracing::warn!(tool = plugin.name(), "Skipping plugin tool whose name contains '{}'", aggregator::SEPARATOR);
+                continue;
+            }
+            if base.get(plugin.name()).is_some() || tools.get(plugin.name()).is_some() {
+                tracing::warn!(tool = plugin.name(), "Skipping plugin tool whose name is already taken");
+                continue;
+            }
+            tools.register_arc(plugin.clone());
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            tools: Arc::new(tools),
+            plugins,
+            policy: Arc::new(Policy::new(config)?),
+            downstream,
+            checkpoints,
+            search,
+            scratchpad,
+            shell: Arc::new(config.shell.clone()),
+        })
+    }
+
+    async fn find_tool(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        match self.tools.get(name) {
+            Some(tool) => Some(tool),
+            None => self.downstream.tool(name).await,
+        }
+    }
+
+    /// Definitions of every registered and downstream tool.
+    async fn definitions(&self) -> Vec<rmcp::model::Tool> {
+        let mut tools = self.tools.definitions();
+        tools.extend(self.downstream.list_tools().await);
+        tools
+    }
+}
+
+#[cfg(feature = "wasm-plugins")]
+async fn load_plugins(config: &Config) -> Result<Vec<Arc<dyn Tool>>> {
+    let tools = crate::plugins::load_tools(&config.plugins).await?;
+    Ok(tools.into_iter().map(|tool| Arc::new(tool) as Arc<dyn Tool>).collect())
+}
+
+#[cfg(not(feature = "wasm-plugins"))]
+async fn load_plugins(config: &Config) -> Result<Vec<Arc<dyn Tool>>> {
+    if config.plugins.dir.is_some() {
+        tracing::warn!("Plugins are configured but this build lacks the `wasm-plugins` feature");
+    }
+    Ok(Vec::new())
+}
+
+/// Sends `tools/list_changed` to every connected client.
+async fn notify_tools_changed(peers: &Peers) {
+    let peers: Vec<Peer<RoleServer>> = {
+        let mut peers = peers.lock().unwrap();
+        peers.retain(|(_, peer)| !peer.is_transport_closed());
+        peers.iter().map(|(_, peer)| peer.clone()).collect()
+    };
+    for peer in peers {
+        if let Err(e) = peer.notify_tool_list_changed().await {
+            tracing::debug!(error = %e, "Failed to send tools/list_changed");
+        }
+    }
+}
+
+/// Logs when a network session's state is dropped, on disconnect or idle
+/// timeout, and forgets its client.
+struct SessionEnd {
+    session: String,
+    peers: Peers,
+}
+
+impl Drop for SessionEnd {
+    fn drop(&mut self) {
+        self.peers.lock().unwrap().retain(|(session, _)| *session != self.session);
+        tracing::info!(session = %self.session, "Session ended");
+        metrics().session_ended();
+    }
+}
+
+impl McpServer {
+    /// A server offering the built-in tools and any configured plugins.
+    pub async fn new(config: &Config, logs: Arc<LogHub>) -> Result<Self> {
+        Self::with_tools(ToolRegistry::builtin(), config, logs).await
+    }
+
+    /// A server offering `tools` and any configured plugins.
+    pub async fn with_tools(tools: ToolRegistry, config: &Config, logs: Arc<LogHub>) -> Result<Self> {
+        let workspace = Arc::new(Workspace::new(std::env::current_dir()?));
+        let (tools_changed, mut changes) = mpsc::unbounded_channel();
+        let loaded = Loaded::build(&tools, config, &tools_changed, None).await?;
+        let peers = Peers::default();
+        let notified = peers.clone();
+        tokio::spawn(async move {
+            while changes.recv().await.is_some() {
+                notify_tools_changed(&notified).await;
+            }
+        });
+        let turns = Arc::new(Turns::new());
+        // The stdio client runs the server, so it sees the whole process's events.
+        let log = logs.session(turns.session());
+        log.grant_admin();
+
+        Ok(Self {
+            base: Arc::new(tools),
+            loaded: Arc::new(RwLock::new(Arc::new(loaded))),
+            authenticator: Arc::new(Authenticator::new(&config.auth)),
+            reloading: Arc::new(tokio::sync::Mutex::new(())),
+            peers,
+            tools_changed,
+            workspace,
+            results: Arc::new(ResultStore::new(&config.pagination)),
+            turns,
+            shells: Arc::new(ShellSessions::new()),
+            log,
+            logs,
+            tools_per_page: config.pagination.tools_per_page.max(1),
+            budget: None,
+            _ended: None,
+        })
+    }
+
+    /// A handler for a new network session. Tools, policy, downstream servers
+    /// and the checkpoint store are shared; roots, result pages, shell
+    /// sessions, checkpoints, log level and call budget are the session's own.
+    pub fn for_session(&self) -> Self {
+        let turns = Arc::new(Turns::isolated());
+        tracing::info!(session = turns.session(), "Session started");
+        metrics().session_started();
+        let sessions = &self.loaded().config.http.sessions;
+        Self {
+            workspace: Arc::new(Workspace::restricted(
+                self.workspace.fallback().to_path_buf(),
+                &sessions.allowed_roots,
+            )),
+            results: Arc::new(self.results.fresh()),
+            shells: Arc::new(ShellSessions::new()),
+            log: self.logs.session(turns.session()),
+            budget: sessions
+                .requests_per_minute
+                .map(|limit| Arc::new(Mutex::new(RateLimiter::new(limit)))),
+            _ended: Some(Arc::new(SessionEnd {
+                session: turns.session().to_string(),
+                peers: self.peers.clone(),
+            })),
+            turns,
+            ..self.clone()
+        }
+    }
+
+    /// The bearer tokens listeners check, kept current across reloads.
+    pub fn authenticator(&self) -> Arc<Authenticator> {
+        self.authenticator.clone()
+    }
+
+    fn loaded(&self) -> Arc<Loaded> {
+        self.loaded.read().unwrap().clone()
+    }
+
+    /// Swaps in the state for `config`, shared by every session. Nothing
+    /// changes when the config is invalid or any part fails to load.
+    /// Connected clients are told when the tool list changed.
+    pub async fn reload(&self, config: &Config) -> Result<()> {
+        config.validate()?;
+        let _reloading = self.reloading.lock().await;
+        let old = self.loaded();
+        let restart_only = [
+            ("http", old.config.http != config.http),
+            ("logging", old.config.logging != config.logging),
+            ("pagination", old.config.pagination != config.pagination),
+            ("metrics", old.config.metrics != config.metrics),
+        ];
+        for (section, _) in restart_only.iter().filter(|(_, changed)| *changed) {
+            tracing::warn!(section, "Config section changed but only takes effect after a restart");
+        }
+
+        let new = Loaded::build(&self.base, config, &self.tools_changed, Some(&old)).await?;
+        let changed = old.definitions().await != new.definitions().await;
+        self.authenticator.reload(&config.auth)?;
+        *self.loaded.write().unwrap() = Arc::new(new);
+        tracing::info!(tools_changed = changed, "Config reloaded");
+        if changed {
+            notify_tools_changed(&self.peers).await;
+        }
+        Ok(())
+    }
+
+    /// A registered tool or, failing that, a downstream one.
+    pub async fn find_tool(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.loaded().find_tool(name).await
+    }
+
+    /// Definitions of every tool `identity` may call, registered ones first.
+    pub async fn tools_for(&self, identity: Option<&Identity>) -> Vec<rmcp::model::Tool> {
+        let mut tools = self.loaded().definitions().await;
+        if let Some(identity) = identity {
+            tools.retain(|tool| identity.can_use(&tool.name));
+        }
+        tools
+    }
+
+    /// Calls a tool through the policy, exactly as `tools/call` does.
+    pub async fn call(
+        &self,
+        name: &str,
+        arguments: Option<JsonObject>,
+        identity: Option<&Identity>,
+        peer: Option<Peer<RoleServer>>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Some(budget) = &self.budget {
+            if !budget.lock().unwrap().try_acquire() {
+                tracing::warn!(session = self.turns.session(), tool = name, "Session rate limit exceeded");
+                metrics().rejection("session_rate_limit");
+                return Err(McpError::invalid_request("Rate limit exceeded for this session", None));
+            }
+        }
+        let loaded = self.loaded();
+        let tool = loaded
+            .find_tool(name)
+            .await
+            .ok_or_else(|| McpError::invalid_params(format!("Unknown tool '{}'", name), None))?;
+        // Events during the call go to this session's client only.
+        let span = tracing::info_span!("tool_call", session = self.turns.session(), tool = name);
+        loaded
+            .policy
+            .call(tool.as_ref(), arguments, self.tool_context(&loaded, peer), identity)
+            .instrument(span)
+            .await
+    }
+
+    fn tool_context(&self, loaded: &Loaded, peer: Option<Peer<RoleServer>>) -> ToolContext {
+        ToolContext {
+            workspace: self.workspace.clone(),
+            results: self.results.clone(),
+            checkpoints: loaded.checkpoints.clone(),
+            search: loaded.search.clone(),
+            scratchpad: loaded.scratchpad.clone(),
+            shell: loaded.shell.clone(),
+            turns: self.turns.clone(),
+            shells: self.shells.clone(),
+            peer,
+            redactor: None,
+            redacted: Arc::default(),
+        }
+    }
+
+    /// Fetches the client's roots in the background; the client may only
+    /// answer once the notification that triggered this has been handled.
+    fn refresh_roots(&self, peer: Peer<RoleServer>) {
+        let workspace = self.workspace.clone();
+        let session = self.turns.session().to_string();
+        tokio::spawn(async move {
+            match workspace.refresh(&peer).await {
+                Ok(()) => tracing::info!(session, roots = ?workspace.roots(), "Workspace roots updated"),
+                Err(e) => tracing::warn!(session, error = %e, "Failed to refresh workspace roots"),
+            }
+        });
+    }
+
+    /// Sends this session's log messages to `peer`, and the whole process's
+    /// when it authenticated with the admin token.
+    fn attach_log(&self, peer: Peer<RoleServer>, extensions: &Extensions) {
+        if Identity::from_extensions(extensions).is_some_and(|identity| self.logs.is_admin_token(&identity.name)) {
+            self.log.grant_admin();
+        }
+        self.log.attach(peer);
+    }
+}
+
+impl ServerHandler for McpServer {
+    fn get_info(&self) -> ServerInfo {
+        let mut capabilities = ServerCapabilities::builder()
+            .enable_tools()
+            .enable_tool_list_changed()
+            .enable_logging()
+            .enable_completions()
+            .build();
+        // Resources and prompts only come from downstream servers.
+        if !self.loaded().downstream.is_empty() {
+            capabilities.resources = Some(ResourcesCapability::default());
+            capabilities.prompts = Some(PromptsCapability::default());
+        }
+        ServerInfo {
+            server_info: Implementation {
+                name: "mcp-server-rust".into(),
+                version: "0.1.0".into(),
+                ..Default::default()
+            },
+            capabilities,
+            ..Default::default()
+        }
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let identity = Identity::from_extensions(&context.extensions);
+        self.call(&request.name, request.arguments, identity, Some(context.peer))
+            .await
+    }
+
+    async fn complete(
+        &self,
+        request: CompleteRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CompleteResult, McpError> {
+        let loaded = self.loaded();
+        let identity = Identity::from_extensions(&context.extensions);
+        let argument = &request.argument;
+        // Downstream completions need the prompt or resource they complete;
+        // local ones the tool whose argument they suggest.
+        let name = match loaded.downstream.completion_target(&request.r#ref) {
+            Some(name) => name,
+            None => match argument.name.as_str() {
+                "path" => "list_directory".to_string(),
+                "cmd" => "shell_command".to_string(),
+                _ => return Ok(CompleteResult::default()),
+            },
+        };
+        let completions = async {
+            if let Some(result) = loaded.downstream.complete(&request).await {
+                return result;
+            }
+            let values = match argument.name.as_str() {
+                "path" => completion::complete_path(&self.workspace, &argument.value),
+                _ => completion::complete_command(&self.workspace, &loaded.shell.allowed_commands, &argument.value),
+            };
+            Ok(CompleteResult {
+                completion: completion::completion_info(values),
+            })
+        };
+        let result = loaded
+            .policy
+            .access(
+                Kind::Completion,
+                &name,
+                self.turns.session(),
+                identity,
+                completions,
+                |redactor, result: &mut CompleteResult| {
+                    let mut findings = Findings::new();
+                    for value in &mut result.completion.values {
+                        *value = redactor.redact(value, &mut findings);
+                    }
+                    findings
+                },
+            )
+            .await;
+        // A caller who may not use the tool gets no suggestions rather than an error.
+        match result {
+            Err(_) if identity.is_some_and(|identity| !identity.can_use(&name)) => Ok(CompleteResult::default()),
+            result => result,
+        }
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.attach_log(context.peer, &context.extensions);
+        self.log.set_level(request.level);
+        tracing::debug!(session = self.turns.session(), client_level = ?request.level, "Client log level changed");
+        Ok(())
+    }
+
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        self.attach_log(context.peer.clone(), &context.extensions);
+        {
+            let mut peers = self.peers.lock().unwrap();
+            peers.retain(|(_, peer)| !peer.is_transport_closed());
+            peers.push((self.turns.session().to_string(), context.peer.clone()));
+        }
+        let supports_roots = context
+            .peer
+            .peer_info()
+            .is_some_and(|info| info.capabilities.roots.is_some());
+        if supports_roots {
+            self.refresh_roots(context.peer);
+        }
+    }
+
+    async fn on_roots_list_changed(&self, context: NotificationContext<RoleServer>) {
+        self.refresh_roots(context.peer);
+    }
+
+    async fn list_tools(
+        &self,
+        request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let mut tools = self
+            .tools_for(Identity::from_extensions(&context.extensions))
+            .await;
+        // The cursor is the index of the first tool on the page.
+        let start = match request.and_then(|request| request.cursor) {
+            Some(cursor) => cursor
+                .parse::<usize>()
+                .ok()
+                .filter(|start| *start <= tools.len())
+                .ok_or_else(|| McpError::invalid_params(format!("Invalid cursor '{}'", cursor), None))?,
+            None => 0,
+        };
+        let end = (start + self.tools_per_page).min(tools.len());
+        Ok(ListToolsResult {
+            next_cursor: (end < tools.len()).then(|| end.to_string()),
+            tools: tools.drain(start..end).collect(),
+            meta: None,
+        })
+    }
+
+    // Resources and prompts are filtered and checked against the caller's
+    // token like tools, and their contents are redacted and audited.
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let identity = Identity::from_extensions(&context.extensions);
+        let mut resources = self.loaded().downstream.list_resources().await;
+        resources.retain(|resource| identity.is_none_or(|identity| identity.can_use(&resource.raw.uri)));
+        Ok(ListResourcesResult::with_all_items(resources))
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListResourceTemplatesResult, McpError> {
+        let identity = Identity::from_extensions(&context.extensions);
+        let mut templates = self.loaded().downstream.list_resource_templates().await;
+        templates.retain(|template| identity.is_none_or(|identity| identity.can_use(&template.raw.uri_template)));
+        Ok(ListResourceTemplatesResult::with_all_items(templates))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let loaded = self.loaded();
+        loaded
+            .policy
+            .access(
+                Kind::Resource,
+                &request.uri,
+                self.turns.session(),
+                Identity::from_extensions(&context.extensions),
+                loaded.downstream.read_resource(&request.uri),
+                Redactor::redact_resource,
+            )
+            .await
+    }
+
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        let identity = Identity::from_extensions(&context.extensions);
+        let mut prompts = self.loaded().downstream.list_prompts().await;
+        prompts.retain(|prompt| identity.is_none_or(|identity| identity.can_use(&prompt.name)));
+        Ok(ListPromptsResult::with_all_items(prompts))
+    }
+
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        let loaded = self.loaded();
+        let name = request.name.clone();
+        loaded
+            .policy
+            .access(
+                Kind::Prompt,
+                &name,
+                self.turns.session(),
+                Identity::from_extensions(&context.extensions),
+                loaded.downstream.get_prompt(request),
+                Redactor::redact_prompt,
+            )
+            .await
+    }
+}
@@ -0,0 +1,346 @@
+//! State kept between `session_exec` calls: a working directory and
+//! environment variables per shell session.
+//!
+//! Sessions hold no process. Each command still runs on its own through the
+//! same allowlist as `shell_command`; `cd`, `export` and `unset` are handled
+//! here and only change what the next command starts with. Commands see the
+//! session's variables on top of a cleared environment.
+
+use anyhow::{anyhow, bail, Result};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::workspace::Workspace;
+
+/// Most sessions open at once; opening another closes the least recently used.
+const MAX_SESSIONS: usize = 32;
+/// Sessions unused for this long are closed.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone)]
+pub struct ShellSession {
+    pub cwd: PathBuf,
+    previous_cwd: Option<PathBuf>,
+    /// Variables set in the session. `None` unsets one the server inherited.
+    pub env: BTreeMap<String, Option<String>>,
+    last_used: Instant,
+}
+
+impl ShellSession {
+    /// Changes directory like `cd`: no argument goes to the primary root and `-` to the previous directory.
+    pub fn cd(&mut self, target: Option<&str>, workspace: &Workspace) -> Result<()> {
+        let dir = match target {
+            None => workspace.primary(),
+            Some("-") => self
+                .previous_cwd
+                .clone()
+                .ok_or_else(|| anyhow!("cd: no previous directory"))?,
+            Some(target) => workspace
+                .resolve_in(&self.cwd, target)
+                .map_err(|_| anyhow!("cd: {}: outside the workspace", target))?,
+        };
+        if !dir.is_dir() {
+            bail!("cd: {}: not a directory", target.unwrap_or_default());
+        }
+        self.previous_cwd = Some(std::mem::replace(&mut self.cwd, dir));
+        Ok(())
+    }
+
+    /// Sets a variable from a `NAME=value` assignment.
+    pub fn export(&mut self, assignment: &str) -> Result<()> {
+        let (name, value) = assignment.split_once('=').unwrap_or((assignment, ""));
+        check_settable(name)?;
+        self.env.insert(name.to_string(), Some(value.to_string()));
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<()> {
+        check_name(name)?;
+        self.env.insert(name.to_string(), None);
+        Ok(())
+    }
+
+    /// The variables set in the session, as `NAME=value` lines.
+    pub fn exported(&self) -> String {
+        self.env
+            .iter()
+            .filter_map(|(name, value)| Some(format!("{}={}\n", name, value.as_ref()?)))
+            .collect()
+    }
+
+    /// Expands `$NAME` and `${NAME}` from the session's variables, except
+    /// inside single quotes. Values are quoted so they stay one argument.
+    pub fn expand(&self, command: &str) -> String {
+        let mut out = String::with_capacity(command.len());
+        let mut chars = command.chars().peekable();
+        let (mut single, mut double) = (false, false);
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if !single => {
+                    out.push(c);
+                    out.extend(chars.next());
+                }
+                '\'' if !double => {
+                    single = !single;
+                    out.push(c);
+                }
+                '"' if !single => {
+                    double = !double;
+                    out.push(c);
+                }
+                '$' if !single => {
+                    let braced = chars.next_if_eq(&'{').is_some();
+                    let mut name = String::new();
+                    while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                        name.push(c);
+                    }
+                    if braced && chars.next_if_eq(&'}').is_none() || name.is_empty() {
+                        // Not a variable reference; keep it as written.
+                        out.push('$');
+                        if braced {
+                            out.push('{');
+                        }
+                        out.push_str(&name);
+                        continue;
+                    }
+                    let value = self.env.get(&name).cloned().flatten().unwrap_or_default();
+                    if double {
+                        for c in value.chars() {
+                            if matches!(c, '"' | '\\' | '$' | '`') {
+                                out.push('\\');
+                            }
+                            out.push(c);
+                        }
+                    } else {
+                        out.push_str(&shlex::try_quote(&value).map_or_else(|_| "''".into(), |quoted| quoted.into_owned()));
+                    }
+                }
+                _ => out.push(c),
+            }
+        }
+        out
+    }
+}
+
+/// Splits a command list at `&&`, `||` and `;` outside quotes. Each command
+/// comes with the operator before it, `;` for the first.
+pub fn split_commands(line: &str) -> Result<Vec<(&'static str, String)>> {
+    let mut commands = Vec::new();
+    let mut operator = ";";
+    let mut current = String::new();
+    let mut chars = line.chars().peekable();
+    let (mut single, mut double) = (false, false);
+    while let Some(c) = chars.next() {
+        let next = match c {
+            '\\' if !single => {
+                current.push(c);
+                current.extend(chars.next());
+                continue;
+            }
+            '\'' if !double => {
+                single = !single;
+                None
+            }
+            '"' if !single => {
+                double = !double;
+                None
+            }
+            ';' if !single && !double => Some(";"),
+            '&' if !single && !double && chars.next_if_eq(&'&').is_some() => Some("&&"),
+            '|' if !single && !double && chars.next_if_eq(&'|').is_some() => Some("||"),
+            _ => None,
+        };
+        match next {
+            Some(next) => {
+                if current.trim().is_empty() {
+                    bail!("Syntax error near '{}'", next);
+                }
+                commands.push((operator, std::mem::take(&mut current)));
+                operator = next;
+            }
+            None => current.push(c),
+        }
+    }
+    if current.trim().is_empty() {
+        if operator != ";" || commands.is_empty() {
+            bail!("Syntax error: '{}' at the end of the command", operator);
+        }
+    } else {
+        commands.push((operator, current));
+    }
+    Ok(commands)
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if !is_valid_name(name) {
+        bail!("'{}' is not a valid variable name", name);
+    }
+    Ok(())
+}
+
+/// Variables that change which program runs, what it loads or which
+/// config files it reads, so setting them would get around the allowlist.
+const PROTECTED: &[&str] = &[
+    "PATH", "IFS", "ENV", "BASH_ENV", "SHELLOPTS", "BASHOPTS", "CDPATH", "GLOBIGNORE", "PS4",
+    "GCONV_PATH", "HOSTALIASES", "LOCPATH", "NLSPATH", "GREP_OPTIONS", "JAVA_TOOL_OPTIONS",
+    "_JAVA_OPTIONS", "HOME", "SHELL", "PAGER", "EDITOR", "VISUAL", "BROWSER", "LESSOPEN",
+    "LESSCLOSE", "MANPAGER", "TMPDIR",
+];
+const PROTECTED_PREFIXES: &[&str] = &[
+    "LD_", "DYLD_", "BASH_FUNC_", "PYTHON", "NODE_", "PERL", "RUBY", "MALLOC_", "GLIBC_", "GIT_",
+    "XDG_", "SSH_", "SUDO_",
+];
+
+/// Checks that a session may set `name`: a valid name that isn't protected.
+pub fn check_settable(name: &str) -> Result<()> {
+    check_name(name)?;
+    if PROTECTED.contains(&name) || PROTECTED_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+        bail!("'{}' cannot be set in a shell session", name);
+    }
+    Ok(())
+}
+
+/// Whether `name` can be used as an environment variable in a session.
+fn is_valid_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The open shell sessions, by random id.
+pub struct ShellSessions {
+    sessions: Mutex<HashMap<String, ShellSession>>,
+}
+
+impl ShellSessions {
+    pub fn new() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Opens a session and returns its id.
+    pub fn open(&self, cwd: PathBuf, env: BTreeMap<String, Option<String>>) -> String {
+        let id = format!("{:016x}", RandomState::new().hash_one(Instant::now()));
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, session| now - session.last_used < IDLE_TIMEOUT);
+        if sessions.len() >= MAX_SESSIONS {
+            let oldest = sessions
+                .iter()
+                .min_by_key(|(_, session)| session.last_used)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+        sessions.insert(
+            id.clone(),
+            ShellSession {
+                cwd,
+                previous_cwd: None,
+                env,
+                last_used: now,
+            },
+        );
+        id
+    }
+
+    /// A copy of the session's state, to run a command with.
+    pub fn get(&self, id: &str) -> Result<ShellSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(id)
+            .filter(|session| session.last_used.elapsed() < IDLE_TIMEOUT)
+            .ok_or_else(|| anyhow!("Shell session '{}' does not exist or has been closed", id))?;
+        session.last_used = Instant::now();
+        Ok(session.clone())
+    }
+
+    /// Stores the state a command left behind, unless the session was closed meanwhile.
+    pub fn update(&self, id: &str, session: ShellSession) {
+        if let Some(stored) = self.sessions.lock().unwrap().get_mut(id) {
+            *stored = ShellSession {
+                last_used: Instant::now(),
+                ..session
+            };
+        }
+    }
+
+    /// Closes a session; returns whether it was open.
+    pub fn close(&self, id: &str) -> bool {
+        self.sessions.lock().unwrap().remove(id).is_some()
+    }
+}
+
+impl Default for ShellSessions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(line: &str) -> Vec<(&'static str, String)> {
+        split_commands(line).unwrap()
+    }
+
+    #[test]
+    fn split_commands_keeps_each_operator() {
+        assert_eq!(
+            split("cd src && ls || echo none; pwd"),
+            [(";", "cd src ".into()), ("&&", " ls ".into()), ("||", " echo none".into()), (";", " pwd".into())]
+        );
+        assert_eq!(split("ls;"), [(";", "ls".into())]);
+    }
+
+    #[test]
+    fn split_commands_ignores_operators_in_quotes_and_escapes() {
+        assert_eq!(split("echo 'a && b' \"c; d\""), [(";", "echo 'a && b' \"c; d\"".into())]);
+        assert_eq!(split(r"echo a\;b"), [(";", r"echo a\;b".into())]);
+        // A single `&` or `|` is not an operator here.
+        assert_eq!(split("echo a & b | c"), [(";", "echo a & b | c".into())]);
+    }
+
+    #[test]
+    fn split_commands_rejects_empty_commands() {
+        for line in ["", "&& ls", "ls &&", "ls ;; pwd", "ls || ;"] {
+            assert!(split_commands(line).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn sessions_cannot_set_loader_variables() {
+        for name in [
+            "PATH", "LD_PRELOAD", "DYLD_INSERT_LIBRARIES", "BASH_ENV", "IFS", "PYTHONPATH", "NODE_OPTIONS",
+            "GIT_SSH_COMMAND", "PAGER", "EDITOR", "HOME", "XDG_CONFIG_HOME",
+        ] {
+            assert!(check_settable(name).is_err(), "{}", name);
+        }
+        for name in ["RUST_LOG", "_private", "DIR"] {
+            assert!(check_settable(name).is_ok(), "{}", name);
+        }
+        assert!(check_settable("1ABC").is_err());
+        assert!(check_settable("A-B").is_err());
+    }
+
+    #[test]
+    fn expand_quotes_values_outside_single_quotes() {
+        let mut session = ShellSession {
+            cwd: PathBuf::from("/"),
+            previous_cwd: None,
+            env: BTreeMap::new(),
+            last_used: Instant::now(),
+        };
+        session.export("DIR=my dir").unwrap();
+        assert_eq!(session.expand("ls $DIR"), "ls 'my dir'");
+        assert_eq!(session.expand("ls \"${DIR}/x\""), "ls \"my dir/x\"");
+        assert_eq!(session.expand("echo '$DIR' $NONE"), "echo '$DIR' ''");
+        assert_eq!(session.expand("echo ${DIR"), "echo ${DIR");
+    }
+}
@@ -0,0 +1,447 @@
+//! SQL over CSV, TSV and Parquet files, answered by an in-memory SQLite
+//! database that lives for one query.
+//!
+//! Every file becomes a table, read whole into memory, which is why file
+//! sizes are capped. CSV columns get the narrowest type all their
+//! values fit (INTEGER, REAL or TEXT) and empty fields become NULL; Parquet
+//! columns keep their numeric types and everything else is loaded as text.
+//! Queries can only read: the database is `query_only`, attaching other
+//! databases is disabled, and a query is interrupted after [`QUERY_TIMEOUT`].
+
+use anyhow::{anyhow, bail, Result};
+use arrow_array::{Array, ArrayRef, Float64Array, Int64Array};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_schema::DataType;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use rmcp::schemars::JsonSchema;
+use rusqlite::ffi::ErrorCode;
+use rusqlite::limits::Limit;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Larger files are refused rather than loaded into memory, where a table
+/// takes a few times its file size.
+const MAX_FILE_BYTES: u64 = 100 * 1024 * 1024;
+/// The most bytes of files one query may load together.
+const MAX_TOTAL_BYTES: u64 = 200 * 1024 * 1024;
+/// Rows shown for each table when no query is given.
+const SAMPLE_ROWS: usize = 5;
+
+#[derive(Serialize, JsonSchema)]
+pub struct TableInfo {
+    /// The name to use in SQL.
+    pub name: String,
+    pub path: String,
+    pub rows: u64,
+    pub columns: Vec<ColumnInfo>,
+    /// The first rows of the table, when no query was given.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sample: Vec<Vec<Value>>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ColumnInfo {
+    pub name: String,
+    /// `INTEGER`, `REAL` or `TEXT`.
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// Whether the query returned more rows than the limit.
+    pub truncated: bool,
+}
+
+/// Loads every `(name, path)` table and, with `sql`, runs the query and
+/// returns up to `limit` rows of its result. Without it, each table comes
+/// with a sample of its first rows instead.
+pub fn query(tables: &[(String, PathBuf)], sql: Option<&str>, limit: usize) -> Result<(Vec<TableInfo>, Option<QueryResult>)> {
+    let total: u64 = tables
+        .iter()
+        .filter_map(|(_, path)| fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    if total > MAX_TOTAL_BYTES {
+        bail!("The files are too large to query together ({} bytes; at most {})", total, MAX_TOTAL_BYTES);
+    }
+    let conn = Connection::open_in_memory()?;
+    conn.set_limit(Limit::SQLITE_LIMIT_ATTACHED, 0)?;
+    let mut infos = Vec::new();
+    for (name, path) in tables {
+        let columns = load(&conn, name, path)?;
+        let rows = conn.query_row(&format!("SELECT count(*) FROM {}", quote(name)), [], |row| row.get(0))?;
+        infos.push(TableInfo {
+            name: name.clone(),
+            path: path.display().to_string(),
+            rows,
+            columns,
+            sample: Vec::new(),
+        });
+    }
+    conn.pragma_update(None, "query_only", true)?;
+
+    let Some(sql) = sql else {
+        for info in &mut infos {
+            let sample = run(&conn, &format!("SELECT * FROM {}", quote(&info.name)), SAMPLE_ROWS)?;
+            info.sample = sample.rows;
+        }
+        return Ok((infos, None));
+    };
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    conn.progress_handler(10_000, Some(move || Instant::now() > deadline));
+    let result = run(&conn, sql, limit).map_err(|e| match e.downcast_ref::<rusqlite::Error>() {
+        Some(e) if e.sqlite_error_code() == Some(ErrorCode::OperationInterrupted) => {
+            anyhow!("Query took longer than {} seconds", QUERY_TIMEOUT.as_secs())
+        }
+        _ => e,
+    })?;
+    Ok((infos, Some(result)))
+}
+
+/// A SQL table name for `path`: its file name without the extension, with
+/// anything but letters, digits and `_` replaced, and a suffix when `taken`.
+pub fn table_name(path: &Path, taken: &HashSet<String>) -> String {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let mut name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert_str(0, "t_");
+    }
+    let mut unique = name.clone();
+    let mut n = 2;
+    while taken.contains(&unique) {
+        unique = format!("{}_{}", name, n);
+        n += 1;
+    }
+    unique
+}
+
+fn run(conn: &Connection, sql: &str, limit: usize) -> Result<QueryResult> {
+    let mut statement = conn.prepare(sql)?;
+    if !statement.readonly() {
+        bail!("Only queries that read data are allowed");
+    }
+    let columns: Vec<String> = statement.column_names().into_iter().map(str::to_string).collect();
+    let mut rows = Vec::new();
+    let mut truncated = false;
+    let mut cursor = statement.query([])?;
+    while let Some(row) = cursor.next()? {
+        if rows.len() == limit {
+            truncated = true;
+            break;
+        }
+        rows.push((0..columns.len()).map(|i| row.get_ref(i).map(json_value)).collect::<Result<_, _>>()?);
+    }
+    Ok(QueryResult {
+        columns,
+        rows,
+        truncated,
+    })
+}
+
+fn json_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(n) => n.into(),
+        ValueRef::Real(x) => serde_json::Number::from_f64(x).map_or(Value::Null, Value::Number),
+        ValueRef::Text(text) => Value::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(blob) => Value::String(format!("<{} bytes>", blob.len())),
+    }
+}
+
+fn load(conn: &Connection, name: &str, path: &Path) -> Result<Vec<ColumnInfo>> {
+    let size = fs::metadata(path)
+        .map_err(|e| anyhow!("Failed to read '{}': {}", path.display(), e))?
+        .len();
+    if size > MAX_FILE_BYTES {
+        bail!("'{}' is too large to query ({} bytes; at most {})", path.display(), size, MAX_FILE_BYTES);
+    }
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let (columns, rows) = match extension.as_str() {
+        "csv" => read_delimited(path, b',')?,
+        "tsv" | "tab" => read_delimited(path, b'\t')?,
+        "parquet" | "pq" => read_parquet(path)?,
+        _ => bail!("'{}' is not a CSV, TSV or Parquet file", path.display()),
+    };
+
+    let definitions: Vec<String> = columns
+        .iter()
+        .map(|column| format!("{} {}", quote(&column.name), column.kind))
+        .collect();
+    conn.execute(&format!("CREATE TABLE {} ({})", quote(name), definitions.join(", ")), [])?;
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    let transaction = conn.unchecked_transaction()?;
+    {
+        let mut insert = transaction.prepare(&format!("INSERT INTO {} VALUES ({})", quote(name), placeholders))?;
+        for row in rows {
+            insert.execute(params_from_iter(row))?;
+        }
+    }
+    transaction.commit()?;
+    Ok(columns)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Integer,
+    Real,
+    Text,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Integer => "INTEGER",
+            Kind::Real => "REAL",
+            Kind::Text => "TEXT",
+        }
+    }
+
+    fn value(self, field: &str) -> SqlValue {
+        match self {
+            _ if field.is_empty() => SqlValue::Null,
+            Kind::Integer => field.trim().parse().map_or(SqlValue::Null, SqlValue::Integer),
+            Kind::Real => field.trim().parse().map_or(SqlValue::Null, SqlValue::Real),
+            Kind::Text => SqlValue::Text(field.to_string()),
+        }
+    }
+}
+
+/// Reads a CSV or TSV file with a header row.
+fn read_delimited(path: &Path, delimiter: u8) -> Result<(Vec<ColumnInfo>, Vec<Vec<SqlValue>>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| anyhow!("Failed to read '{}': {}", path.display(), e))?;
+    let names = column_names(reader.headers()?.iter());
+    let records = reader
+        .records()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow!("Invalid table '{}': {}", path.display(), e))?;
+
+    let kinds: Vec<Kind> = (0..names.len())
+        .map(|i| {
+            let fields = records.iter().filter_map(|record| record.get(i)).map(str::trim).filter(|f| !f.is_empty());
+            let mut kind = Kind::Integer;
+            for field in fields {
+                if kind == Kind::Integer && field.parse::<i64>().is_err() {
+                    kind = Kind::Real;
+                }
+                if kind == Kind::Real && field.parse::<f64>().is_err() {
+                    return Kind::Text;
+                }
+            }
+            kind
+        })
+        .collect();
+    let rows = records
+        .iter()
+        .map(|record| {
+            kinds
+                .iter()
+                .enumerate()
+                .map(|(i, kind)| kind.value(record.get(i).unwrap_or_default()))
+                .collect()
+        })
+        .collect();
+    let columns = names
+        .into_iter()
+        .zip(&kinds)
+        .map(|(name, kind)| ColumnInfo {
+            name,
+            kind: kind.as_str().to_string(),
+        })
+        .collect();
+    Ok((columns, rows))
+}
+
+fn read_parquet(path: &Path) -> Result<(Vec<ColumnInfo>, Vec<Vec<SqlValue>>)> {
+    let file = File::open(path).map_err(|e| anyhow!("Failed to read '{}': {}", path.display(), e))?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+        .map_err(|e| anyhow!("Invalid Parquet file '{}': {}", path.display(), e))?;
+    let fields = builder.schema().fields().clone();
+    let kinds: Vec<Kind> = fields
+        .iter()
+        .map(|field| match field.data_type() {
+            DataType::Boolean
+            | DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64 => Kind::Integer,
+            DataType::Float16
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Decimal128(..)
+            | DataType::Decimal256(..) => Kind::Real,
+            _ => Kind::Text,
+        })
+        .collect();
+
+    let mut rows = Vec::new();
+    for batch in builder.build()? {
+        let batch = batch?;
+        let columns = batch
+            .columns()
+            .iter()
+            .zip(&kinds)
+            .map(|(array, kind)| column_values(array, *kind))
+            .collect::<Result<Vec<_>>>()?;
+        for i in 0..batch.num_rows() {
+            rows.push(columns.iter().map(|column| column[i].clone()).collect());
+        }
+    }
+    let names = column_names(fields.iter().map(|field| field.name().as_str()));
+    let columns = names
+        .into_iter()
+        .zip(&kinds)
+        .map(|(name, kind)| ColumnInfo {
+            name,
+            kind: kind.as_str().to_string(),
+        })
+        .collect();
+    Ok((columns, rows))
+}
+
+fn column_values(array: &ArrayRef, kind: Kind) -> Result<Vec<SqlValue>> {
+    let value = |i: usize, value: SqlValue| if array.is_null(i) { SqlValue::Null } else { value };
+    Ok(match kind {
+        Kind::Integer => {
+            let cast = arrow_cast::cast(array, &DataType::Int64)?;
+            let ints = cast.as_any().downcast_ref::<Int64Array>().expect("cast to Int64");
+            (0..ints.len()).map(|i| value(i, SqlValue::Integer(ints.value(i)))).collect()
+        }
+        Kind::Real => {
+            let cast = arrow_cast::cast(array, &DataType::Float64)?;
+            let reals = cast.as_any().downcast_ref::<Float64Array>().expect("cast to Float64");
+            (0..reals.len()).map(|i| value(i, SqlValue::Real(reals.value(i)))).collect()
+        }
+        Kind::Text => {
+            let formatter = ArrayFormatter::try_new(array.as_ref(), &FormatOptions::default())?;
+            (0..array.len())
+                .map(|i| value(i, SqlValue::Text(formatter.value(i).to_string())))
+                .collect()
+        }
+    })
+}
+
+/// Header names made usable as SQL columns: blanks and duplicates are renamed.
+fn column_names<'a>(headers: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    headers
+        .enumerate()
+        .map(|(i, header)| {
+            let header = header.trim();
+            let mut name = if header.is_empty() {
+                format!("column_{}", i + 1)
+            } else {
+                header.to_string()
+            };
+            while !seen.insert(name.to_lowercase()) {
+                name = format!("{}_{}", name, i + 1);
+            }
+            name
+        })
+        .collect()
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(columns: &[ColumnInfo]) -> Vec<(&str, &str)> {
+        columns.iter().map(|column| (column.name.as_str(), column.kind.as_str())).collect()
+    }
+
+    fn people(dir: &Path) -> Vec<(String, PathBuf)> {
+        let path = dir.join("people.csv");
+        fs::write(&path, "name,age\nada,36\ngrace,45\n").unwrap();
+        vec![("people".to_string(), path)]
+    }
+
+    #[test]
+    fn delimited_columns_widen_from_integer_to_real_to_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.tsv");
+        fs::write(&path, "id\tscore\tlabel\tnote\n1\t2\tx\t\n2\t2.5\t3\t\n\t\t\t\n").unwrap();
+        let (columns, rows) = read_delimited(&path, b'\t').unwrap();
+        assert_eq!(
+            kinds(&columns),
+            [("id", "INTEGER"), ("score", "REAL"), ("label", "TEXT"), ("note", "INTEGER")]
+        );
+        assert_eq!(
+            rows,
+            [
+                vec![SqlValue::Integer(1), SqlValue::Real(2.0), SqlValue::Text("x".into()), SqlValue::Null],
+                vec![SqlValue::Integer(2), SqlValue::Real(2.5), SqlValue::Text("3".into()), SqlValue::Null],
+                vec![SqlValue::Null, SqlValue::Null, SqlValue::Null, SqlValue::Null],
+            ]
+        );
+    }
+
+    #[test]
+    fn blank_and_duplicate_headers_are_renamed() {
+        let names = column_names(["id", " ", "Name", "name", "id", ""].into_iter());
+        assert_eq!(names, ["id", "column_2", "Name", "name_4", "id_5", "column_6"]);
+    }
+
+    #[test]
+    fn table_names_are_sanitized_and_made_unique() {
+        let mut taken = HashSet::new();
+        assert_eq!(table_name(Path::new("data/Sales Report-2024.csv"), &taken), "sales_report_2024");
+        assert_eq!(table_name(Path::new("2024.csv"), &taken), "t_2024");
+        taken.insert("sales".to_string());
+        taken.insert("sales_2".to_string());
+        assert_eq!(table_name(Path::new("other/sales.parquet"), &taken), "sales_3");
+    }
+
+    #[test]
+    fn queries_run_against_loaded_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let tables = people(dir.path());
+        let (infos, result) = query(&tables, Some("SELECT name FROM people WHERE age > 40"), 10).unwrap();
+        assert_eq!(infos[0].rows, 2);
+        let result = result.unwrap();
+        assert_eq!(result.columns, ["name"]);
+        assert_eq!(result.rows, [vec![Value::from("grace")]]);
+        assert!(!result.truncated);
+
+        let (infos, result) = query(&tables, None, 10).unwrap();
+        assert!(result.is_none());
+        assert_eq!(infos[0].sample.len(), 2);
+    }
+
+    #[test]
+    fn writes_and_multiple_statements_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let tables = people(dir.path());
+        for sql in ["DELETE FROM people", "SELECT 1; DROP TABLE people", "ATTACH DATABASE 'x.db' AS x"] {
+            assert!(query(&tables, Some(sql), 10).is_err(), "{}", sql);
+        }
+        let (_, result) = query(&tables, Some("SELECT count(*) FROM people"), 10).unwrap();
+        assert_eq!(result.unwrap().rows, [vec![Value::from(2)]]);
+    }
+}
@@ -0,0 +1,208 @@
+//! The extension point for tools.
+//!
+//! A [`Tool`] describes itself (name, schemas, annotations) and runs with a
+//! [`ToolContext`] that carries the workspace and session state it may use.
+//! Built-in tools, plugins and downstream tools all implement it, so the
+//! same policy applies to each of them.
+
+use anyhow::Error;
+use rmcp::{
+    model::{CallToolResult, Content, JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+    service::Peer,
+    ErrorData as McpError, RoleServer,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use crate::checkpoints::{CheckpointStore, Turns};
+use crate::config::{PaginationConfig, ShellConfig};
+use crate::pages::{Page, ResultStore};
+use crate::redact::{Findings, Redactor};
+use crate::scratchpad::Scratchpad;
+use crate::search::SearchIndex;
+use crate::shell_sessions::ShellSessions;
+use crate::workspace::Workspace;
+
+/// The future a tool call returns.
+pub type ToolFuture<'a> = Pin<Box<dyn Future<Output = Result<CallToolResult, McpError>> + Send + 'a>>;
+
+/// A tool the server can list and call.
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn title(&self) -> Option<&str> {
+        None
+    }
+
+    fn description(&self) -> &str;
+
+    /// JSON Schema of the arguments object.
+    fn input_schema(&self) -> Arc<JsonObject>;
+
+    /// JSON Schema of the structured content, for tools that return it.
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        None
+    }
+
+    /// Hints for clients.
+    fn annotations(&self) -> ToolAnnotations;
+
+    /// Whether policy checkpoints the workspace before a call: by default for
+    /// every tool not marked `read_only_hint`. Tools that write somewhere else
+    /// than the workspace turn it off.
+    fn checkpoints(&self) -> bool {
+        self.annotations().read_only_hint != Some(true)
+    }
+
+    /// Runs the tool. The arguments have not been checked against the input schema.
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_>;
+
+    /// The definition listed to clients.
+    fn definition(&self) -> rmcp::model::Tool {
+        let mut definition = rmcp::model::Tool::new(
+            self.name().to_string(),
+            self.description().to_string(),
+            self.input_schema(),
+        );
+        definition.title = self.title().map(str::to_string);
+        definition.output_schema = self.output_schema();
+        definition.annotations = Some(self.annotations());
+        definition
+    }
+}
+
+/// What a running tool may use besides its arguments. Cheap to clone.
+#[derive(Clone)]
+pub struct ToolContext {
+    pub workspace: Arc<Workspace>,
+    /// Holds output too large for one result until `read_more` fetches it.
+    pub results: Arc<ResultStore>,
+    /// `None` when checkpoints are disabled.
+    pub checkpoints: Option<Arc<CheckpointStore>>,
+    /// `None` when search is disabled.
+    pub search: Option<Arc<SearchIndex>>,
+    /// `None` when the scratchpad is disabled.
+    pub scratchpad: Option<Arc<Scratchpad>>,
+    /// The programs shell tools may run.
+    pub shell: Arc<ShellConfig>,
+    /// The session the call belongs to.
+    pub turns: Arc<Turns>,
+    /// Working directories and variables of `session_exec` shell sessions.
+    pub shells: Arc<ShellSessions>,
+    /// The MCP client, when the call came through one.
+    pub peer: Option<Peer<RoleServer>>,
+    /// Set by [`Policy`](crate::policy::Policy), for text a tool sends to the
+    /// client other than its result, such as sampling requests, and for
+    /// output before it is paged.
+    pub redactor: Option<Arc<Redactor>>,
+    /// Secrets redacted from output before it was paged, or from prompts
+    /// sent for sampling, for the audit record.
+    pub redacted: Arc<Mutex<Findings>>,
+}
+
+impl ToolContext {
+    /// A context for calling tools directly: default pagination and allowed commands, no checkpoints, search index, scratchpad, client or redaction.
+    pub fn new(workspace: Arc<Workspace>) -> Self {
+        Self {
+            workspace,
+            results: Arc::new(ResultStore::new(&PaginationConfig::default())),
+            checkpoints: None,
+            search: None,
+            scratchpad: None,
+            shell: Arc::default(),
+            turns: Arc::new(Turns::new()),
+            shells: Arc::new(ShellSessions::new()),
+            peer: None,
+            redactor: None,
+            redacted: Arc::default(),
+        }
+    }
+
+    /// Redacts `text` whole, then returns it in one page or stores it for
+    /// `read_more` and returns the first page. Redacting before paging keeps
+    /// a secret split across two pages from getting through.
+    pub fn first_page(&self, text: String) -> Page {
+        let text = match &self.redactor {
+            Some(redactor) => redactor.redact(&text, &mut self.redacted.lock().unwrap()),
+            None => text,
+        };
+        self.results.first_page(text)
+    }
+
+    /// Like [`first_page`](Self::first_page) for output of one line per item:
+    /// the page ends between lines, and the number of items on it comes back
+    /// with it. Each line is redacted on its own.
+    pub fn first_page_of_lines(&self, mut lines: Vec<String>) -> (Page, usize) {
+        if let Some(redactor) = &self.redactor {
+            let mut findings = self.redacted.lock().unwrap();
+            for line in &mut lines {
+                *line = redactor.redact(line, &mut findings);
+            }
+        }
+        self.results.first_page_of_lines(&lines)
+    }
+}
+
+/// Deserializes a tool's arguments, rejecting them as invalid params on failure.
+pub fn parse_arguments<T: DeserializeOwned>(arguments: JsonObject) -> Result<T, McpError> {
+    serde_json::from_value(Value::Object(arguments))
+        .map_err(|e| McpError::invalid_params(format!("Invalid arguments: {}", e), None))
+}
+
+pub fn input_schema<T: JsonSchema + 'static>() -> Arc<JsonObject> {
+    rmcp::handler::server::tool::cached_schema_for_type::<T>()
+}
+
+pub fn output_schema<T: JsonSchema + 'static>() -> Arc<JsonObject> {
+    rmcp::handler::server::tool::schema_for_output::<T>()
+        .unwrap_or_else(|e| panic!("Invalid output schema for {}: {}", std::any::type_name::<T>(), e))
+}
+
+/// A successful result carrying a plain-text rendering alongside the structured value.
+pub fn structured_result<T: Serialize>(text: String, value: &T) -> CallToolResult {
+    CallToolResult {
+        content: vec![Content::text(text)],
+        structured_content: serde_json::to_value(value).ok(),
+        is_error: Some(false),
+        meta: None,
+    }
+}
+
+/// Reports a failed tool as an internal error carrying the message.
+pub fn internal_error(e: Error) -> McpError {
+    McpError {
+        code: rmcp::model::ErrorCode::INTERNAL_ERROR,
+        message: e.to_string().into(),
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RedactionConfig;
+
+    #[test]
+    fn first_page_redacts_secrets_cut_by_a_page_boundary() {
+        let mut context = ToolContext::new(Arc::new(Workspace::new(std::env::temp_dir())));
+        context.results = Arc::new(ResultStore::new(&PaginationConfig {
+            page_bytes: 24,
+            ..PaginationConfig::default()
+        }));
+        context.redactor = Some(Arc::new(Redactor::new(&RedactionConfig::default()).unwrap()));
+
+        let mut page = context.first_page("MY_API_KEY=s3cr3t-value-0123456789abcdef\n".into());
+        let mut text = page.text;
+        while let Some(cursor) = page.next_cursor {
+            page = context.results.read(&cursor).unwrap();
+            text.push_str(&page.text);
+        }
+        assert!(!text.contains("s3cr3t"), "{}", text);
+        assert!(text.contains("[REDACTED:"), "{}", text);
+        assert_eq!(context.redacted.lock().unwrap().values().sum::<usize>(), 1);
+    }
+}
@@ -0,0 +1,220 @@
+use anyhow::{anyhow, Result};
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::checkpoints::{CheckpointDiff, CheckpointList, CheckpointStore, RestoreResult};
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ListCheckpointsParams {
+    /// Only this session's checkpoints; every session when omitted. Clients
+    /// sharing the server over HTTP only ever see their own session.
+    pub session: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CheckpointParams {
+    /// Checkpoint id from `list_checkpoints`.
+    pub id: String,
+    /// Limits the operation to this file or directory.
+    pub path: Option<String>,
+}
+
+fn store(context: &ToolContext) -> Result<&CheckpointStore> {
+    context
+        .checkpoints
+        .as_deref()
+        .ok_or_else(|| anyhow!("Checkpoints are disabled"))
+}
+
+/// Whether an isolated session may see checkpoints of `session`.
+fn visible(context: &ToolContext, session: &str) -> bool {
+    !context.turns.is_isolated() || session == context.turns.session()
+}
+
+/// The store, refusing checkpoints of other sessions as if they did not exist.
+pub(crate) fn store_for<'a>(context: &'a ToolContext, id: &str) -> Result<&'a CheckpointStore> {
+    let session = id.rsplit_once('-').map_or(id, |(session, _)| session);
+    if !visible(context, session) {
+        return Err(anyhow!("Checkpoint '{}' does not exist", id));
+    }
+    store(context)
+}
+
+pub struct ListCheckpoints;
+
+impl Tool for ListCheckpoints {
+    fn name(&self) -> &str {
+        "list_checkpoints"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("List Checkpoints")
+    }
+
+    fn description(&self) -> &str {
+        "Lists workspace snapshots taken before tool calls that could change files, newest first."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<ListCheckpointsParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<CheckpointList>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("List Checkpoints")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: ListCheckpointsParams = tool::parse_arguments(arguments)?;
+            let session = match params.session.as_deref() {
+                Some(session) => Some(session),
+                None if context.turns.is_isolated() => Some(context.turns.session()),
+                None => None,
+            };
+            let checkpoints = match session {
+                Some(session) if !visible(&context, session) => Vec::new(),
+                session => store(&context)
+                    .and_then(|store| store.list(session))
+                    .map_err(tool::internal_error)?,
+            };
+            let text = checkpoints
+                .iter()
+                .map(|c| format!("{}  before {}  ({} files)", c.id, c.tool, c.files))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let output = CheckpointList {
+                current_session: context.turns.session().to_string(),
+                checkpoints,
+            };
+            Ok(tool::structured_result(context.first_page(text).render(), &output))
+        })
+    }
+}
+
+pub struct DiffCheckpoint;
+
+impl Tool for DiffCheckpoint {
+    fn name(&self) -> &str {
+        "diff_checkpoint"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Diff Checkpoint")
+    }
+
+    fn description(&self) -> &str {
+        "Shows how files changed since a checkpoint as a unified diff."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<CheckpointParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<CheckpointDiff>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Diff Checkpoint")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: CheckpointParams = tool::parse_arguments(arguments)?;
+            let (changes, diff) = store_for(&context, &params.id)
+                .and_then(|store| {
+                    let scope = params.path.as_deref().map(|path| context.workspace.resolve(path)).transpose()?;
+                    store.diff(&params.id, scope.as_deref(), &context.workspace)
+                })
+                .map_err(tool::internal_error)?;
+            let page = context.first_page(diff);
+            let text = if changes.is_empty() {
+                "No changes since the checkpoint.".to_string()
+            } else {
+                page.render()
+            };
+            let output = CheckpointDiff {
+                id: params.id,
+                changes,
+                diff: page.text,
+                next_cursor: page.next_cursor,
+            };
+            Ok(tool::structured_result(text, &output))
+        })
+    }
+}
+
+pub struct RestoreCheckpoint;
+
+impl Tool for RestoreCheckpoint {
+    fn name(&self) -> &str {
+        "restore_checkpoint"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Restore Checkpoint")
+    }
+
+    fn description(&self) -> &str {
+        "Rolls files back to a checkpoint: changed and deleted files are rewritten and files created since are removed."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<CheckpointParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<RestoreResult>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Restore Checkpoint")
+            .read_only(false)
+            .destructive(true)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: CheckpointParams = tool::parse_arguments(arguments)?;
+            let restored = store_for(&context, &params.id)
+                .and_then(|store| {
+                    let scope = params.path.as_deref().map(|path| context.workspace.resolve(path)).transpose()?;
+                    store.restore(&params.id, scope.as_deref(), &context.workspace)
+                })
+                .map_err(tool::internal_error)?;
+            let text = if restored.is_empty() {
+                "Nothing to restore.".to_string()
+            } else {
+                restored
+                    .iter()
+                    .map(|c| format!("{} (was {})", c.path, c.change.as_str()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            let output = RestoreResult {
+                id: params.id,
+                restored,
+            };
+            Ok(tool::structured_result(text, &output))
+        })
+    }
+}
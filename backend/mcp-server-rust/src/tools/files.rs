@@ -0,0 +1,220 @@
+use anyhow::{Result, anyhow};
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct PathParams {
+    pub path: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FileContent {
+    pub path: String,
+    pub content: String,
+    /// Pass to `read_more` for the rest of the output; missing when nothing was cut off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DirectoryListing {
+    pub entries: Vec<DirEntry>,
+    /// Pass to `read_more` for the rest of the output; missing when nothing was cut off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DirEntry {
+    pub name: String,
+    pub path: String,
+    pub kind: EntryKind,
+    /// Size in bytes; zero for directories.
+    pub size: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+pub struct ReadFile;
+
+impl Tool for ReadFile {
+    fn name(&self) -> &str {
+        "read_file"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Read File")
+    }
+
+    fn description(&self) -> &str {
+        "Reads content from the local workspace."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<PathParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<FileContent>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Read File")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: PathParams = tool::parse_arguments(arguments)?;
+            let content = context
+                .workspace
+                .resolve(&params.path)
+                .and_then(|path| read_file(&path, context.results.max_read_bytes()))
+                .map_err(tool::internal_error)?;
+            let page = context.first_page(content);
+            let output = FileContent {
+                path: params.path,
+                content: page.text.clone(),
+                next_cursor: page.next_cursor.clone(),
+            };
+            Ok(tool::structured_result(page.render(), &output))
+        })
+    }
+}
+
+pub struct ListDirectory;
+
+impl Tool for ListDirectory {
+    fn name(&self) -> &str {
+        "list_directory"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("List Directory")
+    }
+
+    fn description(&self) -> &str {
+        "Lists files in a directory."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<PathParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<DirectoryListing>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("List Directory")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: PathParams = tool::parse_arguments(arguments)?;
+            let mut entries = context
+                .workspace
+                .resolve(&params.path)
+                .and_then(|path| list_directory(&path))
+                .map_err(tool::internal_error)?;
+            // One line per entry, so the first page holds whole entries.
+            let lines = entries
+                .iter()
+                .map(|e| e.path.replace('\n', "\\n").replace('\r', "\\r"))
+                .collect();
+            let (page, count) = context.first_page_of_lines(lines);
+            entries.truncate(count);
+            let output = DirectoryListing {
+                entries,
+                next_cursor: page.next_cursor.clone(),
+            };
+            Ok(tool::structured_result(page.render(), &output))
+        })
+    }
+}
+
+/// Reads a text file of at most `max_bytes`; larger files are refused before anything is read.
+pub fn read_file(path: &Path, max_bytes: u64) -> Result<String> {
+    let error = |e: std::io::Error| anyhow!("Failed to read file '{}': {}", path.display(), e);
+    let file = File::open(path).map_err(error)?;
+    let len = file.metadata().map_err(error)?.len();
+    if len > max_bytes {
+        return Err(anyhow!(
+            "File '{}' is {} bytes, over the {} bytes read_file returns",
+            path.display(),
+            len,
+            max_bytes
+        ));
+    }
+    let mut content = String::new();
+    // The file may have grown since it was checked.
+    file.take(max_bytes).read_to_string(&mut content).map_err(error)?;
+    Ok(content)
+}
+
+pub fn list_directory(path: &Path) -> Result<Vec<DirEntry>> {
+    let entries = fs::read_dir(path)
+        .map_err(|e| anyhow!("Failed to read directory '{}': {}", path.display(), e))?
+        .map(|res| {
+            res.and_then(|e| {
+                let file_type = e.file_type()?;
+                let kind = if file_type.is_symlink() {
+                    EntryKind::Symlink
+                } else if file_type.is_dir() {
+                    EntryKind::Directory
+                } else if file_type.is_file() {
+                    EntryKind::File
+                } else {
+                    EntryKind::Other
+                };
+                let size = if file_type.is_file() { e.metadata()?.len() } else { 0 };
+                Ok(DirEntry {
+                    name: e.file_name().to_string_lossy().into_owned(),
+                    path: e.path().display().to_string(),
+                    kind,
+                    size,
+                })
+            })
+        })
+        .collect::<Result<Vec<_>, std::io::Error>>()
+        .map_err(|e| anyhow!("Failed to collect entries: {}", e))?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_file_refuses_files_over_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "0123456789").unwrap();
+        assert_eq!(read_file(&path, 10).unwrap(), "0123456789");
+        let error = read_file(&path, 9).unwrap_err().to_string();
+        assert!(error.contains("10 bytes"), "{}", error);
+    }
+}
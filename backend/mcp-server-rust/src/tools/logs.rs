@@ -0,0 +1,427 @@
+//! `tail_log`: the end of a log file, then only what was appended since.
+//!
+//! The cursor names the file by inode and a byte offset into it, so following
+//! a log keeps no state on the server. When the inode changes the log was
+//! rotated: the rest of the old file is read if it is still next to the log
+//! (e.g. `app.log.1`), then the new file from its start. A file shorter than
+//! the cursor's offset was truncated and is read from its start.
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+    ErrorData as McpError,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, Metadata};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+use crate::workspace::Workspace;
+
+const DEFAULT_LINES: usize = 50;
+const MAX_LINES: usize = 1000;
+/// Appended output beyond this is skipped when following, keeping the newest part.
+const MAX_FOLLOW_BYTES: u64 = 64 * 1024 * 1024;
+
+static LEVEL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(trace|debug|info|warn|warning|error|err|fatal|critical|panic)\b").unwrap()
+});
+
+#[derive(Deserialize, JsonSchema)]
+pub struct TailLogParams {
+    pub path: String,
+    /// Most lines to return; 50 when omitted, at most 1000.
+    pub lines: Option<usize>,
+    /// Only entries matching this regex.
+    pub pattern: Option<String>,
+    /// Only entries at this level or above.
+    pub level: Option<Level>,
+    /// The cursor from the previous call, to get only lines written since.
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn parse(word: &str) -> Level {
+        match word.to_ascii_lowercase().as_str() {
+            "trace" => Level::Trace,
+            "debug" => Level::Debug,
+            "info" => Level::Info,
+            "warn" | "warning" => Level::Warn,
+            _ => Level::Error,
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LogTail {
+    pub path: String,
+    pub lines: Vec<String>,
+    /// Pass back as `cursor` to get only the lines written after these.
+    pub cursor: String,
+    /// Whether the log was rotated or truncated since the cursor.
+    pub rotated: bool,
+    /// Matching lines left out because more than `lines` were written since the cursor.
+    pub omitted: usize,
+}
+
+pub struct TailLog;
+
+impl Tool for TailLog {
+    fn name(&self) -> &str {
+        "tail_log"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Tail Log")
+    }
+
+    fn description(&self) -> &str {
+        "Returns the last lines of a log file, optionally only those matching a regex or at a minimum \
+         level, and a cursor. Call again with the cursor to get only the lines written since; log \
+         rotation and truncation are handled. Indented lines such as stack traces stay with the entry above them."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<TailLogParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<LogTail>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Tail Log")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(false)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: TailLogParams = tool::parse_arguments(arguments)?;
+            let pattern = params
+                .pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(|e| McpError::invalid_params(format!("Invalid pattern: {}", e), None))?;
+            let filter = Filter {
+                pattern,
+                level: params.level,
+            };
+            let limit = params.lines.unwrap_or(DEFAULT_LINES).clamp(1, MAX_LINES);
+            let path = context.workspace.resolve(&params.path).map_err(tool::internal_error)?;
+            let cursor = params.cursor;
+            let workspace = context.workspace.clone();
+            let mut tail = tokio::task::spawn_blocking(move || tail(&path, cursor.as_deref(), limit, &filter, &workspace))
+                .await
+                .map_err(|e| tool::internal_error(e.into()))?
+                .map_err(tool::internal_error)?;
+            tail.path = params.path;
+
+            let mut text = tail.lines.join("\n");
+            if tail.rotated {
+                text.insert_str(0, "[The log was rotated or truncated since the cursor.]\n");
+            }
+            if tail.omitted > 0 {
+                text.insert_str(0, &format!("[{} earlier matching lines omitted.]\n", tail.omitted));
+            }
+            if tail.lines.is_empty() {
+                text.push_str("No matching lines.");
+            }
+            let text = format!(
+                "{}\n\n[Call `tail_log` with cursor \"{}\" for lines written after these.]",
+                context.first_page(text).render(),
+                tail.cursor
+            );
+            Ok(tool::structured_result(text, &tail))
+        })
+    }
+}
+
+struct Filter {
+    pattern: Option<Regex>,
+    level: Option<Level>,
+}
+
+impl Filter {
+    fn accepts(&self, entry: &Entry) -> bool {
+        let level_ok = match self.level {
+            Some(min) => entry.level.is_some_and(|level| level >= min),
+            None => true,
+        };
+        level_ok
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| entry.lines.iter().any(|line| pattern.is_match(line)))
+    }
+}
+
+/// A log line with the indented lines that follow it.
+struct Entry {
+    level: Option<Level>,
+    lines: Vec<String>,
+}
+
+fn tail(path: &Path, cursor: Option<&str>, limit: usize, filter: &Filter, workspace: &Workspace) -> Result<LogTail> {
+    let metadata = fs::metadata(path).map_err(|e| anyhow!("Failed to read log '{}': {}", path.display(), e))?;
+    let id = file_id(&metadata);
+    let len = metadata.len();
+    let mut file = File::open(path).map_err(|e| anyhow!("Failed to read log '{}': {}", path.display(), e))?;
+
+    let Some((cursor_id, offset)) = cursor.map(parse_cursor).transpose()? else {
+        let (lines, end) = last_lines(&mut file, len, limit, filter)?;
+        return Ok(LogTail {
+            path: path.display().to_string(),
+            lines,
+            cursor: format!("{}:{}", id, end),
+            rotated: false,
+            omitted: 0,
+        });
+    };
+
+    let mut entries = Vec::new();
+    let rotated = cursor_id != id || offset > len;
+    let start = if rotated {
+        if cursor_id != id {
+            if let Some(old) = rotated_file(path, cursor_id, workspace) {
+                let mut old = File::open(old)?;
+                let old_len = old.metadata()?.len();
+                let from = offset.min(old_len).max(old_len.saturating_sub(MAX_FOLLOW_BYTES));
+                // The old file will not grow any more, so a partial last line is read as well.
+                let (old_entries, _) = read_entries(&mut old, from, old_len, true)?;
+                entries.extend(old_entries.into_iter().skip(usize::from(from > offset)));
+            }
+        }
+        0
+    } else {
+        offset
+    };
+    let from = start.max(len.saturating_sub(MAX_FOLLOW_BYTES));
+    let (new, end) = read_entries(&mut file, from, len, false)?;
+    // Past the skipped part, the first entry may be cut in half.
+    entries.extend(new.into_iter().skip(usize::from(from > start)));
+
+    let lines: Vec<String> = entries
+        .into_iter()
+        .filter(|entry| filter.accepts(entry))
+        .flat_map(|entry| entry.lines)
+        .collect();
+    let omitted = lines.len().saturating_sub(limit);
+    Ok(LogTail {
+        path: path.display().to_string(),
+        lines: lines[omitted..].to_vec(),
+        cursor: format!("{}:{}", id, end),
+        rotated,
+        omitted,
+    })
+}
+
+/// The last `limit` matching lines before `len`, reading backwards in
+/// growing windows of up to [`MAX_FOLLOW_BYTES`], and the offset after the
+/// last complete line.
+fn last_lines(file: &mut File, len: u64, limit: usize, filter: &Filter) -> Result<(Vec<String>, u64)> {
+    let mut window = 64 * 1024;
+    loop {
+        let start = len.saturating_sub(window);
+        let (entries, end) = read_entries(file, start, len, false)?;
+        // The first entry may have started before the window.
+        let skip = usize::from(start > 0);
+        let mut lines: Vec<String> = entries
+            .into_iter()
+            .skip(skip)
+            .filter(|entry| filter.accepts(entry))
+            .flat_map(|entry| entry.lines)
+            .collect();
+        if lines.len() >= limit || start == 0 || window == MAX_FOLLOW_BYTES {
+            let keep = lines.len().saturating_sub(limit);
+            return Ok((lines.split_off(keep), end));
+        }
+        window = (window * 4).min(MAX_FOLLOW_BYTES);
+    }
+}
+
+/// Parses `start..end` into entries. Unless `partial` is set, a last line
+/// without a newline is left for later; the returned offset is where
+/// reading should resume.
+fn read_entries(file: &mut File, start: u64, end: u64, partial: bool) -> Result<(Vec<Entry>, u64)> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.take(end - start).read_to_end(&mut bytes)?;
+    let complete = if partial {
+        bytes.len()
+    } else {
+        bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1)
+    };
+    let text = String::from_utf8_lossy(&bytes[..complete]);
+
+    let mut entries: Vec<Entry> = Vec::new();
+    for line in text.lines() {
+        let continuation = line.starts_with([' ', '\t']) && !entries.is_empty();
+        if continuation {
+            entries.last_mut().unwrap().lines.push(line.to_string());
+        } else {
+            entries.push(Entry {
+                level: LEVEL.find(line).map(|m| Level::parse(m.as_str())),
+                lines: vec![line.to_string()],
+            });
+        }
+    }
+    Ok((entries, start + complete as u64))
+}
+
+/// The rotated copy of `path` with inode `id`, if it is still in the same
+/// directory. Symlinks and files outside the workspace are passed over.
+fn rotated_file(path: &Path, id: u64, workspace: &Workspace) -> Option<PathBuf> {
+    let name = path.file_name()?.to_string_lossy().into_owned();
+    let dir = path.parent()?;
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&name))
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .find(|entry| entry.metadata().is_ok_and(|metadata| file_id(&metadata) == id))
+        .and_then(|entry| workspace.resolve(entry.path().to_str()?).ok())
+}
+
+fn parse_cursor(cursor: &str) -> Result<(u64, u64)> {
+    cursor
+        .split_once(':')
+        .and_then(|(id, offset)| Some((id.parse().ok()?, offset.parse().ok()?)))
+        .ok_or_else(|| anyhow!("Invalid cursor '{}'", cursor))
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+/// Without inodes, only truncation is detected.
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const ALL: Filter = Filter { pattern: None, level: None };
+
+    fn workspace(dir: &tempfile::TempDir) -> Workspace {
+        Workspace::new(dir.path().to_path_buf())
+    }
+
+    fn append(path: &Path, text: &str) {
+        fs::OpenOptions::new().create(true).append(true).open(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn cursor_returns_only_new_complete_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "INFO one\nINFO two\n");
+        let first = tail(&path, None, 1, &ALL, &workspace(&dir)).unwrap();
+        assert_eq!(first.lines, ["INFO two"]);
+
+        append(&path, "ERROR three\n  at main.rs:1\nINFO parti");
+        let next = tail(&path, Some(&first.cursor), 10, &ALL, &workspace(&dir)).unwrap();
+        assert_eq!(next.lines, ["ERROR three", "  at main.rs:1"]);
+        assert!(!next.rotated);
+
+        append(&path, "al\n");
+        let last = tail(&path, Some(&next.cursor), 10, &ALL, &workspace(&dir)).unwrap();
+        assert_eq!(last.lines, ["INFO partial"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cursor_follows_a_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "INFO before\n");
+        let cursor = tail(&path, None, 10, &ALL, &workspace(&dir)).unwrap().cursor;
+
+        append(&path, "INFO written before rotation");
+        fs::rename(&path, dir.path().join("app.log.1")).unwrap();
+        append(&path, "INFO after\n");
+        let next = tail(&path, Some(&cursor), 10, &ALL, &workspace(&dir)).unwrap();
+        assert!(next.rotated);
+        assert_eq!(next.lines, ["INFO written before rotation", "INFO after"]);
+        assert_eq!(tail(&path, Some(&next.cursor), 10, &ALL, &workspace(&dir)).unwrap().lines, Vec::<String>::new());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rotation_ignores_symlinks_to_the_old_file() {
+        let (dir, outside) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let path = dir.path().join("app.log");
+        append(&path, "INFO before\n");
+        let cursor = tail(&path, None, 10, &ALL, &workspace(&dir)).unwrap().cursor;
+
+        // The old file leaves the workspace and only a symlink to it stays.
+        append(&path, "INFO secret\n");
+        let moved = outside.path().join("app.log.1");
+        fs::rename(&path, &moved).unwrap();
+        std::os::unix::fs::symlink(&moved, dir.path().join("app.log.1")).unwrap();
+        append(&path, "INFO after\n");
+        let next = tail(&path, Some(&cursor), 10, &ALL, &workspace(&dir)).unwrap();
+        assert!(next.rotated);
+        assert_eq!(next.lines, ["INFO after"]);
+    }
+
+    #[test]
+    fn first_read_stops_after_max_follow_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let filler = format!("{}\n", "x".repeat(1023));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"ERROR too old\n").unwrap();
+        for _ in 0..MAX_FOLLOW_BYTES / 1024 {
+            file.write_all(filler.as_bytes()).unwrap();
+        }
+        let errors = Filter { pattern: None, level: Some(Level::Error) };
+        assert_eq!(tail(&path, None, 10, &errors, &workspace(&dir)).unwrap().lines, Vec::<String>::new());
+    }
+
+    #[test]
+    fn truncation_reads_from_the_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "INFO a long first line\n");
+        let cursor = tail(&path, None, 10, &ALL, &workspace(&dir)).unwrap().cursor;
+        fs::write(&path, "INFO new\n").unwrap();
+        let next = tail(&path, Some(&cursor), 10, &ALL, &workspace(&dir)).unwrap();
+        assert!(next.rotated);
+        assert_eq!(next.lines, ["INFO new"]);
+    }
+
+    #[test]
+    fn filters_apply_to_whole_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "INFO ok\nERROR failed\n  caused by: timeout\nDEBUG noise\n");
+        let errors = Filter { pattern: None, level: Some(Level::Warn) };
+        assert_eq!(tail(&path, None, 10, &errors, &workspace(&dir)).unwrap().lines, ["ERROR failed", "  caused by: timeout"]);
+        let timeout = Filter { pattern: Some(Regex::new("timeout").unwrap()), level: None };
+        assert_eq!(tail(&path, None, 10, &timeout, &workspace(&dir)).unwrap().lines, ["ERROR failed", "  caused by: timeout"]);
+        assert!(tail(&path, Some("nonsense"), 10, &ALL, &workspace(&dir)).is_err());
+    }
+}
@@ -0,0 +1,13 @@
+//! The built-in tools. [`ToolRegistry::builtin`](crate::registry::ToolRegistry::builtin) registers all of them.
+
+pub mod checkpoints;
+pub mod files;
+pub mod logs;
+pub mod notebook;
+pub mod openwebui;
+pub mod read_more;
+pub mod scratchpad;
+pub mod search;
+pub mod shell;
+pub mod summarize;
+pub mod tables;
@@ -0,0 +1,583 @@
+//! Jupyter notebook tools that work on cells instead of raw `.ipynb` JSON.
+//!
+//! Notebooks are edited as JSON values, so metadata and fields this module
+//! doesn't know about survive an edit. Only nbformat 4 is supported.
+
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+    ErrorData as McpError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::BuildHasher;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
+
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+
+static ANSI_ESCAPE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\x1b\[[0-9;?]*[A-Za-z]").unwrap());
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ReadNotebookParams {
+    pub path: String,
+    /// Only this cell, counting from 0; every cell when omitted.
+    pub cell: Option<usize>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct EditNotebookParams {
+    pub path: String,
+    pub operation: EditOperation,
+    /// The cell to replace or delete, or the position a new cell is inserted
+    /// at (the number of cells appends it), counting from 0.
+    pub cell: usize,
+    /// The cell's new source; required for `insert` and `replace`.
+    pub source: Option<String>,
+    /// Defaults to `code` for `insert` and to the cell's current type for `replace`.
+    pub cell_type: Option<CellType>,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EditOperation {
+    Insert,
+    /// Replaces the source and clears the outputs of a code cell.
+    Replace,
+    Delete,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CellType {
+    Code,
+    Markdown,
+    Raw,
+}
+
+impl CellType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CellType::Code => "code",
+            CellType::Markdown => "markdown",
+            CellType::Raw => "raw",
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct NotebookContent {
+    pub path: String,
+    /// The kernel's language, e.g. `python`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Total number of cells in the notebook.
+    pub cell_count: usize,
+    pub cells: Vec<NotebookCell>,
+    /// Pass to `read_more` for the rest of the output; missing when nothing was cut off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct NotebookCell {
+    pub index: usize,
+    pub cell_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution_count: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<CellOutput>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CellOutput {
+    /// `stream`, `execute_result`, `display_data` or `error`.
+    pub output_type: String,
+    /// Text output; images and other binary data are replaced by a note naming their type.
+    pub text: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct NotebookEdit {
+    pub path: String,
+    /// The cell that was inserted, replaced or deleted.
+    pub cell: usize,
+    /// The id of the inserted or replaced cell, for notebooks that have cell ids.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Number of cells after the edit.
+    pub cell_count: usize,
+}
+
+pub struct ReadNotebook;
+
+impl Tool for ReadNotebook {
+    fn name(&self) -> &str {
+        "read_notebook"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Read Notebook")
+    }
+
+    fn description(&self) -> &str {
+        "Reads a Jupyter notebook (.ipynb) as numbered cells with their type, source and text outputs. \
+         Images and other binary outputs are left out."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<ReadNotebookParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<NotebookContent>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Read Notebook")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: ReadNotebookParams = tool::parse_arguments(arguments)?;
+            let (language, cell_count, mut cells) = context
+                .workspace
+                .resolve(&params.path)
+                .and_then(|path| read_notebook(&path, params.cell))
+                .map_err(tool::internal_error)?;
+            let blocks: Vec<String> = cells.iter().map(render_cell).collect();
+            let page = context.first_page(blocks.join("\n"));
+            if page.next_cursor.is_some() {
+                // Keep the cells the first page shows in full.
+                let mut end = 0;
+                let shown = blocks
+                    .iter()
+                    .take_while(|block| {
+                        end += block.len() + 1;
+                        end <= page.text.len() + 1
+                    })
+                    .count();
+                cells.truncate(shown);
+            }
+            let output = NotebookContent {
+                path: params.path,
+                language,
+                cell_count,
+                cells,
+                next_cursor: page.next_cursor.clone(),
+            };
+            Ok(tool::structured_result(page.render(), &output))
+        })
+    }
+}
+
+pub struct EditNotebook;
+
+impl Tool for EditNotebook {
+    fn name(&self) -> &str {
+        "edit_notebook"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Edit Notebook")
+    }
+
+    fn description(&self) -> &str {
+        "Inserts, replaces or deletes one cell of a Jupyter notebook (.ipynb), keeping the rest of the \
+         notebook intact. Replacing a code cell clears its outputs."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<EditNotebookParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<NotebookEdit>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Edit Notebook")
+            .read_only(false)
+            .destructive(true)
+            .idempotent(false)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: EditNotebookParams = tool::parse_arguments(arguments)?;
+            if params.operation != EditOperation::Delete && params.source.is_none() {
+                return Err(McpError::invalid_params("`source` is required to insert or replace a cell", None));
+            }
+            let output = context
+                .workspace
+                .resolve(&params.path)
+                .and_then(|path| edit_notebook(&path, &params))
+                .map_err(tool::internal_error)?;
+            let action = match params.operation {
+                EditOperation::Insert => "Inserted",
+                EditOperation::Replace => "Replaced",
+                EditOperation::Delete => "Deleted",
+            };
+            let text = format!(
+                "{} cell {} in {}. The notebook now has {} cells.",
+                action, output.cell, params.path, output.cell_count
+            );
+            Ok(tool::structured_result(text, &output))
+        })
+    }
+}
+
+/// The kernel language, the number of cells, and `only` or every cell.
+pub fn read_notebook(path: &Path, only: Option<usize>) -> Result<(Option<String>, usize, Vec<NotebookCell>)> {
+    let notebook = load(path)?;
+    let cells = cells(&notebook)?;
+    let language = notebook
+        .pointer("/metadata/kernelspec/language")
+        .or_else(|| notebook.pointer("/metadata/language_info/name"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let selected = match only {
+        Some(index) if index >= cells.len() => bail!("Cell {} does not exist; the notebook has {} cells", index, cells.len()),
+        Some(index) => index..index + 1,
+        None => 0..cells.len(),
+    };
+    let read = selected.map(|index| read_cell(index, &cells[index])).collect();
+    Ok((language, cells.len(), read))
+}
+
+pub fn edit_notebook(path: &Path, params: &EditNotebookParams) -> Result<NotebookEdit> {
+    let mut notebook = load(path)?;
+    let with_ids = notebook["nbformat_minor"].as_u64().unwrap_or(0) >= 5;
+    let ids: Vec<String> = cells(&notebook)?
+        .iter()
+        .filter_map(|cell| cell["id"].as_str().map(str::to_string))
+        .collect();
+    let cells = notebook["cells"].as_array_mut().expect("checked by cells()");
+    let index = params.cell;
+    let source = params.source.as_deref().unwrap_or_default();
+
+    let id = match params.operation {
+        EditOperation::Insert => {
+            if index > cells.len() {
+                bail!("Cannot insert at {}; the notebook has {} cells", index, cells.len());
+            }
+            let cell_type = params.cell_type.unwrap_or(CellType::Code);
+            let mut cell = json!({ "cell_type": cell_type.as_str(), "metadata": {}, "source": source_lines(source) });
+            if cell_type == CellType::Code {
+                cell["execution_count"] = Value::Null;
+                cell["outputs"] = json!([]);
+            }
+            if with_ids {
+                cell["id"] = Value::String(new_id(&ids));
+            }
+            cells.insert(index, cell);
+            with_ids.then(|| cells[index]["id"].as_str().unwrap_or_default().to_string())
+        }
+        EditOperation::Replace => {
+            let count = cells.len();
+            let cell = cells
+                .get_mut(index)
+                .and_then(Value::as_object_mut)
+                .ok_or_else(|| anyhow!("Cell {} does not exist; the notebook has {} cells", index, count))?;
+            let cell_type = match params.cell_type {
+                Some(cell_type) => cell_type.as_str(),
+                None => cell.get("cell_type").and_then(Value::as_str).unwrap_or("code"),
+            }
+            .to_string();
+            set_type(cell, &cell_type);
+            cell.insert("source".into(), source_lines(source));
+            if with_ids && !cell.contains_key("id") {
+                cell.insert("id".into(), Value::String(new_id(&ids)));
+            }
+            cell.get("id").and_then(Value::as_str).map(str::to_string)
+        }
+        EditOperation::Delete => {
+            if index >= cells.len() {
+                bail!("Cell {} does not exist; the notebook has {} cells", index, cells.len());
+            }
+            cells.remove(index);
+            None
+        }
+    };
+    let cell_count = cells.len();
+    save(path, &notebook)?;
+    Ok(NotebookEdit {
+        path: params.path.clone(),
+        cell: index,
+        id,
+        cell_count,
+    })
+}
+
+fn load(path: &Path) -> Result<Value> {
+    let text = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read notebook '{}': {}", path.display(), e))?;
+    let notebook: Value =
+        serde_json::from_str(&text).map_err(|e| anyhow!("Invalid notebook '{}': {}", path.display(), e))?;
+    match notebook["nbformat"].as_u64() {
+        Some(4) => Ok(notebook),
+        Some(version) => bail!("Notebook '{}' uses nbformat {}; only nbformat 4 is supported", path.display(), version),
+        None => bail!("'{}' is not a Jupyter notebook", path.display()),
+    }
+}
+
+/// Writes the notebook the way Jupyter does: one-space indent, sorted keys and a final newline.
+/// It is written to a uniquely named file next to it first and renamed over
+/// it, so readers and concurrent edits never see half a notebook.
+fn save(path: &Path, notebook: &Value) -> Result<()> {
+    let mut bytes = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b" ");
+    notebook.serialize(&mut serde_json::Serializer::with_formatter(&mut bytes, formatter))?;
+    bytes.push(b'\n');
+    let partial = path.with_extension(format!("{:016x}.partial", RandomState::new().hash_one(Instant::now())));
+    let written = fs::write(&partial, bytes)
+        .and_then(|()| match fs::metadata(path) {
+            Ok(metadata) => fs::set_permissions(&partial, metadata.permissions()),
+            Err(_) => Ok(()),
+        })
+        .and_then(|()| fs::rename(&partial, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    written.map_err(|e| anyhow!("Failed to write notebook '{}': {}", path.display(), e))
+}
+
+fn cells(notebook: &Value) -> Result<&Vec<Value>> {
+    notebook["cells"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Notebook has no cell list"))
+}
+
+/// Switches a cell's type, adding or removing the fields only code cells have.
+/// A code cell's outputs are cleared, since they no longer match its source.
+fn set_type(cell: &mut Map<String, Value>, cell_type: &str) {
+    cell.insert("cell_type".into(), Value::String(cell_type.to_string()));
+    if cell_type == "code" {
+        cell.insert("execution_count".into(), Value::Null);
+        cell.insert("outputs".into(), json!([]));
+        cell.remove("attachments");
+    } else {
+        cell.remove("execution_count");
+        cell.remove("outputs");
+    }
+}
+
+/// Source in Jupyter's form: a list of lines, each keeping its newline.
+fn source_lines(source: &str) -> Value {
+    source.split_inclusive('\n').map(|line| Value::String(line.to_string())).collect()
+}
+
+/// Multiline strings may be stored as one string or as a list of lines.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn new_id(taken: &[String]) -> String {
+    loop {
+        let id = format!("{:08x}", RandomState::new().hash_one(Instant::now()) as u32);
+        if !taken.contains(&id) {
+            return id;
+        }
+    }
+}
+
+fn read_cell(index: usize, cell: &Value) -> NotebookCell {
+    NotebookCell {
+        index,
+        cell_type: cell["cell_type"].as_str().unwrap_or("unknown").to_string(),
+        id: cell["id"].as_str().map(str::to_string),
+        source: text(&cell["source"]),
+        execution_count: cell["execution_count"].as_u64(),
+        outputs: cell["outputs"]
+            .as_array()
+            .map(|outputs| outputs.iter().map(read_output).collect())
+            .unwrap_or_default(),
+    }
+}
+
+fn read_output(output: &Value) -> CellOutput {
+    let output_type = output["output_type"].as_str().unwrap_or("unknown").to_string();
+    let text = match output_type.as_str() {
+        "stream" => text(&output["text"]),
+        "error" => match output["traceback"].as_array() {
+            Some(lines) if !lines.is_empty() => lines.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("\n"),
+            _ => format!(
+                "{}: {}",
+                output["ename"].as_str().unwrap_or_default(),
+                output["evalue"].as_str().unwrap_or_default()
+            ),
+        },
+        _ => match output["data"].as_object() {
+            Some(data) => data_text(data),
+            None => String::new(),
+        },
+    };
+    CellOutput {
+        output_type,
+        text: ANSI_ESCAPE.replace_all(&text, "").into_owned(),
+    }
+}
+
+/// The most readable text representation of rich output, with notes for binary data.
+fn data_text(data: &Map<String, Value>) -> String {
+    let preferred = ["text/plain", "text/markdown", "application/json", "text/html", "text/latex"];
+    let mut parts: Vec<String> = preferred
+        .iter()
+        .find_map(|mime| data.get(*mime))
+        .map(|value| match value {
+            Value::Object(_) => serde_json::to_string_pretty(value).unwrap_or_default(),
+            _ => text(value),
+        })
+        .into_iter()
+        .collect();
+    parts.extend(
+        data.keys()
+            .filter(|mime| !mime.starts_with("text/") && !mime.ends_with("json"))
+            .map(|mime| format!("[{} output omitted]", mime)),
+    );
+    parts.join("\n")
+}
+
+fn render_cell(cell: &NotebookCell) -> String {
+    let mut header = format!("--- cell {} ({}", cell.index, cell.cell_type);
+    if let Some(count) = cell.execution_count {
+        header.push_str(&format!(", run {}", count));
+    }
+    header.push_str(") ---");
+    let mut block = format!("{}\n{}", header, cell.source.trim_end_matches('\n'));
+    for output in &cell.outputs {
+        block.push_str(&format!(
+            "\n--- output ({}) ---\n{}",
+            output.output_type,
+            output.text.trim_end_matches('\n')
+        ));
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A notebook with a markdown cell and a code cell with output, in a temporary directory.
+    fn notebook(minor: u64) -> (tempfile::TempDir, std::path::PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("analysis.ipynb");
+        let notebook = json!({
+            "nbformat": 4,
+            "nbformat_minor": minor,
+            "metadata": {"kernelspec": {"language": "python"}},
+            "cells": [
+                {"cell_type": "markdown", "id": "aaaa0001", "metadata": {}, "source": ["# Title\n", "Intro"]},
+                {
+                    "cell_type": "code", "id": "aaaa0002", "metadata": {}, "execution_count": 3,
+                    "source": "print(1)",
+                    "outputs": [{"output_type": "stream", "name": "stdout", "text": ["1\n"]}],
+                },
+            ],
+        });
+        fs::write(&path, notebook.to_string()).unwrap();
+        (dir, path)
+    }
+
+    fn edit(path: &Path, operation: EditOperation, cell: usize, source: Option<&str>, cell_type: Option<CellType>) -> NotebookEdit {
+        let params = EditNotebookParams {
+            path: "analysis.ipynb".into(),
+            operation,
+            cell,
+            source: source.map(str::to_string),
+            cell_type,
+        };
+        edit_notebook(path, &params).unwrap()
+    }
+
+    #[test]
+    fn insert_adds_a_code_cell_with_an_id() {
+        let (_dir, path) = notebook(5);
+        let result = edit(&path, EditOperation::Insert, 1, Some("x = 1\ny = 2"), None);
+        assert_eq!(result.cell_count, 3);
+        assert!(result.id.is_some());
+
+        let (language, count, cells) = read_notebook(&path, None).unwrap();
+        assert_eq!((language.as_deref(), count), (Some("python"), 3));
+        assert_eq!((cells[1].cell_type.as_str(), cells[1].source.as_str()), ("code", "x = 1\ny = 2"));
+        assert_eq!(cells[2].source, "print(1)");
+
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["cells"][1]["source"], json!(["x = 1\n", "y = 2"]));
+        assert_eq!(saved["cells"][1]["outputs"], json!([]));
+    }
+
+    #[test]
+    fn saving_leaves_no_partial_files() {
+        let (dir, path) = notebook(5);
+        edit(&path, EditOperation::Delete, 0, None, None);
+        let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(names, ["analysis.ipynb"]);
+        assert_eq!(read_notebook(&path, None).unwrap().1, 1);
+    }
+
+    #[test]
+    fn replace_clears_outputs_and_can_change_the_type() {
+        let (_dir, path) = notebook(5);
+        let result = edit(&path, EditOperation::Replace, 1, Some("print(2)"), None);
+        assert_eq!(result.id.as_deref(), Some("aaaa0002"));
+        let (_, _, cells) = read_notebook(&path, Some(1)).unwrap();
+        assert_eq!(cells[0].source, "print(2)");
+        assert!(cells[0].outputs.is_empty());
+        assert_eq!(cells[0].execution_count, None);
+
+        edit(&path, EditOperation::Replace, 1, Some("Notes"), Some(CellType::Markdown));
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["cells"][1]["cell_type"], "markdown");
+        assert!(saved["cells"][1].get("outputs").is_none());
+        assert!(saved["cells"][1].get("execution_count").is_none());
+    }
+
+    #[test]
+    fn delete_removes_the_cell() {
+        let (_dir, path) = notebook(5);
+        assert_eq!(edit(&path, EditOperation::Delete, 0, None, None).cell_count, 1);
+        let (_, _, cells) = read_notebook(&path, None).unwrap();
+        assert_eq!(cells[0].source, "print(1)");
+    }
+
+    #[test]
+    fn older_notebooks_get_no_ids() {
+        let (_dir, path) = notebook(4);
+        assert_eq!(edit(&path, EditOperation::Insert, 2, Some("z"), Some(CellType::Raw)).id, None);
+    }
+
+    #[test]
+    fn edits_out_of_range_fail_without_writing() {
+        let (_dir, path) = notebook(5);
+        let before = fs::read_to_string(&path).unwrap();
+        for (operation, cell) in [(EditOperation::Insert, 3), (EditOperation::Replace, 2), (EditOperation::Delete, 2)] {
+            let params = EditNotebookParams {
+                path: "analysis.ipynb".into(),
+                operation,
+                cell,
+                source: Some("x".into()),
+                cell_type: None,
+            };
+            assert!(edit_notebook(&path, &params).is_err());
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+    }
+}
@@ -0,0 +1,458 @@
+//! The `openwebui_*` tools: knowledge, notes and memories of an Open WebUI
+//! account. Only registered when `openwebui.url` is configured.
+
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+    ErrorData as McpError,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::openwebui::{Collection, Memory, Note, OpenWebUi, RetrievalHit};
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+
+const DEFAULT_LIMIT: usize = 5;
+const MAX_LIMIT: usize = 50;
+
+/// The tools, sharing one client.
+pub fn tools(client: Arc<OpenWebUi>) -> Vec<Arc<dyn Tool>> {
+    vec![
+        Arc::new(SearchKnowledge(client.clone())),
+        Arc::new(ListKnowledge(client.clone())),
+        Arc::new(ReadKnowledge(client.clone())),
+        Arc::new(ListNotes(client.clone())),
+        Arc::new(ReadNote(client.clone())),
+        Arc::new(CreateNote(client.clone())),
+        Arc::new(AddMemory(client)),
+    ]
+}
+
+fn annotations(title: &str, read_only: bool) -> ToolAnnotations {
+    ToolAnnotations::with_title(title)
+        .read_only(read_only)
+        .destructive(false)
+        .idempotent(read_only)
+        .open_world(true)
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SearchKnowledgeParams {
+    /// Collection id from `openwebui_list_knowledge`.
+    pub collection: String,
+    pub query: String,
+    /// Most passages to return; 5 when omitted, at most 50.
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RetrievalHits {
+    pub hits: Vec<RetrievalHit>,
+}
+
+pub struct SearchKnowledge(Arc<OpenWebUi>);
+
+impl Tool for SearchKnowledge {
+    fn name(&self) -> &str {
+        "openwebui_search_knowledge"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Search Open WebUI Knowledge")
+    }
+
+    fn description(&self) -> &str {
+        "Searches an Open WebUI knowledge collection and returns the best matching passages."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<SearchKnowledgeParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<RetrievalHits>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        annotations("Search Open WebUI Knowledge", true)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: SearchKnowledgeParams = tool::parse_arguments(arguments)?;
+            if params.query.trim().is_empty() {
+                return Err(McpError::invalid_params("Query is empty", None));
+            }
+            let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+            let hits = self
+                .0
+                .search(&params.collection, &params.query, limit)
+                .await
+                .map_err(tool::internal_error)?;
+            let text = if hits.is_empty() {
+                "No matches.".to_string()
+            } else {
+                let text = hits
+                    .iter()
+                    .enumerate()
+                    .map(|(i, hit)| {
+                        let source = hit.source.as_deref().map(|source| format!(" ({})", source)).unwrap_or_default();
+                        format!("{}.{}\n{}", i + 1, source, hit.content.trim())
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                context.first_page(text).render()
+            };
+            Ok(tool::structured_result(text, &RetrievalHits { hits }))
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct NoParams {}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Collections {
+    pub collections: Vec<Collection>,
+}
+
+pub struct ListKnowledge(Arc<OpenWebUi>);
+
+impl Tool for ListKnowledge {
+    fn name(&self) -> &str {
+        "openwebui_list_knowledge"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("List Open WebUI Knowledge")
+    }
+
+    fn description(&self) -> &str {
+        "Lists the Open WebUI knowledge collections the account can read."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<NoParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<Collections>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        annotations("List Open WebUI Knowledge", true)
+    }
+
+    fn execute(&self, _arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let collections = self.0.collections().await.map_err(tool::internal_error)?;
+            let text = if collections.is_empty() {
+                "No knowledge collections.".to_string()
+            } else {
+                let text = collections
+                    .iter()
+                    .map(|collection| {
+                        let mut line = format!("{}  {}", collection.id, collection.name);
+                        if !collection.description.is_empty() {
+                            line.push_str(&format!(": {}", collection.description));
+                        }
+                        line
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                context.first_page(text).render()
+            };
+            Ok(tool::structured_result(text, &Collections { collections }))
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ReadKnowledgeParams {
+    /// Collection id from `openwebui_list_knowledge`.
+    pub collection: String,
+    /// A file id from the collection, to read that file's text instead of listing the files.
+    pub file: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct KnowledgeContent {
+    /// Set when a file was read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Pass to `read_more` for the rest of the file; missing when nothing was cut off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// Set when no file was given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection: Option<Collection>,
+}
+
+pub struct ReadKnowledge(Arc<OpenWebUi>);
+
+impl Tool for ReadKnowledge {
+    fn name(&self) -> &str {
+        "openwebui_read_knowledge"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Read Open WebUI Knowledge")
+    }
+
+    fn description(&self) -> &str {
+        "Describes an Open WebUI knowledge collection and lists its files, or returns the text of one of its files."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<ReadKnowledgeParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<KnowledgeContent>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        annotations("Read Open WebUI Knowledge", true)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: ReadKnowledgeParams = tool::parse_arguments(arguments)?;
+            let collection = self.0.collection(&params.collection).await.map_err(tool::internal_error)?;
+            let Some(file) = params.file else {
+                let mut text = format!("{}  {}", collection.id, collection.name);
+                if !collection.description.is_empty() {
+                    text.push_str(&format!("\n{}", collection.description));
+                }
+                if collection.files.is_empty() {
+                    text.push_str("\n\nNo files.");
+                } else {
+                    text.push_str("\n\nFiles:");
+                    for file in &collection.files {
+                        text.push_str(&format!("\n{}  {}", file.id, file.name.as_deref().unwrap_or("")));
+                    }
+                }
+                let output = KnowledgeContent {
+                    content: None,
+                    next_cursor: None,
+                    collection: Some(collection),
+                };
+                return Ok(tool::structured_result(context.first_page(text).render(), &output));
+            };
+            // Only files of the named collection, so the collection scopes what can be read.
+            if !collection.files.iter().any(|candidate| candidate.id == file) {
+                return Err(McpError::invalid_params(
+                    format!("Collection '{}' has no file '{}'", params.collection, file),
+                    None,
+                ));
+            }
+            let content = self.0.file_content(&file).await.map_err(tool::internal_error)?;
+            let page = context.first_page(content);
+            let output = KnowledgeContent {
+                content: Some(page.text.clone()),
+                next_cursor: page.next_cursor.clone(),
+                collection: None,
+            };
+            Ok(tool::structured_result(page.render(), &output))
+        })
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Notes {
+    pub notes: Vec<Note>,
+}
+
+pub struct ListNotes(Arc<OpenWebUi>);
+
+impl Tool for ListNotes {
+    fn name(&self) -> &str {
+        "openwebui_list_notes"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("List Open WebUI Notes")
+    }
+
+    fn description(&self) -> &str {
+        "Lists the Open WebUI notes the account can read, by id and title."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<NoParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<Notes>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        annotations("List Open WebUI Notes", true)
+    }
+
+    fn execute(&self, _arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let notes = self.0.notes().await.map_err(tool::internal_error)?;
+            let text = if notes.is_empty() {
+                "No notes.".to_string()
+            } else {
+                let text = notes
+                    .iter()
+                    .map(|note| format!("{}  {}", note.id, note.title))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                context.first_page(text).render()
+            };
+            Ok(tool::structured_result(text, &Notes { notes }))
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ReadNoteParams {
+    /// Note id from `openwebui_list_notes`.
+    pub id: String,
+}
+
+pub struct ReadNote(Arc<OpenWebUi>);
+
+impl Tool for ReadNote {
+    fn name(&self) -> &str {
+        "openwebui_read_note"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Read Open WebUI Note")
+    }
+
+    fn description(&self) -> &str {
+        "Returns the title and Markdown content of an Open WebUI note."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<ReadNoteParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<Note>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        annotations("Read Open WebUI Note", true)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: ReadNoteParams = tool::parse_arguments(arguments)?;
+            let note = self.0.note(&params.id).await.map_err(tool::internal_error)?;
+            let text = format!("# {}\n\n{}", note.title, note.content.as_deref().unwrap_or_default());
+            Ok(tool::structured_result(context.first_page(text).render(), &note))
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateNoteParams {
+    pub title: String,
+    /// The note's text, as Markdown.
+    pub content: String,
+}
+
+pub struct CreateNote(Arc<OpenWebUi>);
+
+impl Tool for CreateNote {
+    fn name(&self) -> &str {
+        "openwebui_create_note"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Create Open WebUI Note")
+    }
+
+    fn description(&self) -> &str {
+        "Creates an Open WebUI note with a title and Markdown content and returns its id."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<CreateNoteParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<Note>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        annotations("Create Open WebUI Note", false)
+    }
+
+    /// Writes to Open WebUI, not the workspace.
+    fn checkpoints(&self) -> bool {
+        false
+    }
+
+    fn execute(&self, arguments: JsonObject, _context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: CreateNoteParams = tool::parse_arguments(arguments)?;
+            if params.title.trim().is_empty() {
+                return Err(McpError::invalid_params("Title is empty", None));
+            }
+            let note = self
+                .0
+                .create_note(&params.title, &params.content)
+                .await
+                .map_err(tool::internal_error)?;
+            let output = Note { content: None, ..note };
+            Ok(tool::structured_result(format!("Created note {} ({}).", output.id, output.title), &output))
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct AddMemoryParams {
+    /// A fact for Open WebUI to remember about the user, e.g. "Prefers tabs over spaces".
+    pub content: String,
+}
+
+pub struct AddMemory(Arc<OpenWebUi>);
+
+impl Tool for AddMemory {
+    fn name(&self) -> &str {
+        "openwebui_add_memory"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Add Open WebUI Memory")
+    }
+
+    fn description(&self) -> &str {
+        "Adds a memory to the Open WebUI account, which its chats can then draw on."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<AddMemoryParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<Memory>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        annotations("Add Open WebUI Memory", false)
+    }
+
+    /// Writes to Open WebUI, not the workspace.
+    fn checkpoints(&self) -> bool {
+        false
+    }
+
+    fn execute(&self, arguments: JsonObject, _context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: AddMemoryParams = tool::parse_arguments(arguments)?;
+            if params.content.trim().is_empty() {
+                return Err(McpError::invalid_params("Content is empty", None));
+            }
+            let memory = self.0.add_memory(&params.content).await.map_err(tool::internal_error)?;
+            Ok(tool::structured_result(format!("Added memory {}.", memory.id), &memory))
+        })
+    }
+}
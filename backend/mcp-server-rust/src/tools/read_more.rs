@@ -0,0 +1,55 @@
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::pages::Page;
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CursorParams {
+    /// The cursor from the truncated result or the previous page.
+    pub cursor: String,
+}
+
+pub struct ReadMore;
+
+impl Tool for ReadMore {
+    fn name(&self) -> &str {
+        "read_more"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Read More")
+    }
+
+    fn description(&self) -> &str {
+        "Fetches the next page of a tool result that was too large to return at once."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<CursorParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<Page>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Read More")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: CursorParams = tool::parse_arguments(arguments)?;
+            let page = context.results.read(&params.cursor).map_err(tool::internal_error)?;
+            Ok(tool::structured_result(page.render(), &page))
+        })
+    }
+}
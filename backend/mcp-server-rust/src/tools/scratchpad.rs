@@ -0,0 +1,388 @@
+//! `todo_read`, `todo_write`, `memory_get`, `memory_set` and `memory_search`:
+//! a plan and notes kept per workspace, so an agent can pick up where it was
+//! after its context was truncated or the server restarted.
+
+use anyhow::anyhow;
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+    ErrorData as McpError,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::scratchpad::{Memory, Scratchpad, Todo, TodoStatus, TodoUpdate};
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 100;
+
+fn scratchpad(context: &ToolContext) -> Result<Arc<Scratchpad>, McpError> {
+    context
+        .scratchpad
+        .clone()
+        .ok_or_else(|| tool::internal_error(anyhow!("The scratchpad is disabled")))
+}
+
+/// Runs a blocking scratchpad operation on the workspace's primary root.
+async fn run<T: Send + 'static>(
+    context: &ToolContext,
+    operation: impl FnOnce(&Scratchpad, &std::path::Path) -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, McpError> {
+    let scratchpad = scratchpad(context)?;
+    let root = context.workspace.primary();
+    tokio::task::spawn_blocking(move || operation(&scratchpad, &root))
+        .await
+        .map_err(|e| tool::internal_error(e.into()))?
+        .map_err(tool::internal_error)
+}
+
+/// How long ago `time`, in seconds since the epoch, was.
+fn ago(time: u64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let secs = now.saturating_sub(time);
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+fn render_todos(todos: &[Todo]) -> String {
+    if todos.is_empty() {
+        return "The todo list is empty.".to_string();
+    }
+    todos
+        .iter()
+        .map(|todo| {
+            let mark = match todo.status {
+                TodoStatus::Pending => "[ ]",
+                TodoStatus::InProgress => "[>]",
+                TodoStatus::Completed => "[x]",
+                TodoStatus::Cancelled => "[-]",
+            };
+            format!(
+                "{} {}. {}  ({}, updated {})",
+                mark,
+                todo.id,
+                todo.content,
+                todo.status.as_str(),
+                ago(todo.updated)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_memory(memory: &Memory) -> String {
+    format!("{} (updated {}):\n{}", memory.key, ago(memory.updated), memory.value)
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TodoList {
+    pub todos: Vec<Todo>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct TodoReadParams {
+    /// Only items with this status.
+    pub status: Option<TodoStatus>,
+}
+
+pub struct TodoRead;
+
+impl Tool for TodoRead {
+    fn name(&self) -> &str {
+        "todo_read"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Read Todos")
+    }
+
+    fn description(&self) -> &str {
+        "Returns the workspace's todo list with each item's id, status and when it last changed. \
+         Read it when resuming work to see what is done and what is next."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<TodoReadParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<TodoList>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Read Todos")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: TodoReadParams = tool::parse_arguments(arguments)?;
+            let mut todos = run(&context, |scratchpad, root| scratchpad.todos(root)).await?;
+            if let Some(status) = params.status {
+                todos.retain(|todo| todo.status == status);
+            }
+            let text = context.first_page(render_todos(&todos)).render();
+            Ok(tool::structured_result(text, &TodoList { todos }))
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct TodoWriteParams {
+    /// The whole list in order. Include the `id` of existing items to keep them;
+    /// items without one are added. Existing items left out are removed.
+    pub todos: Vec<TodoUpdate>,
+    /// Only add and update the given items, keeping the rest of the list.
+    #[serde(default)]
+    pub merge: bool,
+}
+
+pub struct TodoWrite;
+
+impl Tool for TodoWrite {
+    fn name(&self) -> &str {
+        "todo_write"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Write Todos")
+    }
+
+    fn description(&self) -> &str {
+        "Writes the workspace's todo list, which persists across restarts. Each item has content and a \
+         status (pending, in_progress, completed or cancelled). Pass the full list, or set `merge` to \
+         only add and update items. Returns the list with ids."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<TodoWriteParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<TodoList>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Write Todos")
+            .read_only(false)
+            .destructive(false)
+            .idempotent(false)
+            .open_world(false)
+    }
+
+    /// The scratchpad lives outside the workspace.
+    fn checkpoints(&self) -> bool {
+        false
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: TodoWriteParams = tool::parse_arguments(arguments)?;
+            let todos = run(&context, move |scratchpad, root| {
+                scratchpad.write_todos(root, params.todos, params.merge)
+            })
+            .await?;
+            let text = context.first_page(render_todos(&todos)).render();
+            Ok(tool::structured_result(text, &TodoList { todos }))
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct MemoryGetParams {
+    pub key: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MemoryEntry {
+    /// Missing when there is no memory under the key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Memory>,
+}
+
+pub struct MemoryGet;
+
+impl Tool for MemoryGet {
+    fn name(&self) -> &str {
+        "memory_get"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Get Memory")
+    }
+
+    fn description(&self) -> &str {
+        "Returns the note stored under a key in the workspace's memory."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<MemoryGetParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<MemoryEntry>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Get Memory")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: MemoryGetParams = tool::parse_arguments(arguments)?;
+            let key = params.key.clone();
+            let memory = run(&context, move |scratchpad, root| scratchpad.memory(root, &key)).await?;
+            let text = match &memory {
+                Some(memory) => context.first_page(render_memory(memory)).render(),
+                None => format!("No memory under '{}'.", params.key),
+            };
+            Ok(tool::structured_result(text, &MemoryEntry { memory }))
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct MemorySetParams {
+    /// A short name for the note, e.g. "build-command" or "decision/auth-flow".
+    pub key: String,
+    /// The note. Omit to delete the memory under `key`.
+    pub value: Option<String>,
+}
+
+pub struct MemorySet;
+
+impl Tool for MemorySet {
+    fn name(&self) -> &str {
+        "memory_set"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Set Memory")
+    }
+
+    fn description(&self) -> &str {
+        "Stores a note under a key in the workspace's memory, replacing any note already there, or \
+         deletes it when no value is given. Memories persist across restarts; use them for facts, \
+         decisions and findings worth keeping."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<MemorySetParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<MemoryEntry>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Set Memory")
+            .read_only(false)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    /// The scratchpad lives outside the workspace.
+    fn checkpoints(&self) -> bool {
+        false
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: MemorySetParams = tool::parse_arguments(arguments)?;
+            let deleting = params.value.is_none();
+            let key = params.key.clone();
+            let memory = run(&context, move |scratchpad, root| {
+                scratchpad.set_memory(root, &key, params.value)
+            })
+            .await?;
+            let text = match (&memory, deleting) {
+                (Some(_), true) => format!("Deleted the memory under '{}'.", params.key.trim()),
+                (None, _) => format!("No memory under '{}'.", params.key.trim()),
+                (Some(memory), false) => format!("Stored the memory under '{}'.", memory.key),
+            };
+            let output = MemoryEntry {
+                memory: memory.filter(|_| !deleting),
+            };
+            Ok(tool::structured_result(text, &output))
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct MemorySearchParams {
+    /// Words to look for in keys and notes, ignoring case. Empty to list the most recent memories.
+    #[serde(default)]
+    pub query: String,
+    /// Most memories to return; 10 when omitted, at most 100.
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MemoryMatches {
+    pub memories: Vec<Memory>,
+}
+
+pub struct MemorySearch;
+
+impl Tool for MemorySearch {
+    fn name(&self) -> &str {
+        "memory_search"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Search Memory")
+    }
+
+    fn description(&self) -> &str {
+        "Finds notes in the workspace's memory whose key or text contains the query's words, best \
+         matches first. With an empty query, lists the most recently updated notes."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<MemorySearchParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<MemoryMatches>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Search Memory")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: MemorySearchParams = tool::parse_arguments(arguments)?;
+            let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+            let memories = run(&context, move |scratchpad, root| {
+                scratchpad.search_memories(root, &params.query, limit)
+            })
+            .await?;
+            let text = if memories.is_empty() {
+                "No matching memories.".to_string()
+            } else {
+                let text = memories.iter().map(render_memory).collect::<Vec<_>>().join("\n\n");
+                context.first_page(text).render()
+            };
+            Ok(tool::structured_result(text, &MemoryMatches { memories }))
+        })
+    }
+}
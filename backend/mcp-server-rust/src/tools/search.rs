@@ -0,0 +1,102 @@
+use anyhow::anyhow;
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+    ErrorData as McpError,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::search::SearchHit;
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 50;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SearchParams {
+    /// Words to look for, ranked by relevance. Supports `"exact phrases"`,
+    /// `+required` and `-excluded` words.
+    pub query: String,
+    /// Limits the search to this file or directory.
+    pub path: Option<String>,
+    /// Most results to return; 10 when omitted, at most 50.
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SearchResults {
+    /// Best match first.
+    pub hits: Vec<SearchHit>,
+}
+
+pub struct SearchCode;
+
+impl Tool for SearchCode {
+    fn name(&self) -> &str {
+        "search_code"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Search Code")
+    }
+
+    fn description(&self) -> &str {
+        "Full-text search over the workspace, ranked by relevance. Finds code and docs by what they are about \
+         (e.g. \"auth token refresh\"), matching parts of identifiers such as refreshAuthToken. \
+         Returns snippets with paths and line numbers."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<SearchParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<SearchResults>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Search Code")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: SearchParams = tool::parse_arguments(arguments)?;
+            if params.query.trim().is_empty() {
+                return Err(McpError::invalid_params("Query is empty", None));
+            }
+            let index = context
+                .search
+                .clone()
+                .ok_or_else(|| tool::internal_error(anyhow!("Search is disabled")))?;
+            let scope = params
+                .path
+                .as_deref()
+                .map(|path| context.workspace.resolve(path))
+                .transpose()
+                .map_err(tool::internal_error)?;
+            let roots = context.workspace.roots();
+            let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+            let hits = tokio::task::spawn_blocking(move || index.search(&roots, scope.as_deref(), &params.query, limit))
+                .await
+                .map_err(|e| tool::internal_error(e.into()))?
+                .map_err(tool::internal_error)?;
+            let text = if hits.is_empty() {
+                "No matches.".to_string()
+            } else {
+                hits.iter()
+                    .map(|hit| format!("{}:{}\n{}", hit.path, hit.line, hit.snippet))
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            };
+            Ok(tool::structured_result(
+                context.first_page(text).render(),
+                &SearchResults { hits },
+            ))
+        })
+    }
+}
@@ -0,0 +1,631 @@
+use anyhow::{Result, anyhow};
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+    ErrorData as McpError,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::config::ShellConfig;
+use crate::metrics::metrics;
+use crate::pages;
+use crate::shell_sessions::{self, ShellSession};
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+use crate::workspace::Workspace;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CmdParams {
+    pub cmd: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ShellOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// Missing when the process was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// Pass to `read_more` for the rest of the output; missing when nothing was cut off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+impl ShellOutput {
+    /// Plain-text rendering: stdout, then stderr after a separator.
+    pub fn text(&self) -> String {
+        let mut result = self.stdout.clone();
+        if !self.stderr.is_empty() {
+            if !result.is_empty() {
+                result.push_str("\n--- stderr ---\n");
+            }
+            result.push_str(&self.stderr);
+        }
+        result
+    }
+}
+
+pub struct ShellCommand;
+
+impl Tool for ShellCommand {
+    fn name(&self) -> &str {
+        "shell_command"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Run Shell Command")
+    }
+
+    fn description(&self) -> &str {
+        "Executes a safe terminal command."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<CmdParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<ShellOutput>())
+    }
+
+    // Flagged destructive so clients never auto-approve shell access.
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Run Shell Command")
+            .read_only(false)
+            .destructive(true)
+            .idempotent(false)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: CmdParams = tool::parse_arguments(arguments)?;
+            let mut output = shell_command(params.cmd, &context.workspace, &context.shell)
+                .await
+                .map_err(tool::internal_error)?;
+            // Only stdout is paged; stderr is returned whole after the first page.
+            let page = context.first_page(std::mem::take(&mut output.stdout));
+            output.stdout = page.text;
+            output.next_cursor = page.next_cursor;
+            let text = output.text() + &pages::continuation(&output.next_cursor);
+            Ok(tool::structured_result(text, &output))
+        })
+    }
+}
+
+/// Runs `cmd` in the primary root if its program is allowed by `shell`.
+pub async fn shell_command(cmd: String, workspace: &Workspace, shell: &ShellConfig) -> Result<ShellOutput> {
+    let parts = parse(&cmd)?;
+    check(&parts, &workspace.primary(), workspace, &shell.allowed_commands)?;
+    run(&parts, &workspace.primary(), &BTreeMap::new(), Duration::from_secs(shell.timeout_secs)).await
+}
+
+fn parse(cmd: &str) -> Result<Vec<String>> {
+    // Simple parsing to check the command program
+    let parts: Vec<String> = shlex::split(cmd)
+        .ok_or_else(|| anyhow!("Failed to parse command"))?;
+
+    if parts.is_empty() {
+        return Err(anyhow!("Empty command"));
+    }
+    Ok(parts)
+}
+
+/// Refuses programs off the allowlist and path-like arguments outside the workspace.
+fn check(parts: &[String], cwd: &Path, workspace: &Workspace, allowed: &[String]) -> Result<()> {
+    let program = &parts[0];
+    if !allowed.contains(program) {
+        metrics().rejection("command_not_allowed");
+        return Err(anyhow!("Command '{}' is not allowed.", program));
+    }
+    let refused = REFUSED_ARGS
+        .iter()
+        .filter(|(name, _)| name == program)
+        .flat_map(|(_, args)| args.iter());
+    for refused in refused {
+        if parts[1..].iter().any(|arg| arg == refused) {
+            metrics().rejection("command_not_allowed");
+            return Err(anyhow!("'{} {}' is not allowed.", program, refused));
+        }
+    }
+
+    if let Some(arg) = parts[1..].iter().find(|arg| follows_symlinks(program, arg)) {
+        metrics().rejection("outside_workspace");
+        return Err(anyhow!("'{} {}' follows symlinks, which may lead outside the workspace.", program, arg));
+    }
+
+    // Any argument may be a path, so every one must resolve inside the
+    // workspace roots, symlinks included, as must paths attached to an option.
+    for arg in &parts[1..] {
+        let outside = path_candidates(arg)
+            .into_iter()
+            .any(|path| workspace.resolve_in(cwd, path).is_err());
+        if outside {
+            metrics().rejection("outside_workspace");
+            return Err(anyhow!("Argument '{}' is outside the workspace.", arg));
+        }
+    }
+    Ok(())
+}
+
+/// Arguments that make an allowed program run other programs or change
+/// files, so it can't be used to get around the allowlist.
+const REFUSED_ARGS: &[(&str, &[&str])] = &[(
+    "find",
+    &["-exec", "-execdir", "-ok", "-okdir", "-delete", "-fprint", "-fprint0", "-fprintf", "-fls"],
+)];
+
+/// Flags that make a program follow symlinks it finds while walking the
+/// tree: short letters, which may be grouped, and options, which may be
+/// abbreviated.
+const FOLLOW_FLAGS: &[(&str, &[char], &[&str])] = &[
+    ("ls", &['L', 'H'], &["--dereference", "--dereference-command-line", "--dereference-command-line-symlink-to-dir"]),
+    ("grep", &['R'], &["--dereference-recursive"]),
+    ("find", &[], &["-L", "-H", "-follow"]),
+];
+
+fn follows_symlinks(program: &str, arg: &str) -> bool {
+    FOLLOW_FLAGS.iter().filter(|(name, _, _)| *name == program).any(|(_, letters, options)| {
+        let option = arg.split_once('=').map_or(arg, |(option, _)| option);
+        options.contains(&arg)
+            || option.len() > 3 && option.starts_with("--") && options.iter().any(|o| o.starts_with(option))
+            || !arg.starts_with("--") && arg.starts_with('-') && arg[1..].chars().any(|c| letters.contains(&c))
+    })
+}
+
+/// The parts of an argument a program may open as a path: the argument, the
+/// value after `=` (`--output=/x`, `if=/x`) and, for short options, whatever
+/// follows each flag letter (`-f/x`, `-nf/x`).
+fn path_candidates(arg: &str) -> Vec<&str> {
+    let mut candidates = vec![arg];
+    if let Some((_, value)) = arg.split_once('=') {
+        candidates.push(value);
+    }
+    if arg.starts_with('-') && !arg.starts_with("--") {
+        candidates.extend(arg.char_indices().skip(2).map(|(i, _)| &arg[i..]));
+    }
+    candidates
+}
+
+/// Finds `program` on the server's own `PATH`, so the environment a command
+/// runs with can't change which executable that is.
+fn resolve_program(program: &str) -> Result<PathBuf> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| anyhow!("Command '{}' was not found on the server's PATH.", program))
+}
+
+/// Variables commands inherit from the server; everything else in its
+/// environment, such as `HOME` or credentials, is left out.
+const INHERITED_ENV: &[&str] = &["PATH", "LANG", "LC_ALL", "LC_CTYPE", "LC_MESSAGES", "TZ"];
+
+/// Runs a checked command in `cwd` with only the inherited variables and
+/// `env`. The command is killed once it runs longer than `timeout`.
+async fn run(parts: &[String], cwd: &Path, env: &BTreeMap<String, Option<String>>, timeout: Duration) -> Result<ShellOutput> {
+    let mut command = Command::new(resolve_program(&parts[0])?);
+    command
+        .args(&parts[1..])
+        .current_dir(cwd)
+        .env_clear()
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    for name in INHERITED_ENV {
+        if let Some(value) = std::env::var_os(name) {
+            command.env(name, value);
+        }
+    }
+    for (name, value) in env {
+        match value {
+            Some(value) => command.env(name, value),
+            None => command.env_remove(name),
+        };
+    }
+
+    let _running = metrics().process();
+    let output = tokio::time::timeout(timeout, command.output())
+        .await
+        .map_err(|_| anyhow!("Command '{}' timed out after {} seconds and was killed.", parts[0], timeout.as_secs()))?
+        .map_err(|e| anyhow!("Failed to execute command: {}", e))?;
+
+    Ok(ShellOutput {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        exit_code: output.status.code(),
+        next_cursor: None,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SessionOpenParams {
+    /// Starting directory; the primary workspace root when omitted.
+    pub cwd: Option<String>,
+    /// Variables set for every command in the session.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SessionInfo {
+    /// Pass to `session_exec` and `session_close`.
+    pub session: String,
+    pub cwd: String,
+}
+
+pub struct SessionOpen;
+
+impl Tool for SessionOpen {
+    fn name(&self) -> &str {
+        "session_open"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Open Shell Session")
+    }
+
+    fn description(&self) -> &str {
+        "Opens a shell session with a working directory and environment variables that persist \
+         across `session_exec` calls. Close it with `session_close` when done."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<SessionOpenParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<SessionInfo>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Open Shell Session")
+            .read_only(false)
+            .destructive(false)
+            .idempotent(false)
+            .open_world(false)
+    }
+
+    /// Opening a session runs nothing.
+    fn checkpoints(&self) -> bool {
+        false
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: SessionOpenParams = tool::parse_arguments(arguments)?;
+            let cwd = match &params.cwd {
+                Some(cwd) => context.workspace.resolve(cwd).map_err(tool::internal_error)?,
+                None => context.workspace.primary(),
+            };
+            if !cwd.is_dir() {
+                return Err(McpError::invalid_params(
+                    format!("'{}' is not a directory", cwd.display()),
+                    None,
+                ));
+            }
+            let mut env = BTreeMap::new();
+            for (name, value) in params.env {
+                if let Err(e) = shell_sessions::check_settable(&name) {
+                    return Err(McpError::invalid_params(e.to_string(), None));
+                }
+                env.insert(name, Some(value));
+            }
+            let info = SessionInfo {
+                session: context.shells.open(cwd.clone(), env),
+                cwd: cwd.display().to_string(),
+            };
+            let text = format!("Opened shell session {} in {}.", info.session, info.cwd);
+            Ok(tool::structured_result(text, &info))
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SessionExecParams {
+    /// The session from `session_open`.
+    pub session: String,
+    /// Commands joined by `&&`, `||` or `;`. Besides the allowed programs,
+    /// `cd`, `export`, `unset` and `pwd` work as in a shell.
+    pub cmd: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SessionOutput {
+    #[serde(flatten)]
+    pub output: ShellOutput,
+    /// The session's working directory after the commands.
+    pub cwd: String,
+}
+
+pub struct SessionExec;
+
+impl Tool for SessionExec {
+    fn name(&self) -> &str {
+        "session_exec"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Run in Shell Session")
+    }
+
+    fn description(&self) -> &str {
+        "Executes safe terminal commands in a shell session from `session_open`. `cd` and `export` \
+         carry over to later calls, and `$VARS` set in the session are expanded."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<SessionExecParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<SessionOutput>())
+    }
+
+    // Flagged destructive like `shell_command`.
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Run in Shell Session")
+            .read_only(false)
+            .destructive(true)
+            .idempotent(false)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: SessionExecParams = tool::parse_arguments(arguments)?;
+            let mut session = context.shells.get(&params.session).map_err(tool::internal_error)?;
+            let mut output = session_exec(&params.cmd, &mut session, &context.workspace, &context.shell)
+                .await
+                .map_err(tool::internal_error)?;
+            let cwd = session.cwd.display().to_string();
+            context.shells.update(&params.session, session);
+
+            let page = context.first_page(std::mem::take(&mut output.stdout));
+            output.stdout = page.text;
+            output.next_cursor = page.next_cursor;
+            let text = output.text() + &pages::continuation(&output.next_cursor);
+            Ok(tool::structured_result(text, &SessionOutput { output, cwd }))
+        })
+    }
+}
+
+/// Runs a command list in the session, applying builtins to it. Every
+/// program is checked against the allowlist before anything runs.
+async fn session_exec(cmd: &str, session: &mut ShellSession, workspace: &Workspace, shell: &ShellConfig) -> Result<ShellOutput> {
+    let commands = shell_sessions::split_commands(cmd)?;
+    for (_, command) in &commands {
+        let program = parse(command)?.swap_remove(0);
+        if !BUILTINS.contains(&program.as_str()) && !shell.allowed_commands.contains(&program) {
+            metrics().rejection("command_not_allowed");
+            return Err(anyhow!("Command '{}' is not allowed.", program));
+        }
+    }
+
+    let mut output = ShellOutput {
+        stdout: String::new(),
+        stderr: String::new(),
+        exit_code: Some(0),
+        next_cursor: None,
+    };
+    for (operator, command) in commands {
+        let succeeded = output.exit_code == Some(0);
+        if operator == "&&" && !succeeded || operator == "||" && succeeded {
+            continue;
+        }
+        // Expanded only now, so variables exported earlier in the list apply.
+        let command = parse(&session.expand(&command))?;
+        let result = match builtin(&command, session, workspace) {
+            Some(result) => result,
+            None => {
+                check(&command, &session.cwd, workspace, &shell.allowed_commands)?;
+                run(&command, &session.cwd, &session.env, Duration::from_secs(shell.timeout_secs)).await?
+            }
+        };
+        output.stdout.push_str(&result.stdout);
+        output.stderr.push_str(&result.stderr);
+        output.exit_code = result.exit_code;
+    }
+    Ok(output)
+}
+
+const BUILTINS: &[&str] = &["cd", "export", "unset", "pwd"];
+
+/// Runs `command` if it is a builtin; failures go to stderr with exit code 1, as in a shell.
+fn builtin(command: &[String], session: &mut ShellSession, workspace: &Workspace) -> Option<ShellOutput> {
+    let args = &command[1..];
+    let result = match command[0].as_str() {
+        "cd" if args.len() > 1 => Err(anyhow!("cd: too many arguments")),
+        "cd" => session.cd(args.first().map(String::as_str), workspace).map(|()| String::new()),
+        "export" if args.is_empty() => Ok(session.exported()),
+        "export" => args.iter().try_for_each(|arg| session.export(arg)).map(|()| String::new()),
+        "unset" => args.iter().try_for_each(|arg| session.unset(arg)).map(|()| String::new()),
+        "pwd" => Ok(format!("{}\n", session.cwd.display())),
+        _ => return None,
+    };
+    Some(match result {
+        Ok(stdout) => ShellOutput {
+            stdout,
+            stderr: String::new(),
+            exit_code: Some(0),
+            next_cursor: None,
+        },
+        Err(e) => ShellOutput {
+            stdout: String::new(),
+            stderr: format!("{}\n", e),
+            exit_code: Some(1),
+            next_cursor: None,
+        },
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SessionCloseParams {
+    pub session: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SessionClosed {
+    /// Whether the session was still open.
+    pub closed: bool,
+}
+
+pub struct SessionClose;
+
+impl Tool for SessionClose {
+    fn name(&self) -> &str {
+        "session_close"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Close Shell Session")
+    }
+
+    fn description(&self) -> &str {
+        "Closes a shell session from `session_open`."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<SessionCloseParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<SessionClosed>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Close Shell Session")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: SessionCloseParams = tool::parse_arguments(arguments)?;
+            let closed = SessionClosed {
+                closed: context.shells.close(&params.session),
+            };
+            let text = if closed.closed {
+                format!("Closed shell session {}.", params.session)
+            } else {
+                format!("Shell session {} was not open.", params.session)
+            };
+            Ok(tool::structured_result(text, &closed))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ShellConfig;
+
+    fn check_command(cmd: &str) -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().to_path_buf());
+        check(&parse(cmd)?, &workspace.primary(), &workspace, &ShellConfig::default().allowed_commands)
+    }
+
+    #[test]
+    fn check_allows_listed_programs_inside_the_workspace() {
+        for cmd in ["ls -la", "grep -rn foo src", "cat ./a/../b.txt", "find . -name '*.rs'", "echo a=b"] {
+            assert!(check_command(cmd).is_ok(), "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn check_rejects_other_programs() {
+        for cmd in ["rm -rf x", "sh -c ls", "/bin/ls", "bash"] {
+            assert!(check_command(cmd).is_err(), "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn check_rejects_find_actions() {
+        for cmd in ["find . -exec rm {} ;", "find . -execdir sh -c x ;", "find . -name x -delete", "find . -fprint out"] {
+            assert!(check_command(cmd).is_err(), "{}", cmd);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn commands_get_a_cleared_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let env = BTreeMap::from([("SESSION_VAR".to_string(), Some("1".to_string()))]);
+        let output = run(&["env".to_string()], dir.path(), &env, Duration::from_secs(10)).await.unwrap();
+        assert!(output.stdout.contains("SESSION_VAR=1\n"), "{}", output.stdout);
+        assert!(!output.stdout.contains("HOME="), "{}", output.stdout);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn commands_are_killed_after_the_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let started = std::time::Instant::now();
+        let parts = ["sleep".to_string(), "10".to_string()];
+        let error = match run(&parts, dir.path(), &BTreeMap::new(), Duration::from_millis(200)).await {
+            Ok(_) => panic!("sleep was not killed"),
+            Err(e) => e,
+        };
+        assert!(error.to_string().contains("timed out"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn check_follows_the_configured_allowlist() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().to_path_buf());
+        let allowed = ["wc".to_string()];
+        assert!(check(&parse("wc -l x").unwrap(), &workspace.primary(), &workspace, &allowed).is_ok());
+        assert!(check(&parse("ls").unwrap(), &workspace.primary(), &workspace, &allowed).is_err());
+    }
+
+    #[test]
+    fn check_rejects_paths_outside_the_workspace() {
+        for cmd in [
+            "cat /etc/passwd",
+            "ls ../..",
+            "grep -f/etc/shadow x",
+            "grep -nf/etc/shadow x",
+            "grep --file=/etc/shadow x",
+            "ls --color=../../x",
+            "find . -newer ../x",
+        ] {
+            assert!(check_command(cmd).is_err(), "{}", cmd);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_rejects_symlinks_out_of_the_workspace() {
+        let (dir, outside) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let workspace = Workspace::new(dir.path().to_path_buf());
+        std::os::unix::fs::symlink(outside.path(), workspace.primary().join("link")).unwrap();
+        let allowed = ShellConfig::default().allowed_commands;
+        for cmd in ["cat link/secret", "ls link", "grep -r key link", "grep --file=link/patterns x"] {
+            assert!(check(&parse(cmd).unwrap(), &workspace.primary(), &workspace, &allowed).is_err(), "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn check_rejects_flags_that_follow_symlinks() {
+        for cmd in ["ls -L", "ls -laH .", "ls --dereference", "ls --deref", "grep -R key .", "grep -rnR key .", "grep --dereference-recursive key", "find -L .", "find . -follow"] {
+            assert!(check_command(cmd).is_err(), "{}", cmd);
+        }
+        for cmd in ["ls -la", "grep -rn key .", "grep -H key x", "find . -name L"] {
+            assert!(check_command(cmd).is_ok(), "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn check_allows_option_paths_inside_the_workspace() {
+        assert!(check_command("grep -fpatterns.txt x").is_ok());
+        assert!(check_command("grep --file=sub/../patterns.txt x").is_ok());
+    }
+}
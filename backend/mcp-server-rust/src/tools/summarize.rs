@@ -0,0 +1,381 @@
+//! `summarize_file`, `summarize_directory` and `explain_diff`: large inputs
+//! condensed by the client's own model through sampling, so only the
+//! summary enters the conversation.
+
+use anyhow::{anyhow, bail, Result};
+use ignore::WalkBuilder;
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+    ErrorData as McpError,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::sampling::{Sampler, Summary, MAX_INPUT_BYTES};
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+use crate::tools::checkpoints::store_for;
+
+const DEFAULT_WORDS: u32 = 250;
+const MAX_WORDS: u32 = 2000;
+/// Only the start of larger files goes into a directory summary.
+const DIRECTORY_FILE_BYTES: usize = 32 * 1024;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SummarizeParams {
+    pub path: String,
+    /// What the summary should concentrate on, e.g. "error handling" or "the public API".
+    pub focus: Option<String>,
+    /// About how long the summary may be, in words; 250 when omitted, at most 2000.
+    pub max_words: Option<u32>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ExplainDiffParams {
+    /// A unified diff to explain.
+    pub diff: Option<String>,
+    /// Explain the changes since this checkpoint instead, from `list_checkpoints`.
+    pub checkpoint: Option<String>,
+    /// With `checkpoint`, limits the diff to this file or directory.
+    pub path: Option<String>,
+    /// What the explanation should concentrate on, e.g. "behavior changes" or "risks".
+    pub focus: Option<String>,
+    /// About how long the explanation may be, in words; 250 when omitted, at most 2000.
+    pub max_words: Option<u32>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SummaryOutput {
+    pub summary: String,
+    /// The model the client used.
+    pub model: String,
+    /// Pieces the input was split into.
+    pub chunks: usize,
+    /// Sampling requests sent to the client.
+    pub requests: usize,
+    /// Files left out of a directory summary because they are binary or over the size limit.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<String>,
+}
+
+fn annotations(title: &str) -> ToolAnnotations {
+    ToolAnnotations::with_title(title)
+        .read_only(true)
+        .destructive(false)
+        .idempotent(false)
+        .open_world(true)
+}
+
+/// Instructions for the model, and the token budget for them.
+fn instructions(task: &str, focus: Option<&str>, max_words: Option<u32>) -> (String, u32) {
+    let words = max_words.unwrap_or(DEFAULT_WORDS).clamp(20, MAX_WORDS);
+    let mut instructions = format!("{} Use at most {} words.", task, words);
+    if let Some(focus) = focus.filter(|focus| !focus.trim().is_empty()) {
+        instructions.push_str(&format!(" Concentrate on: {}.", focus.trim()));
+    }
+    (instructions, words * 2 + 100)
+}
+
+fn result(summary: Summary, skipped: Vec<String>) -> rmcp::model::CallToolResult {
+    let mut text = summary.text.clone();
+    if !skipped.is_empty() {
+        let shown = skipped.iter().take(20).map(String::as_str).collect::<Vec<_>>().join(", ");
+        let more = skipped.len().saturating_sub(20);
+        let more = if more > 0 { format!(" and {} more", more) } else { String::new() };
+        text.push_str(&format!("\n\n[{} files were not read: {}{}]", skipped.len(), shown, more));
+    }
+    let output = SummaryOutput {
+        summary: summary.text,
+        model: summary.model,
+        chunks: summary.chunks,
+        requests: summary.requests,
+        skipped,
+    };
+    tool::structured_result(text, &output)
+}
+
+pub struct SummarizeFile;
+
+impl Tool for SummarizeFile {
+    fn name(&self) -> &str {
+        "summarize_file"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Summarize File")
+    }
+
+    fn description(&self) -> &str {
+        "Summarizes a text file of up to 1 MiB with the client's model, reading large files in chunks, \
+         so the file itself does not take up context. Needs a client that supports sampling."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<SummarizeParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<SummaryOutput>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        annotations("Summarize File")
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: SummarizeParams = tool::parse_arguments(arguments)?;
+            let sampler = Sampler::new(context.peer.as_ref(), context.redactor.clone(), context.redacted.clone())
+                .map_err(tool::internal_error)?;
+            let path = context.workspace.resolve(&params.path).map_err(tool::internal_error)?;
+            let content = tokio::task::spawn_blocking(move || read_text(&path, MAX_INPUT_BYTES))
+                .await
+                .map_err(|e| tool::internal_error(e.into()))?
+                .map_err(tool::internal_error)?;
+            let (instructions, max_tokens) = instructions(
+                "Summarize this file: what it is for, how it is organized and anything notable.",
+                params.focus.as_deref(),
+                params.max_words,
+            );
+            let subject = format!("the file {}", params.path);
+            let summary = sampler
+                .summarize(&subject, &content, &instructions, max_tokens)
+                .await
+                .map_err(tool::internal_error)?;
+            Ok(result(summary, Vec::new()))
+        })
+    }
+}
+
+pub struct SummarizeDirectory;
+
+impl Tool for SummarizeDirectory {
+    fn name(&self) -> &str {
+        "summarize_directory"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Summarize Directory")
+    }
+
+    fn description(&self) -> &str {
+        "Summarizes the text files under a directory with the client's model, honoring ignore files. \
+         Only the first 32 KiB of each file and 1 MiB in total are read. Needs a client that supports sampling."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<SummarizeParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<SummaryOutput>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        annotations("Summarize Directory")
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: SummarizeParams = tool::parse_arguments(arguments)?;
+            let sampler = Sampler::new(context.peer.as_ref(), context.redactor.clone(), context.redacted.clone())
+                .map_err(tool::internal_error)?;
+            let path = context.workspace.resolve(&params.path).map_err(tool::internal_error)?;
+            let (content, skipped) = tokio::task::spawn_blocking(move || read_directory(&path))
+                .await
+                .map_err(|e| tool::internal_error(e.into()))?
+                .map_err(tool::internal_error)?;
+            let (instructions, max_tokens) = instructions(
+                "Summarize this directory: what it contains, what the main parts do and how they fit together.",
+                params.focus.as_deref(),
+                params.max_words,
+            );
+            let subject = format!("the files under {}, each after a `==> path <==` header", params.path);
+            let summary = sampler
+                .summarize(&subject, &content, &instructions, max_tokens)
+                .await
+                .map_err(tool::internal_error)?;
+            Ok(result(summary, skipped))
+        })
+    }
+}
+
+pub struct ExplainDiff;
+
+impl Tool for ExplainDiff {
+    fn name(&self) -> &str {
+        "explain_diff"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Explain Diff")
+    }
+
+    fn description(&self) -> &str {
+        "Explains what a unified diff changes and why it matters, using the client's model. Pass the diff, \
+         or a checkpoint id to explain the changes made since it. Needs a client that supports sampling."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<ExplainDiffParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<SummaryOutput>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        annotations("Explain Diff")
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: ExplainDiffParams = tool::parse_arguments(arguments)?;
+            let sampler = Sampler::new(context.peer.as_ref(), context.redactor.clone(), context.redacted.clone())
+                .map_err(tool::internal_error)?;
+            let (subject, diff) = match (params.diff, params.checkpoint) {
+                (Some(diff), None) => ("a unified diff".to_string(), diff),
+                (None, Some(id)) => {
+                    let (_, diff) = store_for(&context, &id)
+                        .and_then(|store| {
+                            let scope = params.path.as_deref().map(|path| context.workspace.resolve(path)).transpose()?;
+                            store.diff(&id, scope.as_deref(), &context.workspace)
+                        })
+                        .map_err(tool::internal_error)?;
+                    if diff.is_empty() {
+                        return Ok(tool::structured_result(
+                            "No changes since the checkpoint.".to_string(),
+                            &SummaryOutput {
+                                summary: String::new(),
+                                model: String::new(),
+                                chunks: 0,
+                                requests: 0,
+                                skipped: Vec::new(),
+                            },
+                        ));
+                    }
+                    (format!("the changes to the workspace since checkpoint {}", id), diff)
+                }
+                _ => {
+                    return Err(McpError::invalid_params(
+                        "Pass exactly one of `diff` and `checkpoint`",
+                        None,
+                    ))
+                }
+            };
+            let (instructions, max_tokens) = instructions(
+                "Explain this diff: what changed, file by file where useful, the intent behind it and any \
+                 behavior changes or risks a reviewer should know about.",
+                params.focus.as_deref(),
+                params.max_words,
+            );
+            let summary = sampler
+                .summarize(&subject, &diff, &instructions, max_tokens)
+                .await
+                .map_err(tool::internal_error)?;
+            Ok(result(summary, Vec::new()))
+        })
+    }
+}
+
+/// The file as text, refusing binary files and files over `limit` bytes.
+fn read_text(path: &Path, limit: usize) -> Result<String> {
+    let read_error = |e: std::io::Error| anyhow!("Failed to read file '{}': {}", path.display(), e);
+    let file = File::open(path).map_err(read_error)?;
+    let len = file.metadata().map_err(read_error)?.len();
+    if len > limit as u64 {
+        bail!("'{}' is {} bytes; at most {} can be summarized", path.display(), len, limit);
+    }
+    // The file may have grown since; never read past the limit.
+    let mut bytes = Vec::with_capacity(len as usize);
+    file.take(limit as u64).read_to_end(&mut bytes).map_err(read_error)?;
+    if is_binary(&bytes) {
+        bail!("'{}' is a binary file", path.display());
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The text files under `dir`, each after a header with its relative path,
+/// and the files left out.
+fn read_directory(dir: &Path) -> Result<(String, Vec<String>)> {
+    if !dir.is_dir() {
+        bail!("'{}' is not a directory", dir.display());
+    }
+    let mut files: Vec<_> = WalkBuilder::new(dir)
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| entry.into_path())
+        .collect();
+    files.sort();
+
+    let mut content = String::new();
+    let mut skipped = Vec::new();
+    for path in files {
+        let relative = path.strip_prefix(dir).unwrap_or(&path).display().to_string();
+        let Ok((bytes, len)) = read_start(&path, DIRECTORY_FILE_BYTES) else {
+            skipped.push(relative);
+            continue;
+        };
+        if is_binary(&bytes) {
+            skipped.push(relative);
+            continue;
+        }
+        let mut text = String::from_utf8_lossy(&bytes).into_owned();
+        if len > bytes.len() as u64 {
+            text.push_str(&format!("\n[{} more bytes not shown]", len - bytes.len() as u64));
+        }
+        let section = format!("==> {} <==\n{}\n\n", relative, text.trim_end());
+        if content.len() + section.len() > MAX_INPUT_BYTES {
+            skipped.push(relative);
+            continue;
+        }
+        content.push_str(&section);
+    }
+    Ok((content, skipped))
+}
+
+/// The first `limit` bytes of the file at `path`, and its length.
+fn read_start(path: &Path, limit: usize) -> std::io::Result<(Vec<u8>, u64)> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut bytes = Vec::with_capacity(limit.min(len as usize));
+    file.take(limit as u64).read_to_end(&mut bytes)?;
+    Ok((bytes, len))
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(8192)].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_text_refuses_files_over_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.txt");
+        std::fs::write(&path, "x".repeat(100)).unwrap();
+        assert_eq!(read_text(&path, 100).unwrap().len(), 100);
+        let error = read_text(&path, 99).unwrap_err().to_string();
+        assert!(error.contains("is 100 bytes"), "{}", error);
+    }
+
+    #[test]
+    fn read_directory_reads_only_the_start_of_each_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("long.txt"), "a".repeat(DIRECTORY_FILE_BYTES + 10)).unwrap();
+        std::fs::write(dir.path().join("blob.bin"), b"\0\x01\x02").unwrap();
+        let (content, skipped) = read_directory(dir.path()).unwrap();
+        assert!(content.starts_with("==> long.txt <==\n"));
+        assert!(content.contains("\n[10 more bytes not shown]"));
+        assert_eq!(content.matches('a').count(), DIRECTORY_FILE_BYTES);
+        assert_eq!(skipped, ["blob.bin"]);
+    }
+}
@@ -0,0 +1,148 @@
+use rmcp::{
+    model::{JsonObject, ToolAnnotations},
+    schemars::JsonSchema,
+    ErrorData as McpError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::tables::{self, QueryResult, TableInfo};
+use crate::tool::{self, Tool, ToolContext, ToolFuture};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct QueryTableParams {
+    /// CSV, TSV or Parquet files to load. Each becomes a table named after its
+    /// file name: `data/sales-2024.csv` is `sales_2024`.
+    pub paths: Vec<String>,
+    /// A SQLite `SELECT` over the tables. When omitted, the result shows each
+    /// table's columns and first rows.
+    pub sql: Option<String>,
+    /// Most rows to return; 100 when omitted, at most 1000.
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct QueryTableOutput {
+    /// The loaded tables with their inferred columns.
+    pub tables: Vec<TableInfo>,
+    /// The query's result; missing when no query was given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<QueryResult>,
+    /// Pass to `read_more` for the rest of the output; missing when nothing was cut off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+pub struct QueryTable;
+
+impl Tool for QueryTable {
+    fn name(&self) -> &str {
+        "query_table"
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some("Query Table")
+    }
+
+    fn description(&self) -> &str {
+        "Runs a SQL query over CSV, TSV or Parquet files of up to 100 MiB each, e.g. \
+         `SELECT status, count(*) FROM orders GROUP BY status`. Uses SQLite syntax. Without `sql`, \
+         shows each file's inferred columns, row count and first rows."
+    }
+
+    fn input_schema(&self) -> Arc<JsonObject> {
+        tool::input_schema::<QueryTableParams>()
+    }
+
+    fn output_schema(&self) -> Option<Arc<JsonObject>> {
+        Some(tool::output_schema::<QueryTableOutput>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::with_title("Query Table")
+            .read_only(true)
+            .destructive(false)
+            .idempotent(true)
+            .open_world(false)
+    }
+
+    fn execute(&self, arguments: JsonObject, context: ToolContext) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let params: QueryTableParams = tool::parse_arguments(arguments)?;
+            if params.paths.is_empty() {
+                return Err(McpError::invalid_params("`paths` must name at least one file", None));
+            }
+            let mut names = HashSet::new();
+            let mut tables = Vec::new();
+            for path in &params.paths {
+                let path = context.workspace.resolve(path).map_err(tool::internal_error)?;
+                let name = tables::table_name(&path, &names);
+                names.insert(name.clone());
+                tables.push((name, path));
+            }
+            let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+            let (tables, result) =
+                tokio::task::spawn_blocking(move || tables::query(&tables, params.sql.as_deref(), limit))
+                    .await
+                    .map_err(|e| tool::internal_error(e.into()))?
+                    .map_err(tool::internal_error)?;
+
+            let mut text = tables.iter().map(render_table).collect::<Vec<_>>().join("\n\n");
+            if let Some(result) = &result {
+                text.push_str("\n\n");
+                text.push_str(&render_rows(&result.columns, &result.rows));
+                if result.truncated {
+                    text.push_str(&format!("\n[Only the first {} rows are shown.]", limit));
+                }
+            }
+            let page = context.first_page(text);
+            let output = QueryTableOutput {
+                tables,
+                result,
+                next_cursor: page.next_cursor.clone(),
+            };
+            Ok(tool::structured_result(page.render(), &output))
+        })
+    }
+}
+
+fn render_table(table: &TableInfo) -> String {
+    let columns = table
+        .columns
+        .iter()
+        .map(|column| format!("{} {}", column.name, column.kind))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut text = format!("Table {} ({}, {} rows): {}", table.name, table.path, table.rows, columns);
+    if !table.sample.is_empty() {
+        let names: Vec<String> = table.columns.iter().map(|column| column.name.clone()).collect();
+        text.push('\n');
+        text.push_str(&render_rows(&names, &table.sample));
+    }
+    text
+}
+
+/// Rows as tab-separated lines under a header line.
+fn render_rows(columns: &[String], rows: &[Vec<Value>]) -> String {
+    let mut lines = vec![columns.join("\t")];
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .map(|value| match value {
+                Value::Null => "NULL".to_string(),
+                Value::String(text) => text.replace(['\t', '\n'], " "),
+                other => other.to_string(),
+            })
+            .collect();
+        lines.push(cells.join("\t"));
+    }
+    if rows.is_empty() {
+        lines.push("(no rows)".to_string());
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,243 @@
+use anyhow::{anyhow, Result};
+use rmcp::{service::Peer, RoleServer};
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use std::sync::RwLock;
+
+/// The directories tools may touch, as announced by the client's MCP roots.
+///
+/// Until the client reports roots (or when it doesn't support them) the
+/// server's working directory is the only root.
+pub struct Workspace {
+    fallback: PathBuf,
+    roots: RwLock<Vec<PathBuf>>,
+    /// Directories announced roots must lie in; any root is accepted when unset.
+    allowed: Option<Vec<PathBuf>>,
+}
+
+impl Workspace {
+    pub fn new(fallback: PathBuf) -> Self {
+        let fallback = fallback.canonicalize().unwrap_or(fallback);
+        Self {
+            fallback,
+            roots: RwLock::new(Vec::new()),
+            allowed: None,
+        }
+    }
+
+    /// A workspace that ignores announced roots outside `allowed`, for
+    /// clients that aren't trusted with the whole machine. With nothing
+    /// in `allowed`, only `fallback` and the directories inside it are.
+    pub fn restricted(fallback: PathBuf, allowed: &[PathBuf]) -> Self {
+        let mut workspace = Self::new(fallback);
+        let allowed = if allowed.is_empty() {
+            vec![workspace.fallback.clone()]
+        } else {
+            allowed.iter().map(|dir| dir.canonicalize().unwrap_or_else(|_| dir.clone())).collect()
+        };
+        workspace.allowed = Some(allowed);
+        workspace
+    }
+
+    /// The only root until the client reports its own.
+    pub fn fallback(&self) -> &Path {
+        &self.fallback
+    }
+
+    pub fn roots(&self) -> Vec<PathBuf> {
+        let roots = self.roots.read().unwrap();
+        if roots.is_empty() {
+            vec![self.fallback.clone()]
+        } else {
+            roots.clone()
+        }
+    }
+
+    /// The first root; relative paths are resolved against it.
+    pub fn primary(&self) -> PathBuf {
+        self.roots.read().unwrap().first().cloned().unwrap_or_else(|| self.fallback.clone())
+    }
+
+    /// Replaces the roots. Roots outside the allowed directories are
+    /// dropped; the fallback is used when none are left.
+    pub fn set_roots(&self, roots: Vec<PathBuf>) {
+        let roots = roots
+            .into_iter()
+            .map(|root| canonicalize_lenient(&normalize(&root)))
+            .filter(|root| match &self.allowed {
+                Some(allowed) => {
+                    let inside = allowed.iter().any(|dir| root.starts_with(dir));
+                    if !inside {
+                        tracing::warn!(root = %root.display(), "Ignoring a root outside the allowed directories");
+                    }
+                    inside
+                }
+                None => true,
+            })
+            .collect();
+        *self.roots.write().unwrap() = roots;
+    }
+
+    /// Replaces the roots with the client's current `roots/list`.
+    pub async fn refresh(&self, peer: &Peer<RoleServer>) -> Result<()> {
+        let result = peer
+            .list_roots()
+            .await
+            .map_err(|e| anyhow!("Failed to list roots: {}", e))?;
+        let roots = result
+            .roots
+            .iter()
+            .filter_map(|root| file_uri_to_path(&root.uri))
+            .collect();
+        self.set_roots(roots);
+        Ok(())
+    }
+
+    /// Resolves `path` against the primary root and rejects anything outside every root.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf> {
+        self.resolve_in(&self.primary(), path)
+    }
+
+    /// Like [`resolve`](Self::resolve), with relative paths resolved against `dir`.
+    pub fn resolve_in(&self, dir: &Path, path: &str) -> Result<PathBuf> {
+        let path = Path::new(path);
+        let joined = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            dir.join(path)
+        };
+        let resolved = canonicalize_lenient(&normalize(&joined));
+        if self.roots().iter().any(|root| resolved.starts_with(root)) {
+            Ok(resolved)
+        } else {
+            Err(anyhow!("Path '{}' is outside the workspace", path.display()))
+        }
+    }
+}
+
+/// Removes `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Canonicalizes the longest existing prefix of `path`, so symlinks are
+/// resolved even for files that don't exist yet.
+fn canonicalize_lenient(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest: Vec<OsString> = Vec::new();
+    loop {
+        if let Ok(mut resolved) = existing.canonicalize() {
+            resolved.extend(rest.iter().rev());
+            return resolved;
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    // Drop an optional authority such as `localhost`.
+    let encoded = &encoded[encoded.find('/')?..];
+
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn resolve_keeps_paths_inside_the_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().to_path_buf());
+        let root = workspace.primary();
+        fs::create_dir(root.join("src")).unwrap();
+
+        assert_eq!(workspace.resolve("src/main.rs").unwrap(), root.join("src/main.rs"));
+        assert_eq!(workspace.resolve("src/../Cargo.toml").unwrap(), root.join("Cargo.toml"));
+        assert_eq!(workspace.resolve_in(&root.join("src"), "../README.md").unwrap(), root.join("README.md"));
+        assert!(workspace.resolve("../outside").is_err());
+        assert!(workspace.resolve("src/../../outside").is_err());
+        assert!(workspace.resolve("/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn resolve_accepts_any_root() {
+        let (first, second) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let workspace = Workspace::new(std::env::temp_dir());
+        workspace.set_roots(vec![first.path().to_path_buf(), second.path().to_path_buf()]);
+        let second = second.path().canonicalize().unwrap();
+
+        assert!(workspace.resolve(second.join("notes.txt").to_str().unwrap()).is_ok());
+        assert!(workspace.resolve(std::env::temp_dir().join("x").to_str().unwrap()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_follows_symlinks_out_of_the_workspace() {
+        let (dir, outside) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let workspace = Workspace::new(dir.path().to_path_buf());
+        std::os::unix::fs::symlink(outside.path(), workspace.primary().join("link")).unwrap();
+
+        assert!(workspace.resolve("link/secret").is_err());
+        assert!(workspace.resolve("link/new/file.txt").is_err());
+    }
+
+    #[test]
+    fn restricted_workspaces_drop_roots_outside_the_allowed_directories() {
+        let (allowed, outside) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        fs::create_dir(allowed.path().join("project")).unwrap();
+        let fallback = allowed.path().canonicalize().unwrap();
+        let workspace = Workspace::restricted(fallback.clone(), &[allowed.path().to_path_buf()]);
+
+        workspace.set_roots(vec![allowed.path().join("project"), outside.path().to_path_buf()]);
+        assert_eq!(workspace.roots(), vec![fallback.join("project")]);
+
+        workspace.set_roots(vec![outside.path().to_path_buf(), allowed.path().join("project/../..")]);
+        assert_eq!(workspace.roots(), vec![fallback]);
+        assert!(workspace.resolve(outside.path().join("secret").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn file_uris_are_decoded() {
+        assert_eq!(file_uri_to_path("file:///home/me/my%20project"), Some(PathBuf::from("/home/me/my project")));
+        assert_eq!(file_uri_to_path("file://localhost/srv"), Some(PathBuf::from("/srv")));
+        assert_eq!(file_uri_to_path("https://example.com/"), None);
+    }
+}